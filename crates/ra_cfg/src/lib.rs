@@ -41,6 +41,14 @@ impl CfgOptions {
         self.atoms.insert(key);
     }
 
+    pub fn atoms(&self) -> impl Iterator<Item = &SmolStr> + '_ {
+        self.atoms.iter()
+    }
+
+    pub fn key_values(&self) -> impl Iterator<Item = (&SmolStr, &SmolStr)> + '_ {
+        self.key_values.iter().map(|(key, value)| (key, value))
+    }
+
     pub fn remove_atom(&mut self, name: &str) {
         self.atoms.remove(name);
     }
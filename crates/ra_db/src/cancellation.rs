@@ -15,6 +15,14 @@
 //! any background processing (this bit is handled by salsa, see the
 //! `BaseDatabase::check_canceled` method).
 
+use std::{
+    cell::Cell,
+    panic,
+    sync::atomic::{AtomicBool, Ordering},
+};
+
+use crate::CheckCanceled;
+
 /// An "error" signifying that the operation was canceled.
 #[derive(Clone, PartialEq, Eq, Hash)]
 pub struct Canceled {
@@ -46,3 +54,98 @@ impl std::fmt::Debug for Canceled {
 }
 
 impl std::error::Error for Canceled {}
+
+/// Checks for cancellation when created and once again when dropped.
+///
+/// Salsa only checks for cancellation between queries, so a long-running
+/// custom query should hold a guard for each unit of work:
+///
+/// ```ignore
+/// fn my_query(db: &impl MyDatabase, file_id: FileId) -> Arc<Stuff> {
+///     for item in items(db, file_id) {
+///         let _guard = db.query_guard();
+///         process(item);
+///     }
+/// }
+/// ```
+pub struct QueryGuard<'a, DB: CheckCanceled + ?Sized> {
+    db: &'a DB,
+}
+
+impl<'a, DB: CheckCanceled + ?Sized> QueryGuard<'a, DB> {
+    pub fn new(db: &'a DB) -> QueryGuard<'a, DB> {
+        db.check_canceled();
+        QueryGuard { db }
+    }
+}
+
+impl<DB: CheckCanceled + ?Sized> Drop for QueryGuard<'_, DB> {
+    fn drop(&mut self) {
+        // Don't turn an unrelated panic into an abort.
+        if !std::thread::panicking() {
+            self.db.check_canceled();
+        }
+    }
+}
+
+thread_local! {
+    static UNCANCELLABLE_DEPTH: Cell<u32> = Cell::new(0);
+}
+
+/// Whether cancellation checks are disabled on the current thread.
+pub(crate) fn is_uncancellable() -> bool {
+    UNCANCELLABLE_DEPTH.with(|depth| depth.get() > 0)
+}
+
+/// Runs `f` with cancellation checks disabled on the current thread.
+pub(crate) fn uncancellable<T>(f: impl FnOnce() -> T) -> T {
+    struct Restore;
+
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            UNCANCELLABLE_DEPTH.with(|depth| depth.set(depth.get() - 1));
+        }
+    }
+
+    UNCANCELLABLE_DEPTH.with(|depth| depth.set(depth.get() + 1));
+    let _restore = Restore;
+    f()
+}
+
+/// Runs `f`, turning a panic with a `Canceled` payload into an `Err`. Other
+/// panics are propagated.
+pub(crate) fn catch_canceled_unwind<T>(
+    f: impl FnOnce() -> T + panic::UnwindSafe,
+) -> Result<T, Canceled> {
+    panic::catch_unwind(f).map_err(|err| match err.downcast::<Canceled>() {
+        Ok(canceled) => *canceled,
+        Err(payload) => panic::resume_unwind(payload),
+    })
+}
+
+static QUIET_HOOK_INSTALLED: AtomicBool = AtomicBool::new(false);
+static QUIET_HOOK_ENABLED: AtomicBool = AtomicBool::new(false);
+
+/// Installs a panic hook which prints nothing for panics with a `Canceled`
+/// payload, and passes all other panics on to the previously installed hook.
+///
+/// `Canceled::throw` doesn't run the hook in the first place, but cancellation
+/// which is raised with a plain `panic!` does. Calling this again is a no-op.
+pub fn set_quiet_panic_hook() {
+    QUIET_HOOK_ENABLED.store(true, Ordering::SeqCst);
+    if QUIET_HOOK_INSTALLED.swap(true, Ordering::SeqCst) {
+        return;
+    }
+    let prev = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if QUIET_HOOK_ENABLED.load(Ordering::SeqCst) && info.payload().is::<Canceled>() {
+            return;
+        }
+        prev(info)
+    }));
+}
+
+/// Undoes `set_quiet_panic_hook`: all panics go to the previous hook again.
+pub fn restore_panic_hook() {
+    QUIET_HOOK_ENABLED.store(false, Ordering::SeqCst);
+}
@@ -0,0 +1,59 @@
+//! A `FileTextLoader` which reads library files on demand.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use rustc_hash::FxHashMap;
+
+use crate::{FileId, FileTextLoader};
+
+/// Loads the text of the registered files from disk when it is asked for.
+///
+/// This is meant for large read-only library files: install it with
+/// `set_file_text_loader` for those files, and keep workspace files in
+/// `file_text`. Memory is given back by the LRU of `LazyFileTextQuery`, which
+/// reads the file again on the next access.
+///
+/// Files are read rather than mapped: `file_text` hands out an `Arc<String>`,
+/// so the text would be copied out of the map anyway, and a mapped file
+/// which is truncated behind our back kills the process with `SIGBUS`.
+#[derive(Debug, Default)]
+pub struct DiskFileTextLoader {
+    paths: FxHashMap<FileId, PathBuf>,
+}
+
+impl DiskFileTextLoader {
+    pub fn new() -> DiskFileTextLoader {
+        DiskFileTextLoader::default()
+    }
+
+    pub fn add_file(&mut self, file_id: FileId, path: PathBuf) {
+        self.paths.insert(file_id, path);
+    }
+}
+
+impl FileTextLoader for DiskFileTextLoader {
+    fn load_text(&self, file_id: FileId) -> String {
+        let path = match self.paths.get(&file_id) {
+            Some(it) => it,
+            None => return String::new(),
+        };
+        // The file might have been removed since it was added, the VFS will
+        // tell us about that soon.
+        read_text(path).unwrap_or_default()
+    }
+
+    fn is_missing(&self, file_id: FileId) -> bool {
+        self.paths.get(&file_id).map_or(true, |path| !path.is_file())
+    }
+}
+
+fn read_text(path: &Path) -> io::Result<String> {
+    let bytes = fs::read(path)?;
+    // Only copy the text if it has to be fixed up.
+    let text = String::from_utf8(bytes)
+        .unwrap_or_else(|err| String::from_utf8_lossy(err.as_bytes()).into_owned());
+    Ok(text)
+}
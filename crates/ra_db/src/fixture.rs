@@ -4,6 +4,7 @@ use std::str::FromStr;
 use std::sync::Arc;
 
 use ra_cfg::CfgOptions;
+use ra_syntax::ParserFeatures;
 use rustc_hash::FxHashMap;
 use test_utils::{extract_offset, parse_fixture, CURSOR_MARKER};
 
@@ -53,18 +54,28 @@ fn with_single_file(db: &mut dyn SourceDatabaseExt, text: &str) -> FileId {
     source_root.insert_file(rel_path.clone(), file_id);
 
     let mut crate_graph = CrateGraph::default();
-    crate_graph.add_crate_root(
+    let crate_id = crate_graph.add_crate_root(
         file_id,
         Edition::Edition2018,
         CfgOptions::default(),
         Env::default(),
     );
+    crate_graph.set_is_member(crate_id, !source_root.is_library);
 
     db.set_file_text(file_id, Arc::new(text.to_string()));
     db.set_file_relative_path(file_id, rel_path);
     db.set_file_source_root(file_id, WORKSPACE);
     db.set_source_root(WORKSPACE, Arc::new(source_root));
+    db.set_source_root_paths(Arc::new(vec![(WORKSPACE, "/".into())].into_iter().collect()));
+    db.set_crate_display_data(Arc::new(crate_graph.display_data()));
     db.set_crate_graph(Arc::new(crate_graph));
+    db.set_parser_features(ParserFeatures::default());
+    db.set_parse_timings(Default::default());
+    db.set_synthetic_files(Default::default());
+    db.set_pinned_files(Default::default());
+    db.set_lazy_files(Default::default());
+    db.set_file_text_revisions(Default::default());
+    db.set_file_metadata(Default::default());
 
     file_id
 }
@@ -75,11 +86,12 @@ fn with_files(db: &mut dyn SourceDatabaseExt, fixture: &str) -> Option<FilePosit
     let mut crate_graph = CrateGraph::default();
     let mut crates = FxHashMap::default();
     let mut crate_deps = Vec::new();
-    let mut default_crate_root: Option<FileId> = None;
+    let mut default_crate_root: Option<(FileId, bool)> = None;
 
     let mut source_root = SourceRoot::default();
     let mut source_root_id = WORKSPACE;
     let mut source_root_prefix: RelativePathBuf = "/".into();
+    let mut source_root_paths = FxHashMap::default();
     let mut file_id = FileId(0);
 
     let mut file_position = None;
@@ -89,6 +101,7 @@ fn with_files(db: &mut dyn SourceDatabaseExt, fixture: &str) -> Option<FilePosit
             ParsedMeta::Root { path } => {
                 let source_root = std::mem::replace(&mut source_root, SourceRoot::default());
                 db.set_source_root(source_root_id, Arc::new(source_root));
+                source_root_paths.insert(source_root_id, source_root_prefix);
                 source_root_id.0 += 1;
                 source_root_prefix = path;
                 continue;
@@ -100,6 +113,7 @@ fn with_files(db: &mut dyn SourceDatabaseExt, fixture: &str) -> Option<FilePosit
         if let Some(krate) = meta.krate {
             let crate_id =
                 crate_graph.add_crate_root(file_id, meta.edition, meta.cfg, Env::default());
+            crate_graph.set_is_member(crate_id, !source_root.is_library);
             let prev = crates.insert(krate.clone(), crate_id);
             assert!(prev.is_none());
             for dep in meta.deps {
@@ -107,7 +121,7 @@ fn with_files(db: &mut dyn SourceDatabaseExt, fixture: &str) -> Option<FilePosit
             }
         } else if meta.path == "/main.rs" || meta.path == "/lib.rs" {
             assert!(default_crate_root.is_none());
-            default_crate_root = Some(file_id);
+            default_crate_root = Some((file_id, source_root.is_library));
         }
 
         let text = if entry.text.contains(CURSOR_MARKER) {
@@ -128,13 +142,14 @@ fn with_files(db: &mut dyn SourceDatabaseExt, fixture: &str) -> Option<FilePosit
     }
 
     if crates.is_empty() {
-        let crate_root = default_crate_root.unwrap();
-        crate_graph.add_crate_root(
+        let (crate_root, is_library) = default_crate_root.unwrap();
+        let crate_id = crate_graph.add_crate_root(
             crate_root,
             Edition::Edition2018,
             CfgOptions::default(),
             Env::default(),
         );
+        crate_graph.set_is_member(crate_id, !is_library);
     } else {
         for (from, to) in crate_deps {
             let from_id = crates[&from];
@@ -144,7 +159,17 @@ fn with_files(db: &mut dyn SourceDatabaseExt, fixture: &str) -> Option<FilePosit
     }
 
     db.set_source_root(source_root_id, Arc::new(source_root));
+    source_root_paths.insert(source_root_id, source_root_prefix);
+    db.set_source_root_paths(Arc::new(source_root_paths));
+    db.set_crate_display_data(Arc::new(crate_graph.display_data()));
     db.set_crate_graph(Arc::new(crate_graph));
+    db.set_parser_features(ParserFeatures::default());
+    db.set_parse_timings(Default::default());
+    db.set_synthetic_files(Default::default());
+    db.set_pinned_files(Default::default());
+    db.set_lazy_files(Default::default());
+    db.set_file_text_revisions(Default::default());
+    db.set_file_metadata(Default::default());
 
     file_position
 }
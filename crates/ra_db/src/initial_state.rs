@@ -0,0 +1,75 @@
+//! Collects the initial project model, to set it in one go.
+
+use std::sync::Arc;
+
+use rustc_hash::FxHashMap;
+use salsa::Durability;
+
+use crate::{CrateGraph, FileId, RelativePathBuf, SourceDatabaseExt, SourceRoot, SourceRootId};
+
+/// Source roots, files, and the crate graph of a freshly loaded project.
+///
+/// Setting those one by one has every write invalidate the previous ones.
+/// `apply` sets each input exactly once instead, with `Durability::HIGH` for
+/// the files of libraries. Global inputs other than the crate graph, like
+/// `parser_features`, are left to the embedder.
+#[derive(Debug, Default)]
+pub struct InitialState {
+    roots: FxHashMap<SourceRootId, SourceRoot>,
+    files: Vec<InitialFile>,
+    crate_graph: CrateGraph,
+}
+
+#[derive(Debug)]
+struct InitialFile {
+    file_id: FileId,
+    root_id: SourceRootId,
+    path: RelativePathBuf,
+    text: Arc<String>,
+}
+
+impl InitialState {
+    pub fn new() -> InitialState {
+        InitialState::default()
+    }
+
+    pub fn add_root(&mut self, root_id: SourceRootId, is_library: bool) {
+        let root = if is_library { SourceRoot::new_library() } else { SourceRoot::new() };
+        self.roots.insert(root_id, root);
+    }
+
+    /// Adds a file to a source root, which must have been added before.
+    pub fn add_file(
+        &mut self,
+        root_id: SourceRootId,
+        file_id: FileId,
+        path: RelativePathBuf,
+        text: Arc<String>,
+    ) {
+        let root = self.roots.get_mut(&root_id).expect("unknown source root");
+        root.insert_file(path.clone(), file_id);
+        self.files.push(InitialFile { file_id, root_id, path, text });
+    }
+
+    pub fn set_crate_graph(&mut self, crate_graph: CrateGraph) {
+        self.crate_graph = crate_graph;
+    }
+
+    pub fn apply(self, db: &mut impl SourceDatabaseExt) {
+        let durability =
+            |root: &SourceRoot| if root.is_library { Durability::HIGH } else { Durability::LOW };
+        for file in self.files {
+            let durability = durability(&self.roots[&file.root_id]);
+            db.set_file_text_with_durability(file.file_id, file.text, durability);
+            db.set_file_relative_path_with_durability(file.file_id, file.path, durability);
+            db.set_file_source_root_with_durability(file.file_id, file.root_id, durability);
+        }
+        for (root_id, root) in self.roots {
+            let durability = durability(&root);
+            db.set_source_root_with_durability(root_id, Arc::new(root), durability);
+        }
+        let display_data = self.crate_graph.display_data();
+        db.set_crate_display_data_with_durability(Arc::new(display_data), Durability::HIGH);
+        db.set_crate_graph_with_durability(Arc::new(self.crate_graph), Durability::HIGH);
+    }
+}
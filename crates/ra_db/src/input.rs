@@ -9,11 +9,12 @@
 use std::{fmt, str::FromStr};
 
 use ra_cfg::CfgOptions;
+use ra_prof::profile;
 use ra_syntax::SmolStr;
 use rustc_hash::FxHashMap;
 use rustc_hash::FxHashSet;
 
-use crate::{RelativePath, RelativePathBuf};
+use crate::{RelativePath, RelativePathBuf, SourceDatabase, SourceDatabaseExt};
 
 /// `FileId` is an integer which uniquely identifies a file. File paths are
 /// messy and system-dependent, so most of the code should work directly with
@@ -23,6 +24,26 @@ use crate::{RelativePath, RelativePathBuf};
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct FileId(pub u32);
 
+/// A `FileId` together with the edition the file is written in, as needed
+/// for parsing and lexing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct EditionedFileId {
+    file_id: FileId,
+    edition: Edition,
+}
+
+impl EditionedFileId {
+    pub fn new(file_id: FileId, edition: Edition) -> EditionedFileId {
+        EditionedFileId { file_id, edition }
+    }
+    pub fn file_id(self) -> FileId {
+        self.file_id
+    }
+    pub fn edition(self) -> Edition {
+        self.edition
+    }
+}
+
 /// Files are grouped into source roots. A source root is a directory on the
 /// file systems which is watched for changes. Typically it corresponds to a
 /// Rust crate. Source roots *might* be nested: in this case, a file belongs to
@@ -33,6 +54,14 @@ pub struct FileId(pub u32);
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
 pub struct SourceRootId(pub u32);
 
+/// Source root of the files registered with `add_synthetic_file`. It doesn't
+/// list any files, so nothing can be resolved by path from a synthetic file.
+pub const SYNTHETIC_SOURCE_ROOT: SourceRootId = SourceRootId(u32::MAX);
+
+/// Source root of the files registered with `intern_anonymous_file`. Like
+/// `SYNTHETIC_SOURCE_ROOT`, it doesn't list any files.
+pub const ANONYMOUS_SOURCE_ROOT: SourceRootId = SourceRootId(u32::MAX - 1);
+
 #[derive(Default, Clone, Debug, PartialEq, Eq)]
 pub struct SourceRoot {
     /// Sysroot or crates.io library.
@@ -41,6 +70,31 @@ pub struct SourceRoot {
     /// optimize salsa's query structure
     pub is_library: bool,
     files: FxHashMap<RelativePathBuf, FileId>,
+    /// Paths which are symlinks on disk. Files under them are listed as if
+    /// they were regular files.
+    symlinks: FxHashSet<RelativePathBuf>,
+    /// Directories, like `target/`, whose files are kept out of the analysis
+    /// even though they are in the source root.
+    exclude: Vec<RelativePathBuf>,
+}
+
+/// Whether a source root is edited by the user, see `SourceRoot::kind`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum SourceRootKind {
+    /// Part of the workspace.
+    Local,
+    /// The sysroot or an external dependency, like a crates.io package.
+    Library,
+}
+
+/// Changes between two versions of a source root, as computed by
+/// `SourceRoot::diff`. All lists are sorted by `FileId`.
+#[derive(Default, Clone, Debug, PartialEq, Eq)]
+pub struct SourceRootDiff {
+    pub added: Vec<FileId>,
+    pub removed: Vec<FileId>,
+    /// Files present in both versions, under the old and the new path.
+    pub moved: Vec<(FileId, RelativePathBuf, RelativePathBuf)>,
 }
 
 impl SourceRoot {
@@ -50,18 +104,160 @@ impl SourceRoot {
     pub fn new_library() -> SourceRoot {
         SourceRoot { is_library: true, ..SourceRoot::new() }
     }
+    pub fn kind(&self) -> SourceRootKind {
+        if self.is_library {
+            SourceRootKind::Library
+        } else {
+            SourceRootKind::Local
+        }
+    }
     pub fn insert_file(&mut self, path: RelativePathBuf, file_id: FileId) {
         self.files.insert(path, file_id);
     }
     pub fn remove_file(&mut self, path: &RelativePath) {
         self.files.remove(path);
     }
+    pub fn insert_symlink(&mut self, path: RelativePathBuf) {
+        self.symlinks.insert(path);
+    }
+    /// Whether the path is a symlink or lies under a symlinked directory.
+    pub fn is_behind_symlink(&self, path: &RelativePath) -> bool {
+        self.symlinks.iter().any(|link| path.starts_with(link))
+    }
+    pub fn insert_exclude(&mut self, path: RelativePathBuf) {
+        self.exclude.push(path);
+    }
+    /// Whether the path lies under one of the excluded directories. Excluded
+    /// files are skipped by `walk` and can't be looked up by path.
+    pub fn is_excluded(&self, path: &RelativePath) -> bool {
+        self.exclude.iter().any(|dir| path.starts_with(dir))
+    }
     pub fn walk(&self) -> impl Iterator<Item = FileId> + '_ {
-        self.files.values().copied()
+        self.files
+            .iter()
+            .filter(move |(path, _)| !self.is_excluded(path))
+            .map(|(_, &file_id)| file_id)
     }
     pub fn file_by_relative_path(&self, path: &RelativePath) -> Option<FileId> {
+        if self.is_excluded(path) {
+            return None;
+        }
         self.files.get(path).copied()
     }
+    pub fn contains_path(&self, path: &RelativePath) -> bool {
+        self.file_by_relative_path(path).is_some()
+    }
+    /// The deepest directory which contains all the files of the root, not
+    /// counting excluded ones. Empty if the files don't share a directory, or
+    /// if there are none.
+    pub fn common_prefix(&self) -> RelativePathBuf {
+        let mut prefix: Option<Vec<&str>> = None;
+        for path in self.files.keys().filter(|path| !self.is_excluded(path)) {
+            let dir = path.parent().map_or("", |it| it.as_str());
+            let dir = dir.split('/').filter(|it| !it.is_empty());
+            prefix = Some(match prefix {
+                None => dir.collect(),
+                Some(mut prefix) => {
+                    let common = prefix.iter().zip(dir).take_while(|(a, b)| *a == b).count();
+                    prefix.truncate(common);
+                    prefix
+                }
+            });
+        }
+        RelativePathBuf::from(prefix.unwrap_or_default().join("/"))
+    }
+    /// Like `file_by_relative_path`, but `\` is accepted as a separator as
+    /// well, for paths coming from Windows-authored project files.
+    pub fn file_by_path_normalized(&self, path: &str) -> Option<FileId> {
+        let path = RelativePathBuf::from(path.replace('\\', "/"));
+        self.file_by_relative_path(&path.normalize())
+    }
+
+    /// What happened to the files of `self` to get to `other`. Changes to the
+    /// text of the files are not tracked by source roots.
+    pub fn diff(&self, other: &SourceRoot) -> SourceRootDiff {
+        let old_paths: FxHashMap<FileId, &RelativePathBuf> =
+            self.files.iter().map(|(path, &file_id)| (file_id, path)).collect();
+        let new_paths: FxHashMap<FileId, &RelativePathBuf> =
+            other.files.iter().map(|(path, &file_id)| (file_id, path)).collect();
+
+        let mut res = SourceRootDiff::default();
+        for (&file_id, &new_path) in new_paths.iter() {
+            match old_paths.get(&file_id) {
+                None => res.added.push(file_id),
+                Some(&old_path) if old_path != new_path => {
+                    res.moved.push((file_id, old_path.clone(), new_path.clone()))
+                }
+                Some(_) => (),
+            }
+        }
+        res.removed =
+            old_paths.keys().copied().filter(|file_id| !new_paths.contains_key(file_id)).collect();
+        res.added.sort();
+        res.removed.sort();
+        res.moved.sort_by_key(|&(file_id, _, _)| file_id);
+        res
+    }
+
+    /// Up to `max` module files in `dir` whose name is close to `name`, best
+    /// matches first, for "did you mean" hints on unresolved modules. Both
+    /// `foo.rs` and `foo/mod.rs` count as a module called `foo`.
+    pub fn suggest_similar(
+        &self,
+        dir: &RelativePath,
+        name: &str,
+        max: usize,
+    ) -> Vec<RelativePathBuf> {
+        let max_distance = std::cmp::max(1, name.len() / 3);
+        let mut candidates = self
+            .files
+            .keys()
+            .filter_map(|path| {
+                let stem = module_stem(path, dir)?;
+                let distance = edit_distance(stem, name);
+                if distance > max_distance {
+                    return None;
+                }
+                Some((distance, path.clone()))
+            })
+            .collect::<Vec<_>>();
+        candidates.sort();
+        candidates.into_iter().take(max).map(|(_, path)| path).collect()
+    }
+}
+
+/// Name of the module defined by `path`, if it's a `name.rs` or `name/mod.rs`
+/// file inside of `dir`.
+fn module_stem<'a>(path: &'a RelativePath, dir: &RelativePath) -> Option<&'a str> {
+    let is_in = |parent: Option<&RelativePath>, dir: &RelativePath| {
+        parent.map_or(false, |it| it.components().eq(dir.components()))
+    };
+    if path.file_name() == Some("mod.rs") {
+        let module_dir = path.parent()?;
+        if is_in(module_dir.parent(), dir) {
+            return module_dir.file_name();
+        }
+        return None;
+    }
+    if path.extension() == Some("rs") && is_in(path.parent(), dir) {
+        return path.file_stem();
+    }
+    None
+}
+
+/// Levenshtein distance between the two strings, counted in chars.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b = b.chars().collect::<Vec<_>>();
+    let mut prev = (0..=b.len()).collect::<Vec<_>>();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut curr = vec![i + 1; b.len() + 1];
+        for (j, &b_char) in b.iter().enumerate() {
+            let substitution = prev[j] + if a_char == b_char { 0 } else { 1 };
+            curr[j + 1] = substitution.min(prev[j + 1] + 1).min(curr[j] + 1);
+        }
+        prev = curr;
+    }
+    prev[b.len()]
 }
 
 /// `CrateGraph` is a bit of information which turns a set of text files into a
@@ -75,21 +271,287 @@ impl SourceRoot {
 /// Note that `CrateGraph` is build-system agnostic: it's a concept of the Rust
 /// language proper, not a concept of the build system. In practice, we get
 /// `CrateGraph` by lowering `cargo metadata` output.
-#[derive(Debug, Clone, Default, PartialEq, Eq)]
+///
+/// `CrateGraph` is a plain value, so speculative analysis ("what if this
+/// dependency were removed?") is done by cloning the graph from the database,
+/// modifying the clone, installing it with `set_crate_graph`, and setting the
+/// original `Arc` back once the queries of interest have run.
+#[derive(Debug, Clone, Default)]
 pub struct CrateGraph {
     arena: FxHashMap<CrateId, CrateData>,
+    names: NameInterner,
+    /// Crates by root file, so that edition conflicts are found without
+    /// scanning the arena.
+    roots: FxHashMap<FileId, Vec<CrateId>>,
+    /// Id of the next crate to be added; ids of removed crates are not reused.
+    next_id: u32,
+}
+
+// Display names are compared by text: the interner depends on the order in
+// which names were set, and keeps names no crate uses anymore.
+impl PartialEq for CrateGraph {
+    fn eq(&self, other: &CrateGraph) -> bool {
+        self.arena.len() == other.arena.len()
+            && self.arena.iter().all(|(crate_id, data)| {
+                other
+                    .arena
+                    .get(crate_id)
+                    .map_or(false, |it| data.eq_with_names(&self.names, it, &other.names))
+            })
+    }
+}
+
+impl Eq for CrateGraph {}
+
+/// Staging copy of a graph, handed out by `CrateGraph::transaction`. Unlike
+/// with `CrateGraph::add_dep`, dependencies are not checked when they're
+/// added, only once the transaction is over.
+#[derive(Debug)]
+pub struct CrateGraphBuilder {
+    graph: CrateGraph,
+}
+
+impl CrateGraphBuilder {
+    pub fn add_crate_root(
+        &mut self,
+        file_id: FileId,
+        edition: Edition,
+        cfg_options: CfgOptions,
+        env: Env,
+    ) -> CrateId {
+        self.graph.add_crate_root(file_id, edition, cfg_options, env)
+    }
+
+    pub fn add_dep(&mut self, from: CrateId, name: SmolStr, to: CrateId) {
+        self.add_dep_with_kind(from, name, to, DependencyKind::Normal)
+    }
+
+    pub fn add_dep_with_kind(
+        &mut self,
+        from: CrateId,
+        name: SmolStr,
+        to: CrateId,
+        kind: DependencyKind,
+    ) {
+        self.graph.arena.get_mut(&from).expect("unknown crate").add_dep(name, to, kind);
+    }
+
+    /// The staged graph, for the other setters, like `set_is_member`.
+    pub fn graph_mut(&mut self) -> &mut CrateGraph {
+        &mut self.graph
+    }
+}
+
+/// An interned display name of a crate. Names are local to a `CrateGraph`, so
+/// the same name in two graphs might be a different `CrateName`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct CrateName(u32);
+
+/// Crates sharing a name, like the many `std`s of a workspace with several
+/// sysroots, share a single string.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct NameInterner {
+    ids: FxHashMap<SmolStr, CrateName>,
+    names: Vec<SmolStr>,
+}
+
+impl NameInterner {
+    fn intern(&mut self, name: &str) -> CrateName {
+        if let Some(&id) = self.ids.get(name) {
+            return id;
+        }
+        let id = CrateName(self.names.len() as u32);
+        let name = SmolStr::new(name);
+        self.names.push(name.clone());
+        self.ids.insert(name, id);
+        id
+    }
+
+    fn lookup(&self, id: CrateName) -> &str {
+        self.names[id.0 as usize].as_str()
+    }
+}
+
+/// The informational parts of a crate graph: display names, versions and
+/// priorities. They are stored in their own input,
+/// `SourceDatabase::crate_display_data`, so that a refreshed graph which only
+/// changes them leaves `SourceDatabase::crate_graph` alone.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CrateDisplayData {
+    crates: FxHashMap<CrateId, CrateDisplay>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+struct CrateDisplay {
+    display_name: Option<SmolStr>,
+    version: Option<String>,
+    priority: i32,
+}
+
+impl CrateDisplayData {
+    pub fn display_name(&self, crate_id: CrateId) -> Option<&str> {
+        self.crates.get(&crate_id)?.display_name.as_ref().map(SmolStr::as_str)
+    }
+
+    pub fn version(&self, crate_id: CrateId) -> Option<&str> {
+        self.crates.get(&crate_id)?.version.as_ref().map(String::as_str)
+    }
+
+    pub fn crate_priority(&self, crate_id: CrateId) -> i32 {
+        self.crates.get(&crate_id).map_or(0, |it| it.priority)
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
 pub struct CrateId(pub u32);
 
+/// Opaque handle to the expander of a proc-macro crate. `ra_db` only stores
+/// it, the expander itself, like a proc-macro server, lives elsewhere.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct ProcMacroId(pub u32);
+
+/// Size of a crate graph, as computed by `CrateGraph::stats`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GraphStats {
+    pub crates: usize,
+    pub edges: usize,
+    /// Crates no other crate depends on, like binaries.
+    pub roots: usize,
+    /// Crates without dependencies, like `core`.
+    pub sinks: usize,
+}
+
+/// How much a crate pulls in, as computed by
+/// `SourceDatabase::crate_dep_metrics`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CrateDepMetrics {
+    pub direct: usize,
+    /// Distinct crates reachable through dependencies, direct ones included.
+    pub transitive: usize,
+    pub max_depth: usize,
+}
+
+/// Transitive dependencies of every crate of a graph, as computed by
+/// `CrateGraph::reachability`, for answering many questions at once.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Reachability {
+    /// Row of the crate's strongly connected component and the crate's bit
+    /// within a row.
+    index: FxHashMap<CrateId, (usize, usize)>,
+    words_per_row: usize,
+    rows: Vec<u64>,
+}
+
+impl Reachability {
+    /// Whether `b` is in `CrateGraph::transitive_dependencies(a)`. Crates
+    /// which are not in the graph reach nothing and can't be reached.
+    pub fn can_reach(&self, a: CrateId, b: CrateId) -> bool {
+        match (self.index.get(&a), self.index.get(&b)) {
+            (Some(&(row, _)), Some(&(_, bit))) => {
+                self.rows[row * self.words_per_row + bit / 64] & (1u64 << (bit % 64)) != 0
+            }
+            _ => false,
+        }
+    }
+}
+
+/// An identity of a crate which, unlike `CrateId`, does not depend on the
+/// order in which crates were added, and so survives a rebuild of the graph.
+///
+/// It is made of the path of the crate root and the kind of its source root,
+/// see `CrateStableId::for_crate`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct CrateStableId {
+    pub root_path: RelativePathBuf,
+    pub kind: SourceRootKind,
+}
+
+impl CrateStableId {
+    /// The stable id of the crate of the database's crate graph.
+    pub fn for_crate(db: &impl SourceDatabaseExt, crate_id: CrateId) -> CrateStableId {
+        CrateStableId::for_root(db, db.crate_graph().crate_root(crate_id))
+    }
+
+    /// The stable id of a crate rooted at the file. The path is prefixed with
+    /// the directory of the source root if it's in `source_root_paths`, so that
+    /// the `src/lib.rs`s of different packages are told apart.
+    pub fn for_root(db: &impl SourceDatabaseExt, root: FileId) -> CrateStableId {
+        let source_root_id = db.file_source_root(root);
+        let path = db.file_relative_path(root);
+        let root_path = match db.source_root_paths().get(&source_root_id) {
+            Some(dir) => dir.join_normalized(&path),
+            None => path,
+        };
+        CrateStableId { root_path, kind: db.source_root(source_root_id).kind() }
+    }
+}
+
+/// A crate of a `CrateGraph`, built outside of the graph for
+/// `CrateGraph::update_crate`.
 #[derive(Debug, Clone, PartialEq, Eq)]
-struct CrateData {
+pub struct CrateData {
     file_id: FileId,
     edition: Edition,
     cfg_options: CfgOptions,
     env: Env,
     dependencies: Vec<Dependency>,
+    is_member: bool,
+    /// The crate is one of the sysroot crates, like `core` or `std`.
+    is_lang: bool,
+    /// Name of the package the crate comes from. Unlike the names on the
+    /// dependency edges, it is purely informational.
+    display_name: Option<CrateName>,
+    version: Option<String>,
+    stable_id: Option<CrateStableId>,
+    potential_cfg: Option<CfgOptions>,
+    /// The project model didn't say, `edition` is a placeholder.
+    edition_is_unknown: bool,
+    /// Whether `cfg(test)` is enabled. It's kept apart from `cfg_options`, as
+    /// it's switched often, to look at the crate as it's seen by `cargo test`.
+    cfg_test: bool,
+    /// Crate-wide lint levels, like `-D warnings` passed by the build system
+    /// or `#![deny(missing_docs)]` at the top of the crate root.
+    lint_levels: FxHashMap<SmolStr, LintLevel>,
+    /// Artifacts built from the crate, never empty.
+    kinds: Vec<CrateKind>,
+    /// Hint for background work, higher goes first. It doesn't affect the
+    /// analysis.
+    priority: i32,
+    proc_macro: Option<ProcMacroId>,
+}
+
+/// Level of a lint, as in `#![allow(..)]` and friends. `ra_db` only stores
+/// it, it's up to the diagnostics to decide what it means for them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintLevel {
+    Allow,
+    Warn,
+    Deny,
+    Forbid,
+}
+
+/// Kind of artifact a crate is built into, as in Cargo's `crate-type`. Binary
+/// crates have a `main` function, the others don't.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CrateKind {
+    Bin,
+    Lib,
+    Rlib,
+    Dylib,
+    Cdylib,
+    Staticlib,
+    ProcMacro,
+}
+
+/// Where the crate comes from, see `CrateGraph::origin`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CrateOrigin {
+    /// A member of the workspace.
+    Member,
+    /// A dependency of the workspace, like a crate from crates.io.
+    Dependency,
+    /// A crate of the standard library, from the sysroot.
+    Lang,
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
@@ -107,9 +569,25 @@ pub struct Env {
 pub struct Dependency {
     pub crate_id: CrateId,
     pub name: SmolStr,
+    pub kind: DependencyKind,
+    /// For optional dependencies, the feature of the dependent crate which
+    /// enables the dependency.
+    pub feature: Option<SmolStr>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DependencyKind {
+    Normal,
+    /// A procedural macro, which is compiled for and runs on the host rather
+    /// than the target.
+    ProcMacro,
+    /// A dependency of the build script.
+    Build,
 }
 
 impl CrateGraph {
+    /// Panics if the root is already a root of a crate with a different
+    /// edition, use `try_add_crate_root` for roots coming from the user.
     pub fn add_crate_root(
         &mut self,
         file_id: FileId,
@@ -117,183 +595,2346 @@ impl CrateGraph {
         cfg_options: CfgOptions,
         env: Env,
     ) -> CrateId {
+        match self.try_add_crate_root(file_id, edition, cfg_options, env) {
+            Ok(crate_id) => crate_id,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Like `add_crate_root`, but refuses to register a root which is already
+    /// a root of a crate with a different edition.
+    pub fn try_add_crate_root(
+        &mut self,
+        file_id: FileId,
+        edition: Edition,
+        cfg_options: CfgOptions,
+        env: Env,
+    ) -> Result<CrateId, ConflictingEditionError> {
+        let _p = profile("CrateGraph::add_crate_root");
+        self.check_edition(file_id, edition)?;
         let data = CrateData::new(file_id, edition, cfg_options, env);
-        let crate_id = CrateId(self.arena.len() as u32);
+        let crate_id = CrateId(self.next_id);
+        self.next_id += 1;
         let prev = self.arena.insert(crate_id, data);
         assert!(prev.is_none());
-        crate_id
+        self.roots.entry(file_id).or_default().push(crate_id);
+        Ok(crate_id)
+    }
+
+    fn check_edition(
+        &self,
+        file_id: FileId,
+        edition: Edition,
+    ) -> Result<(), ConflictingEditionError> {
+        let crates = self.roots.get(&file_id).map(|it| it.as_slice()).unwrap_or_default();
+        match crates.iter().map(|crate_id| &self.arena[crate_id]).find(|it| it.edition != edition) {
+            Some(data) => {
+                Err(ConflictingEditionError { file_id, existing: data.edition, new: edition })
+            }
+            None => Ok(()),
+        }
+    }
+
+    fn remove_root(&mut self, file_id: FileId, crate_id: CrateId) {
+        if let Some(crates) = self.roots.get_mut(&file_id) {
+            crates.retain(|&it| it != crate_id);
+            if crates.is_empty() {
+                self.roots.remove(&file_id);
+            }
+        }
     }
 
     pub fn cfg_options(&self, crate_id: CrateId) -> &CfgOptions {
         &self.arena[&crate_id].cfg_options
     }
 
-    pub fn add_dep(
-        &mut self,
-        from: CrateId,
-        name: SmolStr,
-        to: CrateId,
-    ) -> Result<(), CyclicDependenciesError> {
-        if self.dfs_find(from, to, &mut FxHashSet::default()) {
-            return Err(CyclicDependenciesError);
-        }
-        self.arena.get_mut(&from).unwrap().add_dep(name, to);
-        Ok(())
+    /// Marks the crate as a member of the workspace, as opposed to a
+    /// dependency or a sysroot crate. Crates are not members by default.
+    ///
+    /// A crate is a member exactly if its root is not in a library source
+    /// root, so this should be set from `SourceRoot::is_library` of the root,
+    /// see `SourceDatabaseExt::check_invariants`.
+    pub fn set_is_member(&mut self, crate_id: CrateId, is_member: bool) {
+        self.arena.get_mut(&crate_id).unwrap().is_member = is_member;
     }
 
-    pub fn is_empty(&self) -> bool {
-        self.arena.is_empty()
+    /// Whether the crate is being edited by the user, and not just used by it.
+    /// Features like diagnostics and inlay hints are usually limited to member
+    /// crates.
+    pub fn is_member(&self, crate_id: CrateId) -> bool {
+        self.arena[&crate_id].is_member
     }
 
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = CrateId> + 'a {
-        self.arena.keys().copied()
+    /// Marks the crate as coming from the sysroot. Crates are not lang crates
+    /// by default.
+    pub fn set_is_lang(&mut self, crate_id: CrateId, is_lang: bool) {
+        self.arena.get_mut(&crate_id).unwrap().is_lang = is_lang;
     }
 
-    pub fn crate_root(&self, crate_id: CrateId) -> FileId {
-        self.arena[&crate_id].file_id
+    /// Member crates are `Member`s even if they are lang crates too, as is the
+    /// case when working on the standard library itself.
+    pub fn origin(&self, crate_id: CrateId) -> CrateOrigin {
+        let data = &self.arena[&crate_id];
+        if data.is_member {
+            CrateOrigin::Member
+        } else if data.is_lang {
+            CrateOrigin::Lang
+        } else {
+            CrateOrigin::Dependency
+        }
     }
 
-    pub fn edition(&self, crate_id: CrateId) -> Edition {
-        self.arena[&crate_id].edition
+    pub fn set_display_name(&mut self, crate_id: CrateId, display_name: String) {
+        let name = self.names.intern(&display_name);
+        self.arena.get_mut(&crate_id).unwrap().display_name = Some(name);
     }
 
-    // FIXME: this only finds one crate with the given root; we could have multiple
-    pub fn crate_id_for_crate_root(&self, file_id: FileId) -> Option<CrateId> {
-        let (&crate_id, _) = self.arena.iter().find(|(_crate_id, data)| data.file_id == file_id)?;
-        Some(crate_id)
+    /// Queries should use `SourceDatabase::crate_display_data` instead: the
+    /// graph in the database is not replaced if only display data changes.
+    pub fn display_name(&self, crate_id: CrateId) -> Option<&str> {
+        let name = self.arena[&crate_id].display_name?;
+        Some(self.names.lookup(name))
     }
 
-    pub fn dependencies<'a>(
-        &'a self,
-        crate_id: CrateId,
-    ) -> impl Iterator<Item = &'a Dependency> + 'a {
-        self.arena[&crate_id].dependencies.iter()
+    /// The interned display name of the crate, which is cheaper to compare
+    /// than the string.
+    pub fn crate_name(&self, crate_id: CrateId) -> Option<CrateName> {
+        self.arena[&crate_id].display_name
     }
 
-    /// Extends this crate graph by adding a complete disjoint second crate
-    /// graph.
-    ///
-    /// The ids of the crates in the `other` graph are shifted by the return
-    /// amount.
-    pub fn extend(&mut self, other: CrateGraph) -> u32 {
-        let start = self.arena.len() as u32;
-        self.arena.extend(other.arena.into_iter().map(|(id, mut data)| {
-            let new_id = id.shift(start);
-            for dep in &mut data.dependencies {
-                dep.crate_id = dep.crate_id.shift(start);
-            }
-            (new_id, data)
-        }));
-        start
+    /// Sets the cfg options the crate could be compiled with, like all of the
+    /// features it declares, as opposed to the currently active ones.
+    pub fn set_potential_cfg(&mut self, crate_id: CrateId, potential_cfg: CfgOptions) {
+        self.arena.get_mut(&crate_id).unwrap().potential_cfg = Some(potential_cfg);
     }
 
-    fn dfs_find(&self, target: CrateId, from: CrateId, visited: &mut FxHashSet<CrateId>) -> bool {
-        if !visited.insert(from) {
-            return false;
-        }
+    pub fn set_cfg_test(&mut self, crate_id: CrateId, cfg_test: bool) {
+        self.arena.get_mut(&crate_id).unwrap().cfg_test = cfg_test;
+    }
 
-        for dep in self.dependencies(from) {
-            let crate_id = dep.crate_id();
-            if crate_id == target {
-                return true;
-            }
+    /// Whether the crate is analyzed with `cfg(test)` enabled. This is not
+    /// reflected in `cfg_options`.
+    pub fn cfg_test(&self, crate_id: CrateId) -> bool {
+        self.arena[&crate_id].cfg_test
+    }
 
-            if self.dfs_find(target, crate_id, visited) {
-                return true;
-            }
-        }
-        false
+    /// Lets the host bias background work, like warming up caches, towards
+    /// the crate the user is working on.
+    pub fn set_crate_priority(&mut self, crate_id: CrateId, priority: i32) {
+        self.arena.get_mut(&crate_id).unwrap().priority = priority;
     }
-}
 
-impl CrateId {
-    pub fn shift(self, amount: u32) -> CrateId {
-        CrateId(self.0 + amount)
+    /// Priority set with `set_crate_priority`, `0` by default. Crates of
+    /// higher priority should be worked on first.
+    pub fn crate_priority(&self, crate_id: CrateId) -> i32 {
+        self.arena[&crate_id].priority
     }
-}
 
-impl CrateData {
-    fn new(file_id: FileId, edition: Edition, cfg_options: CfgOptions, env: Env) -> CrateData {
-        CrateData { file_id, edition, dependencies: Vec::new(), cfg_options, env }
+    /// Sets the crate-wide level of the lint, like `warnings` or
+    /// `clippy::all`, replacing the previous one.
+    pub fn set_lint_level(&mut self, crate_id: CrateId, lint: &str, level: LintLevel) {
+        self.arena.get_mut(&crate_id).unwrap().lint_levels.insert(lint.into(), level);
     }
 
-    fn add_dep(&mut self, name: SmolStr, crate_id: CrateId) {
-        self.dependencies.push(Dependency { name, crate_id })
+    /// Replaces the kinds of artifacts built from the crate, which are just
+    /// `[CrateKind::Lib]` by default.
+    pub fn set_crate_kinds(&mut self, crate_id: CrateId, kinds: Vec<CrateKind>) {
+        assert!(!kinds.is_empty(), "a crate must have at least one kind");
+        self.arena.get_mut(&crate_id).unwrap().kinds = kinds;
     }
-}
 
-impl FromStr for Edition {
-    type Err = ParseEditionError;
+    pub fn crate_kinds(&self, crate_id: CrateId) -> &[CrateKind] {
+        &self.arena[&crate_id].kinds
+    }
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let res = match s {
-            "2015" => Edition::Edition2015,
-            "2018" => Edition::Edition2018,
-            _ => Err(ParseEditionError { invalid_input: s.to_string() })?,
-        };
-        Ok(res)
+    /// Sets the handle through which the macros of a proc-macro crate are
+    /// expanded. The crate must already have the `ProcMacro` kind.
+    pub fn set_proc_macro_handle(&mut self, crate_id: CrateId, handle: ProcMacroId) {
+        let data = self.arena.get_mut(&crate_id).unwrap();
+        assert!(data.kinds.contains(&CrateKind::ProcMacro), "not a proc-macro crate");
+        data.proc_macro = Some(handle);
     }
-}
 
-impl fmt::Display for Edition {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        f.write_str(match self {
-            Edition::Edition2015 => "2015",
-            Edition::Edition2018 => "2018",
-        })
+    /// `None` if the crate is not a proc-macro crate, or if no expander was
+    /// registered for it.
+    pub fn proc_macro_handle(&self, crate_id: CrateId) -> Option<ProcMacroId> {
+        let data = &self.arena[&crate_id];
+        if !data.kinds.contains(&CrateKind::ProcMacro) {
+            return None;
+        }
+        data.proc_macro
     }
-}
 
-impl Dependency {
-    pub fn crate_id(&self) -> CrateId {
-        self.crate_id
+    /// The crate-wide level of the lint, `None` if the crate doesn't set one.
+    /// Levels of lint groups are not applied to the lints in the group.
+    pub fn crate_lint_level(&self, crate_id: CrateId, lint: &str) -> Option<LintLevel> {
+        self.arena[&crate_id].lint_levels.get(lint).copied()
     }
-}
 
-#[derive(Debug)]
-pub struct ParseEditionError {
-    invalid_input: String,
-}
+    /// Potential cfg options of the crate, or the active ones if unknown.
+    pub fn potential_cfg(&self, crate_id: CrateId) -> &CfgOptions {
+        let data = &self.arena[&crate_id];
+        data.potential_cfg.as_ref().unwrap_or(&data.cfg_options)
+    }
 
-impl fmt::Display for ParseEditionError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "invalid edition: {:?}", self.invalid_input)
+    pub fn set_version(&mut self, crate_id: CrateId, version: String) {
+        self.arena.get_mut(&crate_id).unwrap().version = Some(version);
     }
-}
 
-impl std::error::Error for ParseEditionError {}
+    pub fn version(&self, crate_id: CrateId) -> Option<&str> {
+        self.arena[&crate_id].version.as_ref().map(String::as_str)
+    }
 
-#[derive(Debug)]
-pub struct CyclicDependenciesError;
+    pub fn set_stable_id(&mut self, crate_id: CrateId, stable_id: CrateStableId) {
+        self.arena.get_mut(&crate_id).unwrap().stable_id = Some(stable_id);
+    }
 
-#[cfg(test)]
-mod tests {
-    use super::{CfgOptions, CrateGraph, Edition::Edition2018, Env, FileId, SmolStr};
+    pub fn stable_id(&self, crate_id: CrateId) -> Option<&CrateStableId> {
+        self.arena[&crate_id].stable_id.as_ref()
+    }
 
-    #[test]
-    fn it_should_panic_because_of_cycle_dependencies() {
-        let mut graph = CrateGraph::default();
-        let crate1 =
-            graph.add_crate_root(FileId(1u32), Edition2018, CfgOptions::default(), Env::default());
-        let crate2 =
-            graph.add_crate_root(FileId(2u32), Edition2018, CfgOptions::default(), Env::default());
-        let crate3 =
-            graph.add_crate_root(FileId(3u32), Edition2018, CfgOptions::default(), Env::default());
-        assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
-        assert!(graph.add_dep(crate2, SmolStr::new("crate3"), crate3).is_ok());
-        assert!(graph.add_dep(crate3, SmolStr::new("crate1"), crate1).is_err());
+    /// Sets the stable id of every crate to the one `CrateStableId::for_root`
+    /// derives from its root. Only the files of the roots have to be known to
+    /// the database, not the graph itself.
+    pub fn assign_stable_ids(&mut self, db: &impl SourceDatabaseExt) {
+        for data in self.arena.values_mut() {
+            data.stable_id = Some(CrateStableId::for_root(db, data.file_id));
+        }
     }
 
-    #[test]
+    pub fn by_stable_id(&self, stable_id: &CrateStableId) -> Option<CrateId> {
+        let (&crate_id, _) = self
+            .arena
+            .iter()
+            .find(|(_crate_id, data)| data.stable_id.as_ref() == Some(stable_id))?;
+        Some(crate_id)
+    }
+
+    /// Renumbers the crates from `0`, in the order of their stable ids, so
+    /// that the ids don't depend on the order in which the crates were added.
+    /// Crates without a stable id come last, ordered by root file. Returns the
+    /// new id of each old one, for fixing up ids held outside of the graph.
+    ///
+    /// Ids are only reproducible if every crate has a distinct stable id, as
+    /// is the case after `assign_stable_ids` unless crates share a root.
+    pub fn renumber_by_stable_id(&mut self) -> FxHashMap<CrateId, CrateId> {
+        let mut crates = self.arena.keys().copied().collect::<Vec<_>>();
+        crates.sort_by_key(|&crate_id| {
+            let data = &self.arena[&crate_id];
+            (data.stable_id.is_none(), data.stable_id.clone(), data.file_id, crate_id)
+        });
+        let mapping = crates
+            .into_iter()
+            .enumerate()
+            .map(|(idx, crate_id)| (crate_id, CrateId(idx as u32)))
+            .collect::<FxHashMap<_, _>>();
+        let arena = std::mem::replace(&mut self.arena, FxHashMap::default());
+        for (crate_id, mut data) in arena {
+            for dep in &mut data.dependencies {
+                // Dangling dependencies are kept as is, for `validate`.
+                dep.crate_id = mapping.get(&dep.crate_id).copied().unwrap_or(dep.crate_id);
+            }
+            self.arena.insert(mapping[&crate_id], data);
+        }
+        for crates in self.roots.values_mut() {
+            for crate_id in crates.iter_mut() {
+                *crate_id = mapping[crate_id];
+            }
+        }
+        self.next_id = mapping.len() as u32;
+        mapping
+    }
+
+    /// Base URL of the crate's documentation on docs.rs, if both the name and
+    /// the version of the crate are known.
+    pub fn docs_url(&self, crate_id: CrateId) -> Option<String> {
+        let name = self.display_name(crate_id)?;
+        let version = self.version(crate_id)?;
+        Some(format!("https://docs.rs/{}/{}", name, version))
+    }
+
+    pub fn add_dep(
+        &mut self,
+        from: CrateId,
+        name: SmolStr,
+        to: CrateId,
+    ) -> Result<(), CyclicDependenciesError> {
+        self.add_dep_with_kind(from, name, to, DependencyKind::Normal)
+    }
+
+    pub fn add_dep_with_kind(
+        &mut self,
+        from: CrateId,
+        name: SmolStr,
+        to: CrateId,
+        kind: DependencyKind,
+    ) -> Result<(), CyclicDependenciesError> {
+        let _p = profile("CrateGraph::add_dep");
+        if self.dfs_find(from, to, &mut FxHashSet::default()) {
+            return Err(CyclicDependenciesError);
+        }
+        self.arena.get_mut(&from).unwrap().add_dep(name, to, kind);
+        Ok(())
+    }
+
+    /// Adds a dependency which is only there when `feature` of `from` is
+    /// enabled, see `active_dependencies`.
+    pub fn add_optional_dep(
+        &mut self,
+        from: CrateId,
+        name: SmolStr,
+        to: CrateId,
+        feature: SmolStr,
+    ) -> Result<(), CyclicDependenciesError> {
+        self.add_dep(from, name, to)?;
+        self.arena.get_mut(&from).unwrap().dependencies.last_mut().unwrap().feature = Some(feature);
+        Ok(())
+    }
+
+    /// Replaces the root file, edition, cfg options, env, and dependencies of
+    /// the crate with those of `new`, keeping its `CrateId`, so that
+    /// `CrateId`-keyed caches stay valid. The other properties of the crate,
+    /// like its display name or membership, are kept as well. Edges leading
+    /// to the crate are not touched.
+    ///
+    /// The graph is left as it was if one of the new dependencies is not in
+    /// the graph, or would create a cycle.
+    pub fn update_crate(&mut self, crate_id: CrateId, new: CrateData) -> Result<(), GraphError> {
+        let _p = profile("CrateGraph::update_crate");
+        for dep in new.dependencies.iter() {
+            if !self.arena.contains_key(&dep.crate_id) {
+                return Err(GraphError::DanglingDependency { from: crate_id, to: dep.crate_id });
+            }
+        }
+        let data = self.arena.get_mut(&crate_id).expect("unknown crate");
+        let old = data.clone();
+        data.file_id = new.file_id;
+        data.edition = new.edition;
+        data.edition_is_unknown = false;
+        data.cfg_options = new.cfg_options;
+        data.env = new.env;
+        data.dependencies = new.dependencies;
+
+        let mut errors = Vec::new();
+        self.find_cycles(crate_id, &mut Vec::new(), &mut FxHashSet::default(), &mut errors);
+        // Cycles which don't go through the crate were there before.
+        if let Some(error) = errors.into_iter().find(|error| match error {
+            GraphError::Cycle { crates } => crates.contains(&crate_id),
+            _ => false,
+        }) {
+            self.arena.insert(crate_id, old);
+            return Err(error);
+        }
+        if old.file_id != new.file_id {
+            self.remove_root(old.file_id, crate_id);
+            self.roots.entry(new.file_id).or_default().push(crate_id);
+        }
+        Ok(())
+    }
+
+    /// Runs `f` on a copy of the graph, which replaces the graph if `f`
+    /// succeeds and the result has neither cycles nor dependencies on unknown
+    /// crates. Otherwise the graph is left as it was, and the error of `f` or
+    /// the first problem found is returned.
+    ///
+    /// The whole graph is cloned, and validated again afterwards, so this is
+    /// meant for assembling many crates at once and not for single edits. A
+    /// graph which already has a cycle can't be changed this way.
+    pub fn transaction<E, F>(&mut self, f: F) -> Result<(), E>
+    where
+        E: From<GraphError>,
+        F: FnOnce(&mut CrateGraphBuilder) -> Result<(), E>,
+    {
+        let _p = profile("CrateGraph::transaction");
+        let mut builder = CrateGraphBuilder { graph: self.clone() };
+        f(&mut builder)?;
+        if let Err(errors) = builder.graph.validate() {
+            // Shared roots are legal, see `GraphError::DuplicateRoot`.
+            if let Some(error) = errors.into_iter().find(|error| match error {
+                GraphError::DuplicateRoot { .. } => false,
+                _ => true,
+            }) {
+                return Err(error.into());
+            }
+        }
+        *self = builder.graph;
+        Ok(())
+    }
+
+    /// Removes the crate together with all dependency edges leading to it.
+    ///
+    /// Ids of the remaining crates stay the same, and crates added later never
+    /// get the id of a removed crate.
+    pub fn remove_crate(&mut self, crate_id: CrateId) {
+        if let Some(data) = self.arena.remove(&crate_id) {
+            self.remove_root(data.file_id, crate_id);
+        }
+        for data in self.arena.values_mut() {
+            data.dependencies.retain(|dep| dep.crate_id != crate_id);
+        }
+    }
+
+    /// Crates whose module tree contains one of the files (see
+    /// `crate_reachable_files`), together with their transitive dependencies.
+    /// The graph should be the `crate_graph` of the `db`.
+    pub fn crates_reachable_from(
+        &self,
+        db: &impl SourceDatabase,
+        files: &[FileId],
+    ) -> FxHashSet<CrateId> {
+        let mut res = FxHashSet::default();
+        for crate_id in self.iter() {
+            if res.contains(&crate_id) {
+                continue;
+            }
+            let reachable = db.crate_reachable_files(crate_id);
+            if files.iter().any(|file_id| reachable.contains(file_id)) {
+                res.insert(crate_id);
+                res.extend(self.transitive_dependencies(crate_id));
+            }
+        }
+        res
+    }
+
+    /// Crates whose root file was removed from its source root, sorted by id.
+    /// Such crates are left behind by a stale project model, which should be
+    /// reloaded.
+    pub fn dangling_roots(&self, db: &impl SourceDatabaseExt) -> Vec<CrateId> {
+        let mut res = self
+            .crate_roots()
+            .filter(|&(_, file_id)| !db.file_exists(file_id))
+            .map(|(crate_id, _)| crate_id)
+            .collect::<Vec<_>>();
+        res.sort();
+        res
+    }
+
+    /// Dependency edges whose crates are rooted in source roots of different
+    /// kinds, like a workspace crate depending on a crates.io one, with the
+    /// kinds of the dependent and of the dependency. Sorted by the ids of the
+    /// two crates.
+    pub fn cross_kind_edges(
+        &self,
+        db: &impl SourceDatabaseExt,
+    ) -> Vec<(CrateId, CrateId, SourceRootKind, SourceRootKind)> {
+        let kind_of = |crate_id: CrateId| {
+            db.source_root(db.file_source_root(self.crate_root(crate_id))).kind()
+        };
+        let mut res = Vec::new();
+        for from in self.iter() {
+            let from_kind = kind_of(from);
+            for dep in self.dependencies(from) {
+                if !self.arena.contains_key(&dep.crate_id) {
+                    continue;
+                }
+                let to_kind = kind_of(dep.crate_id);
+                if from_kind != to_kind {
+                    res.push((from, dep.crate_id, from_kind, to_kind));
+                }
+            }
+        }
+        res.sort_by_key(|&(from, to, _, _)| (from, to));
+        res
+    }
+
+    /// Crates with the number of files in their source root, biggest first,
+    /// as a rough measure of how much work analyzing the crate takes.
+    pub fn crates_by_file_count(&self, db: &impl SourceDatabaseExt) -> Vec<(CrateId, usize)> {
+        let mut res = self
+            .crate_roots()
+            .map(|(crate_id, file_id)| {
+                let source_root = db.source_root(db.file_source_root(file_id));
+                (crate_id, source_root.walk().count())
+            })
+            .collect::<Vec<_>>();
+        res.sort_by_key(|&(crate_id, count)| (std::cmp::Reverse(count), crate_id));
+        res
+    }
+
+    /// Equality which ignores the purely informational fields, the ones in
+    /// `display_data`. If a refreshed project model produces a graph
+    /// semantically equal to the current one, it doesn't affect analysis.
+    pub fn semantically_eq(&self, other: &CrateGraph) -> bool {
+        self.arena.len() == other.arena.len()
+            && self.arena.iter().all(|(crate_id, data)| {
+                other.arena.get(crate_id).map_or(false, |it| data.semantically_eq(it))
+            })
+    }
+
+    pub fn display_data(&self) -> CrateDisplayData {
+        let crates = self
+            .arena
+            .iter()
+            .map(|(&crate_id, data)| {
+                let display = CrateDisplay {
+                    display_name: data.display_name.map(|it| self.names.lookup(it).into()),
+                    version: data.version.clone(),
+                    priority: data.priority,
+                };
+                (crate_id, display)
+            })
+            .collect();
+        CrateDisplayData { crates }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.arena.is_empty()
+    }
+
+    pub fn iter<'a>(&'a self) -> impl Iterator<Item = CrateId> + 'a {
+        self.arena.keys().copied()
+    }
+
+    pub fn crate_root(&self, crate_id: CrateId) -> FileId {
+        self.arena[&crate_id].file_id
+    }
+
+    /// All crates of the graph, paired with their root files.
+    pub fn crate_roots<'a>(&'a self) -> impl Iterator<Item = (CrateId, FileId)> + 'a {
+        self.arena.iter().map(|(&crate_id, data)| (crate_id, data.file_id))
+    }
+
+    pub fn edition(&self, crate_id: CrateId) -> Edition {
+        self.arena[&crate_id].edition
+    }
+
+    /// Records that the edition given to `add_crate_root` was made up because
+    /// the project model doesn't know the real one. See
+    /// `SourceDatabase::inferred_edition`.
+    pub fn mark_edition_unknown(&mut self, crate_id: CrateId) {
+        self.arena.get_mut(&crate_id).unwrap().edition_is_unknown = true;
+    }
+
+    pub fn is_edition_known(&self, crate_id: CrateId) -> bool {
+        !self.arena[&crate_id].edition_is_unknown
+    }
+
+    // FIXME: this only finds one crate with the given root; we could have multiple
+    pub fn crate_id_for_crate_root(&self, file_id: FileId) -> Option<CrateId> {
+        self.roots.get(&file_id)?.first().copied()
+    }
+
+    pub fn dependencies<'a>(
+        &'a self,
+        crate_id: CrateId,
+    ) -> impl Iterator<Item = &'a Dependency> + 'a {
+        self.arena[&crate_id].dependencies.iter()
+    }
+
+    /// `dependencies` without the optional dependencies whose feature is not
+    /// among `enabled_features`.
+    pub fn active_dependencies<'a>(
+        &'a self,
+        crate_id: CrateId,
+        enabled_features: &'a FxHashSet<SmolStr>,
+    ) -> impl Iterator<Item = &'a Dependency> + 'a {
+        self.dependencies(crate_id).filter(move |dep| match &dep.feature {
+            Some(feature) => enabled_features.contains(feature),
+            None => true,
+        })
+    }
+
+    /// The crate `from` knows under `name` in `extern crate` items and paths,
+    /// which is the name of the dependency edge and not necessarily the name
+    /// of the crate. `None` if `from` has no such dependency or is not in the
+    /// graph.
+    pub fn resolve_dep_name(&self, from: CrateId, name: &str) -> Option<CrateId> {
+        let data = self.arena.get(&from)?;
+        data.dependencies.iter().find(|dep| dep.name.as_str() == name).map(|dep| dep.crate_id)
+    }
+
+    /// Extends this crate graph by adding a complete disjoint second crate
+    /// graph.
+    ///
+    /// The ids of the crates in the `other` graph are shifted by the return
+    /// amount. Nothing is added if `other` has a crate whose root is a root of
+    /// this graph as well, but with a different edition.
+    pub fn extend(&mut self, other: CrateGraph) -> Result<u32, ConflictingEditionError> {
+        for data in other.arena.values() {
+            self.check_edition(data.file_id, data.edition)?;
+        }
+        let start = self.next_id;
+        let CrateGraph { arena, names, next_id, roots: _ } = other;
+        self.next_id += next_id;
+        for (id, mut data) in arena {
+            let new_id = id.shift(start);
+            self.roots.entry(data.file_id).or_default().push(new_id);
+            for dep in &mut data.dependencies {
+                dep.crate_id = dep.crate_id.shift(start);
+            }
+            data.display_name = data.display_name.map(|name| self.names.intern(names.lookup(name)));
+            self.arena.insert(new_id, data);
+        }
+        Ok(start)
+    }
+
+    /// Checks the graph for cycles, dependencies on crates which are not in
+    /// the graph, and crates sharing a root file.
+    ///
+    /// `add_dep` already refuses to create cycles, but graphs assembled from an
+    /// external project model are worth checking once at load time.
+    pub fn validate(&self) -> Result<(), Vec<GraphError>> {
+        let mut crates = self.arena.keys().copied().collect::<Vec<_>>();
+        crates.sort();
+
+        let mut errors = Vec::new();
+        let mut roots: FxHashMap<FileId, Vec<CrateId>> = FxHashMap::default();
+        for &crate_id in crates.iter() {
+            let data = &self.arena[&crate_id];
+            roots.entry(data.file_id).or_default().push(crate_id);
+            for dep in data.dependencies.iter() {
+                if !self.arena.contains_key(&dep.crate_id) {
+                    errors
+                        .push(GraphError::DanglingDependency { from: crate_id, to: dep.crate_id });
+                }
+            }
+        }
+
+        let mut duplicates =
+            roots.into_iter().filter(|(_, crates)| crates.len() > 1).collect::<Vec<_>>();
+        duplicates.sort();
+        errors.extend(
+            duplicates
+                .into_iter()
+                .map(|(file_id, crates)| GraphError::DuplicateRoot { file_id, crates }),
+        );
+
+        let mut done = FxHashSet::default();
+        for &crate_id in crates.iter() {
+            self.find_cycles(crate_id, &mut Vec::new(), &mut done, &mut errors);
+        }
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(errors)
+        }
+    }
+
+    /// Distinct editions of the crates.
+    pub fn editions_in_use(&self) -> FxHashSet<Edition> {
+        self.arena.values().map(|data| data.edition).collect()
+    }
+
+    pub fn stats(&self) -> GraphStats {
+        let depended_on: FxHashSet<CrateId> = self
+            .arena
+            .values()
+            .flat_map(|data| data.dependencies.iter().map(|dep| dep.crate_id))
+            .collect();
+        GraphStats {
+            crates: self.arena.len(),
+            edges: self.arena.values().map(|data| data.dependencies.len()).sum(),
+            roots: self.arena.keys().filter(|crate_id| !depended_on.contains(crate_id)).count(),
+            sinks: self.leaves().count(),
+        }
+    }
+
+    /// Crates without dependencies, the starting points of a bottom-up
+    /// traversal. Usually these are just `core` and the like.
+    pub fn leaves<'a>(&'a self) -> impl Iterator<Item = CrateId> + 'a {
+        self.arena
+            .iter()
+            .filter(|(_, data)| data.dependencies.is_empty())
+            .map(|(&crate_id, _)| crate_id)
+    }
+
+    /// Crates the crate depends on, directly or indirectly. The crate itself is
+    /// only included if it's a part of a dependency cycle.
+    pub fn transitive_dependencies(&self, of: CrateId) -> FxHashSet<CrateId> {
+        let mut res = FxHashSet::default();
+        let mut work = vec![of];
+        while let Some(crate_id) = work.pop() {
+            for dep in self.dependencies(crate_id) {
+                if self.arena.contains_key(&dep.crate_id) && res.insert(dep.crate_id) {
+                    work.push(dep.crate_id);
+                }
+            }
+        }
+        res
+    }
+
+    /// Whether `on` is in `transitive_dependencies(from)`, stopping as soon as
+    /// it's found. For many such checks, `SourceDatabase::crate_reachability`
+    /// is cheaper.
+    pub fn depends_on(&self, from: CrateId, on: CrateId) -> bool {
+        let mut visited = FxHashSet::default();
+        let mut work = vec![from];
+        while let Some(crate_id) = work.pop() {
+            let data = match self.arena.get(&crate_id) {
+                Some(it) => it,
+                None => continue,
+            };
+            for dep in data.dependencies.iter() {
+                if dep.crate_id == on && self.arena.contains_key(&on) {
+                    return true;
+                }
+                if visited.insert(dep.crate_id) {
+                    work.push(dep.crate_id);
+                }
+            }
+        }
+        false
+    }
+
+    /// Sum of `transitive_dependencies(crate_id).len()` over all crates.
+    ///
+    /// The dependencies of a crate are computed once and reused for its
+    /// dependents, but they are still stored for every crate at once, so time
+    /// and memory are quadratic in the number of crates in the worst case.
+    /// Don't call this on every change of a graph with many thousands of
+    /// crates.
+    pub fn transitive_edge_count(&self) -> usize {
+        // Crates of a strongly connected component share their dependencies,
+        // and `sccs` lists components after the ones they depend on.
+        let sccs = self.sccs();
+        let scc_of = sccs
+            .iter()
+            .enumerate()
+            .flat_map(|(idx, scc)| scc.iter().map(move |&crate_id| (crate_id, idx)))
+            .collect::<FxHashMap<_, _>>();
+        let mut closures: Vec<FxHashSet<CrateId>> = Vec::with_capacity(sccs.len());
+        let mut res = 0;
+        for (idx, scc) in sccs.iter().enumerate() {
+            let mut closure = FxHashSet::default();
+            for &crate_id in scc.iter() {
+                for dep in self.dependencies(crate_id) {
+                    let dep_scc = match scc_of.get(&dep.crate_id) {
+                        Some(&it) => it,
+                        None => continue,
+                    };
+                    if dep_scc == idx {
+                        // A cycle, every crate of which depends on all of them.
+                        closure.extend(scc.iter().copied());
+                    } else if closure.insert(dep.crate_id) {
+                        closure.extend(closures[dep_scc].iter().copied());
+                    }
+                }
+            }
+            res += closure.len() * scc.len();
+            closures.push(closure);
+        }
+        res
+    }
+
+    /// `transitive_dependencies` of all crates at once, as a bitset for each
+    /// strongly connected component. Memory is quadratic in the number of
+    /// crates, as with `transitive_edge_count`; use
+    /// `SourceDatabase::crate_reachability` to avoid recomputing it.
+    pub fn reachability(&self) -> Reachability {
+        let sccs = self.sccs();
+        let mut crates = self.arena.keys().copied().collect::<Vec<_>>();
+        crates.sort();
+        let mut index = FxHashMap::default();
+        for (row, scc) in sccs.iter().enumerate() {
+            for &crate_id in scc.iter() {
+                index.insert(crate_id, (row, 0));
+            }
+        }
+        for (bit, crate_id) in crates.iter().enumerate() {
+            index.get_mut(crate_id).unwrap().1 = bit;
+        }
+
+        let words_per_row = (crates.len() + 63) / 64;
+        let mut rows = vec![0u64; sccs.len() * words_per_row];
+        // `sccs` lists components after the ones they depend on, so the rows
+        // of the dependencies are complete by the time they are merged.
+        for (row, scc) in sccs.iter().enumerate() {
+            let (done, rest) = rows.split_at_mut(row * words_per_row);
+            let current = &mut rest[..words_per_row];
+            for &crate_id in scc.iter() {
+                for dep in self.dependencies(crate_id) {
+                    let (dep_row, dep_bit) = match index.get(&dep.crate_id) {
+                        Some(&it) => it,
+                        None => continue,
+                    };
+                    if dep_row == row {
+                        // A cycle, every crate of which depends on all of them.
+                        for member in scc.iter() {
+                            let bit = index[member].1;
+                            current[bit / 64] |= 1u64 << (bit % 64);
+                        }
+                        continue;
+                    }
+                    current[dep_bit / 64] |= 1u64 << (dep_bit % 64);
+                    let dep_words = &done[dep_row * words_per_row..(dep_row + 1) * words_per_row];
+                    for (word, dep_word) in current.iter_mut().zip(dep_words) {
+                        *word |= *dep_word;
+                    }
+                }
+            }
+        }
+        Reachability { index, words_per_row, rows }
+    }
+
+    /// Length of the longest dependency chain starting at the crate, `0` for a
+    /// crate without dependencies. Edges closing a cycle are not followed.
+    pub fn max_depth(&self, of: CrateId) -> usize {
+        self.depth_visit(of, &mut FxHashMap::default(), &mut FxHashSet::default())
+    }
+
+    fn depth_visit(
+        &self,
+        crate_id: CrateId,
+        memo: &mut FxHashMap<CrateId, usize>,
+        in_progress: &mut FxHashSet<CrateId>,
+    ) -> usize {
+        if let Some(&depth) = memo.get(&crate_id) {
+            return depth;
+        }
+        let data = match self.arena.get(&crate_id) {
+            Some(it) => it,
+            None => return 0,
+        };
+        in_progress.insert(crate_id);
+        let mut depth = 0;
+        for dep in data.dependencies.iter() {
+            if in_progress.contains(&dep.crate_id) || !self.arena.contains_key(&dep.crate_id) {
+                continue;
+            }
+            depth = depth.max(self.depth_visit(dep.crate_id, memo, in_progress) + 1);
+        }
+        in_progress.remove(&crate_id);
+        memo.insert(crate_id, depth);
+        depth
+    }
+
+    /// The immediate dominator of every crate reachable from `root` through
+    /// dependencies, except for `root` itself: the closest crate every
+    /// dependency chain from `root` to the crate goes through. Removing a
+    /// dominator cuts the crates it dominates off from `root`.
+    pub fn dominators(&self, root: CrateId) -> FxHashMap<CrateId, CrateId> {
+        // "A Simple, Fast Dominance Algorithm" by Cooper, Harvey and Kennedy.
+        let mut postorder = Vec::new();
+        self.postorder_visit(root, &mut FxHashSet::default(), &mut postorder);
+        let postorder_idx = postorder
+            .iter()
+            .enumerate()
+            .map(|(idx, &crate_id)| (crate_id, idx))
+            .collect::<FxHashMap<_, _>>();
+        let mut predecessors: FxHashMap<CrateId, Vec<CrateId>> = FxHashMap::default();
+        for &crate_id in postorder.iter() {
+            for dep in self.dependencies(crate_id) {
+                if postorder_idx.contains_key(&dep.crate_id) {
+                    predecessors.entry(dep.crate_id).or_default().push(crate_id);
+                }
+            }
+        }
+
+        let mut idom = FxHashMap::default();
+        idom.insert(root, root);
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for &crate_id in postorder.iter().rev() {
+                if crate_id == root {
+                    continue;
+                }
+                let mut new_idom = None;
+                for &pred in predecessors[&crate_id].iter() {
+                    if !idom.contains_key(&pred) {
+                        continue;
+                    }
+                    new_idom = Some(match new_idom {
+                        None => pred,
+                        Some(new_idom) => intersect(&idom, &postorder_idx, pred, new_idom),
+                    });
+                }
+                let new_idom = new_idom.unwrap();
+                if idom.insert(crate_id, new_idom) != Some(new_idom) {
+                    changed = true;
+                }
+            }
+        }
+        idom.remove(&root);
+        return idom;
+
+        fn intersect(
+            idom: &FxHashMap<CrateId, CrateId>,
+            postorder_idx: &FxHashMap<CrateId, usize>,
+            mut lhs: CrateId,
+            mut rhs: CrateId,
+        ) -> CrateId {
+            while lhs != rhs {
+                while postorder_idx[&lhs] < postorder_idx[&rhs] {
+                    lhs = idom[&lhs];
+                }
+                while postorder_idx[&rhs] < postorder_idx[&lhs] {
+                    rhs = idom[&rhs];
+                }
+            }
+            lhs
+        }
+    }
+
+    fn postorder_visit(
+        &self,
+        crate_id: CrateId,
+        visited: &mut FxHashSet<CrateId>,
+        res: &mut Vec<CrateId>,
+    ) {
+        if !visited.insert(crate_id) {
+            return;
+        }
+        let data = match self.arena.get(&crate_id) {
+            Some(it) => it,
+            None => return,
+        };
+        let mut deps = data.dependencies.iter().map(|dep| dep.crate_id).collect::<Vec<_>>();
+        deps.sort();
+        for dep in deps {
+            if self.arena.contains_key(&dep) {
+                self.postorder_visit(dep, visited, res);
+            }
+        }
+        res.push(crate_id);
+    }
+
+    /// All crates of the graph, each one coming after all of its dependencies.
+    pub fn iter_topological(&self) -> impl Iterator<Item = CrateId> {
+        let mut crates = self.arena.keys().copied().collect::<Vec<_>>();
+        crates.sort();
+        let mut visited = FxHashSet::default();
+        let mut res = Vec::with_capacity(crates.len());
+        for crate_id in crates {
+            self.topological_visit(crate_id, &mut visited, &mut res);
+        }
+        res.into_iter()
+    }
+
+    fn topological_visit(
+        &self,
+        crate_id: CrateId,
+        visited: &mut FxHashSet<CrateId>,
+        res: &mut Vec<CrateId>,
+    ) {
+        let data = match self.arena.get(&crate_id) {
+            Some(it) => it,
+            None => return,
+        };
+        if !visited.insert(crate_id) {
+            return;
+        }
+        for dep in data.dependencies.iter() {
+            self.topological_visit(dep.crate_id, visited, res);
+        }
+        res.push(crate_id);
+    }
+
+    /// Crates in dependency order, like `iter_topological`, but independent of
+    /// the order in which crates and dependencies were added: ties are broken
+    /// by stable id and then by root file. Equivalent graphs thus always come
+    /// out the same, which keeps serialized graphs reproducible.
+    pub fn serialization_order(&self) -> Vec<CrateId> {
+        let mut crates = self.arena.keys().copied().collect::<Vec<_>>();
+        crates.sort_by_key(|&crate_id| self.serialization_key(crate_id));
+        let mut visited = FxHashSet::default();
+        let mut res = Vec::with_capacity(crates.len());
+        for crate_id in crates {
+            self.serialization_visit(crate_id, &mut visited, &mut res);
+        }
+        res
+    }
+
+    fn serialization_key(&self, crate_id: CrateId) -> (Option<&CrateStableId>, FileId) {
+        let data = &self.arena[&crate_id];
+        (data.stable_id.as_ref(), data.file_id)
+    }
+
+    fn serialization_visit(
+        &self,
+        crate_id: CrateId,
+        visited: &mut FxHashSet<CrateId>,
+        res: &mut Vec<CrateId>,
+    ) {
+        let data = match self.arena.get(&crate_id) {
+            Some(it) => it,
+            None => return,
+        };
+        if !visited.insert(crate_id) {
+            return;
+        }
+        let mut deps = data
+            .dependencies
+            .iter()
+            .map(|dep| dep.crate_id)
+            .filter(|dep| self.arena.contains_key(dep))
+            .collect::<Vec<_>>();
+        deps.sort_by_key(|&dep| self.serialization_key(dep));
+        for dep in deps {
+            self.serialization_visit(dep, visited, res);
+        }
+        res.push(crate_id);
+    }
+
+    /// The graph in `petgraph` form, with an edge from every crate to each of
+    /// its dependencies, together with the node of every crate.
+    #[cfg(feature = "petgraph")]
+    pub fn to_petgraph(
+        &self,
+    ) -> (petgraph::graph::DiGraph<CrateId, ()>, FxHashMap<CrateId, petgraph::graph::NodeIndex>)
+    {
+        let mut crates = self.arena.keys().copied().collect::<Vec<_>>();
+        crates.sort();
+        let mut graph = petgraph::graph::DiGraph::with_capacity(crates.len(), 0);
+        let nodes = crates
+            .into_iter()
+            .map(|crate_id| (crate_id, graph.add_node(crate_id)))
+            .collect::<FxHashMap<_, _>>();
+        for (crate_id, data) in self.arena.iter() {
+            for dep in data.dependencies.iter() {
+                if let Some(&to) = nodes.get(&dep.crate_id) {
+                    graph.add_edge(nodes[crate_id], to, ());
+                }
+            }
+        }
+        (graph, nodes)
+    }
+
+    /// Strongly connected components of the graph, computed with Tarjan's
+    /// algorithm. Components come in dependency order: a component is listed
+    /// after every component it depends on. A valid graph has only singleton
+    /// components, larger ones are cycles.
+    pub fn sccs(&self) -> Vec<Vec<CrateId>> {
+        let mut crates = self.arena.keys().copied().collect::<Vec<_>>();
+        crates.sort();
+        let mut tarjan = Tarjan {
+            graph: self,
+            next_index: 0,
+            indices: FxHashMap::default(),
+            low_links: FxHashMap::default(),
+            stack: Vec::new(),
+            on_stack: FxHashSet::default(),
+            sccs: Vec::new(),
+        };
+        for crate_id in crates {
+            if !tarjan.indices.contains_key(&crate_id) {
+                tarjan.visit(crate_id);
+            }
+        }
+        tarjan.sccs
+    }
+
+    fn find_cycles(
+        &self,
+        crate_id: CrateId,
+        stack: &mut Vec<CrateId>,
+        done: &mut FxHashSet<CrateId>,
+        errors: &mut Vec<GraphError>,
+    ) {
+        if let Some(idx) = stack.iter().position(|&it| it == crate_id) {
+            errors.push(GraphError::Cycle { crates: stack[idx..].to_vec() });
+            return;
+        }
+        if done.contains(&crate_id) {
+            return;
+        }
+        let data = match self.arena.get(&crate_id) {
+            Some(it) => it,
+            None => return,
+        };
+        stack.push(crate_id);
+        for dep in data.dependencies.iter() {
+            self.find_cycles(dep.crate_id, stack, done, errors);
+        }
+        stack.pop();
+        done.insert(crate_id);
+    }
+
+    fn dfs_find(&self, target: CrateId, from: CrateId, visited: &mut FxHashSet<CrateId>) -> bool {
+        if !visited.insert(from) {
+            return false;
+        }
+
+        for dep in self.dependencies(from) {
+            let crate_id = dep.crate_id();
+            if crate_id == target {
+                return true;
+            }
+
+            if self.dfs_find(target, crate_id, visited) {
+                return true;
+            }
+        }
+        false
+    }
+}
+
+struct Tarjan<'a> {
+    graph: &'a CrateGraph,
+    next_index: u32,
+    indices: FxHashMap<CrateId, u32>,
+    low_links: FxHashMap<CrateId, u32>,
+    stack: Vec<CrateId>,
+    on_stack: FxHashSet<CrateId>,
+    sccs: Vec<Vec<CrateId>>,
+}
+
+impl Tarjan<'_> {
+    fn visit(&mut self, crate_id: CrateId) {
+        let index = self.next_index;
+        self.next_index += 1;
+        self.indices.insert(crate_id, index);
+        self.low_links.insert(crate_id, index);
+        self.stack.push(crate_id);
+        self.on_stack.insert(crate_id);
+
+        let graph = self.graph;
+        for dep in graph.arena[&crate_id].dependencies.iter() {
+            let dep_id = dep.crate_id;
+            if !graph.arena.contains_key(&dep_id) {
+                continue;
+            }
+            let low_link = if !self.indices.contains_key(&dep_id) {
+                self.visit(dep_id);
+                self.low_links[&dep_id]
+            } else if self.on_stack.contains(&dep_id) {
+                self.indices[&dep_id]
+            } else {
+                continue;
+            };
+            let current = self.low_links.get_mut(&crate_id).unwrap();
+            *current = (*current).min(low_link);
+        }
+
+        if self.low_links[&crate_id] == index {
+            let mut scc = Vec::new();
+            loop {
+                let member = self.stack.pop().unwrap();
+                self.on_stack.remove(&member);
+                scc.push(member);
+                if member == crate_id {
+                    break;
+                }
+            }
+            scc.sort();
+            self.sccs.push(scc);
+        }
+    }
+}
+
+impl CrateId {
+    pub fn shift(self, amount: u32) -> CrateId {
+        CrateId(self.0 + amount)
+    }
+}
+
+impl CrateData {
+    pub fn new(file_id: FileId, edition: Edition, cfg_options: CfgOptions, env: Env) -> CrateData {
+        CrateData {
+            file_id,
+            edition,
+            dependencies: Vec::new(),
+            cfg_options,
+            env,
+            is_member: false,
+            is_lang: false,
+            display_name: None,
+            version: None,
+            stable_id: None,
+            potential_cfg: None,
+            edition_is_unknown: false,
+            cfg_test: false,
+            lint_levels: FxHashMap::default(),
+            kinds: vec![CrateKind::Lib],
+            priority: 0,
+            proc_macro: None,
+        }
+    }
+
+    pub fn add_dep(&mut self, name: SmolStr, crate_id: CrateId, kind: DependencyKind) {
+        self.dependencies.push(Dependency { name, crate_id, kind, feature: None })
+    }
+
+    fn eq_with_names(
+        &self,
+        names: &NameInterner,
+        other: &CrateData,
+        other_names: &NameInterner,
+    ) -> bool {
+        let CrateData { display_name, version, priority, .. } = self;
+        self.semantically_eq(other)
+            && display_name.map(|it| names.lookup(it))
+                == other.display_name.map(|it| other_names.lookup(it))
+            && *version == other.version
+            && *priority == other.priority
+    }
+
+    fn semantically_eq(&self, other: &CrateData) -> bool {
+        // Destructure, so that new fields are not ignored by accident.
+        let CrateData {
+            file_id,
+            edition,
+            cfg_options,
+            env,
+            dependencies,
+            is_member,
+            is_lang,
+            display_name: _,
+            version: _,
+            stable_id,
+            potential_cfg,
+            edition_is_unknown,
+            cfg_test,
+            lint_levels,
+            kinds,
+            priority: _,
+            proc_macro,
+        } = self;
+        *file_id == other.file_id
+            && *edition == other.edition
+            && *cfg_options == other.cfg_options
+            && *env == other.env
+            && *dependencies == other.dependencies
+            && *is_member == other.is_member
+            && *is_lang == other.is_lang
+            && *stable_id == other.stable_id
+            && *potential_cfg == other.potential_cfg
+            && *edition_is_unknown == other.edition_is_unknown
+            && *cfg_test == other.cfg_test
+            && *lint_levels == other.lint_levels
+            && *kinds == other.kinds
+            && *proc_macro == other.proc_macro
+    }
+}
+
+impl FromStr for Edition {
+    type Err = ParseEditionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let res = match s {
+            "2015" => Edition::Edition2015,
+            "2018" => Edition::Edition2018,
+            _ => Err(ParseEditionError { invalid_input: s.to_string() })?,
+        };
+        Ok(res)
+    }
+}
+
+impl FromStr for CrateKind {
+    type Err = ParseCrateKindError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let res = match s {
+            "bin" => CrateKind::Bin,
+            "lib" => CrateKind::Lib,
+            "rlib" => CrateKind::Rlib,
+            "dylib" => CrateKind::Dylib,
+            "cdylib" => CrateKind::Cdylib,
+            "staticlib" => CrateKind::Staticlib,
+            "proc-macro" => CrateKind::ProcMacro,
+            _ => Err(ParseCrateKindError { invalid_input: s.to_string() })?,
+        };
+        Ok(res)
+    }
+}
+
+impl fmt::Display for Edition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Edition::Edition2015 => "2015",
+            Edition::Edition2018 => "2018",
+        })
+    }
+}
+
+impl Dependency {
+    pub fn crate_id(&self) -> CrateId {
+        self.crate_id
+    }
+
+    pub fn kind(&self) -> DependencyKind {
+        self.kind
+    }
+
+    pub fn is_optional(&self) -> bool {
+        self.feature.is_some()
+    }
+}
+
+#[derive(Debug)]
+pub struct ParseEditionError {
+    invalid_input: String,
+}
+
+impl fmt::Display for ParseEditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid edition: {:?}", self.invalid_input)
+    }
+}
+
+impl std::error::Error for ParseEditionError {}
+
+#[derive(Debug)]
+pub struct ParseCrateKindError {
+    invalid_input: String,
+}
+
+impl fmt::Display for ParseCrateKindError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "invalid crate kind: {:?}", self.invalid_input)
+    }
+}
+
+impl std::error::Error for ParseCrateKindError {}
+
+#[derive(Debug)]
+pub struct CyclicDependenciesError;
+
+/// The same root file was registered for crates of two different editions,
+/// which is a bug in the project model.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConflictingEditionError {
+    pub file_id: FileId,
+    pub existing: Edition,
+    pub new: Edition,
+}
+
+impl fmt::Display for ConflictingEditionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "crate root {:?} is registered with editions {} and {}",
+            self.file_id, self.existing, self.new
+        )
+    }
+}
+
+impl std::error::Error for ConflictingEditionError {}
+
+/// A problem found by `CrateGraph::validate`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum GraphError {
+    /// Each crate depends on the next one, and the last one depends on the
+    /// first.
+    Cycle { crates: Vec<CrateId> },
+    /// `from` depends on `to`, which is not a part of the graph.
+    DanglingDependency { from: CrateId, to: CrateId },
+    /// Several crates share a single root file. This is legal in principle
+    /// (the crates might differ in cfg options), but
+    /// `crate_id_for_crate_root` only ever finds one of them.
+    DuplicateRoot { file_id: FileId, crates: Vec<CrateId> },
+}
+
+impl fmt::Display for GraphError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GraphError::Cycle { crates } => write!(f, "cyclic dependencies: {:?}", crates),
+            GraphError::DanglingDependency { from, to } => {
+                write!(f, "{:?} depends on unknown crate {:?}", from, to)
+            }
+            GraphError::DuplicateRoot { file_id, crates } => {
+                write!(f, "crates {:?} share the root file {:?}", crates, file_id)
+            }
+        }
+    }
+}
+
+impl std::error::Error for GraphError {}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        edit_distance, CfgOptions, ConflictingEditionError, CrateData, CrateGraph, CrateId,
+        CrateKind, CrateStableId, DependencyKind, Edition, Edition::Edition2015,
+        Edition::Edition2018, Env, FileId, FxHashSet, GraphError, GraphStats, LintLevel,
+        ProcMacroId, RelativePath, RelativePathBuf, SmolStr, SourceRoot, SourceRootDiff,
+        SourceRootKind,
+    };
+    use crate::test_db::add_crate;
+
+    #[test]
+    fn it_should_panic_because_of_cycle_dependencies() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let crate3 = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
+        assert!(graph.add_dep(crate2, SmolStr::new("crate3"), crate3).is_ok());
+        assert!(graph.add_dep(crate3, SmolStr::new("crate1"), crate1).is_err());
+    }
+
+    #[test]
     fn it_works() {
         let mut graph = CrateGraph::default();
-        let crate1 =
-            graph.add_crate_root(FileId(1u32), Edition2018, CfgOptions::default(), Env::default());
-        let crate2 =
-            graph.add_crate_root(FileId(2u32), Edition2018, CfgOptions::default(), Env::default());
-        let crate3 =
-            graph.add_crate_root(FileId(3u32), Edition2018, CfgOptions::default(), Env::default());
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let crate3 = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
+        assert!(graph.add_dep(crate2, SmolStr::new("crate3"), crate3).is_ok());
+    }
+
+    #[test]
+    fn validate_reports_broken_graphs() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let crate3 = add_crate(&mut graph, FileId(2));
+        assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
+        assert!(graph.validate().is_err());
+
+        add_dep_unchecked(&mut graph, crate2, "crate1", crate1);
+        add_dep_unchecked(&mut graph, crate3, "ghost", CrateId(92));
+
+        assert_eq!(
+            graph.validate(),
+            Err(vec![
+                GraphError::DanglingDependency { from: crate3, to: CrateId(92) },
+                GraphError::DuplicateRoot { file_id: FileId(2u32), crates: vec![crate2, crate3] },
+                GraphError::Cycle { crates: vec![crate1, crate2] },
+            ])
+        );
+    }
+
+    #[test]
+    fn validate_accepts_well_formed_graph() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
+        assert_eq!(graph.validate(), Ok(()));
+    }
+
+    #[test]
+    fn crates_are_members_only_when_marked() {
+        let mut graph = CrateGraph::default();
+        let member = add_crate(&mut graph, FileId(1));
+        let external = add_crate(&mut graph, FileId(2));
+        graph.set_is_member(member, true);
+        assert!(graph.is_member(member));
+        assert!(!graph.is_member(external));
+    }
+
+    #[test]
+    fn crate_roots_pairs_crates_with_files() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let mut roots = graph.crate_roots().collect::<Vec<_>>();
+        roots.sort();
+        assert_eq!(roots, vec![(crate1, FileId(1u32)), (crate2, FileId(2u32))]);
+    }
+
+    #[test]
+    fn docs_url_needs_name_and_version() {
+        let mut graph = CrateGraph::default();
+        let krate = add_crate(&mut graph, FileId(1));
+        assert_eq!(graph.docs_url(krate), None);
+        graph.set_display_name(krate, "serde".to_string());
+        assert_eq!(graph.docs_url(krate), None);
+        graph.set_version(krate, "1.0.104".to_string());
+        assert_eq!(graph.docs_url(krate), Some("https://docs.rs/serde/1.0.104".to_string()));
+    }
+
+    #[test]
+    fn versions_tell_apart_crates_with_the_same_name() {
+        let mut graph = CrateGraph::default();
+        let rand07 = add_crate(&mut graph, FileId(1));
+        let rand08 = add_crate(&mut graph, FileId(2));
+        for &(krate, version) in [(rand07, "0.7.3"), (rand08, "0.8.0")].iter() {
+            graph.set_display_name(krate, "rand".to_string());
+            graph.set_version(krate, version.to_string());
+        }
+        assert_eq!(graph.display_name(rand07), graph.display_name(rand08));
+        assert_eq!(graph.version(rand07), Some("0.7.3"));
+        assert_eq!(graph.version(rand08), Some("0.8.0"));
+    }
+
+    #[test]
+    fn remove_crate_prunes_dependencies() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let crate3 = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
+        assert!(graph.add_dep(crate1, SmolStr::new("crate3"), crate3).is_ok());
+        assert!(graph.add_dep(crate3, SmolStr::new("crate2"), crate2).is_ok());
+
+        graph.remove_crate(crate2);
+
+        assert_eq!(graph.iter().count(), 2);
+        assert_eq!(
+            graph.dependencies(crate1).map(|it| it.crate_id).collect::<Vec<_>>(),
+            vec![crate3]
+        );
+        assert_eq!(graph.dependencies(crate3).count(), 0);
+        assert_eq!(graph.crate_root(crate3), FileId(3u32));
+        assert_eq!(graph.validate(), Ok(()));
+
+        let crate4 = add_crate(&mut graph, FileId(4));
+        assert!(crate4 != crate1 && crate4 != crate2 && crate4 != crate3);
+    }
+
+    #[test]
+    fn sccs_of_acyclic_graph_are_singletons() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let crate3 = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
+        assert!(graph.add_dep(crate2, SmolStr::new("crate3"), crate3).is_ok());
+        assert_eq!(graph.sccs(), vec![vec![crate3], vec![crate2], vec![crate1]]);
+    }
+
+    #[test]
+    fn sccs_group_a_cycle_among_acyclic_crates() {
+        let mut graph = CrateGraph::default();
+        let mut add = |n| add_crate(&mut graph, FileId(n));
+        let (bin, a, b, c, core, tool) = (add(1), add(2), add(3), add(4), add(5), add(6));
+        graph.add_dep(bin, SmolStr::new("a"), a).unwrap();
+        graph.add_dep(a, SmolStr::new("b"), b).unwrap();
+        graph.add_dep(b, SmolStr::new("c"), c).unwrap();
+        graph.add_dep(c, SmolStr::new("core"), core).unwrap();
+        graph.add_dep(tool, SmolStr::new("core"), core).unwrap();
+        add_dep_unchecked(&mut graph, c, "a", a);
+
+        assert_eq!(graph.sccs(), vec![vec![core], vec![a, b, c], vec![bin], vec![tool]]);
+    }
+
+    #[test]
+    fn sccs_find_a_cycle() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let crate3 = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
+        assert!(graph.add_dep(crate2, SmolStr::new("crate3"), crate3).is_ok());
+        add_dep_unchecked(&mut graph, crate3, "crate1", crate1);
+        assert_eq!(graph.sccs(), vec![vec![crate1, crate2, crate3]]);
+    }
+
+    #[test]
+    fn renumber_by_stable_id_ignores_load_order() {
+        let build = |order: &[(&str, u32)]| {
+            let mut graph = CrateGraph::default();
+            for &(root, file) in order {
+                let crate_id = add_crate(&mut graph, FileId(file));
+                let stable_id = CrateStableId {
+                    root_path: root.into(),
+                    kind: if root == "app" {
+                        SourceRootKind::Local
+                    } else {
+                        SourceRootKind::Library
+                    },
+                };
+                graph.set_stable_id(crate_id, stable_id);
+            }
+            let by_root = |graph: &CrateGraph, root: &str| {
+                graph.iter().find(|&it| graph.stable_id(it).unwrap().root_path == root).unwrap()
+            };
+            let (app, core, std) =
+                (by_root(&graph, "app"), by_root(&graph, "core"), by_root(&graph, "std"));
+            graph.add_dep(app, SmolStr::new("std"), std).unwrap();
+            graph.add_dep(std, SmolStr::new("core"), core).unwrap();
+            let mapping = graph.renumber_by_stable_id();
+            assert_eq!(mapping.len(), 3);
+            graph
+        };
+        let graph1 = build(&[("core", 1), ("std", 2), ("app", 3)]);
+        let graph2 = build(&[("app", 10), ("std", 11), ("core", 12)]);
+        let ids = |graph: &CrateGraph| {
+            let mut ids = graph
+                .iter()
+                .map(|it| {
+                    let deps = graph.dependencies(it).map(|dep| dep.crate_id).collect::<Vec<_>>();
+                    (it, graph.stable_id(it).unwrap().clone(), deps)
+                })
+                .collect::<Vec<_>>();
+            ids.sort();
+            ids
+        };
+        assert_eq!(ids(&graph1), ids(&graph2));
+        assert!(graph1.validate().is_ok());
+    }
+
+    #[test]
+    fn stable_ids_survive_rebuild() {
+        let core_id =
+            CrateStableId { root_path: "core/lib.rs".into(), kind: SourceRootKind::Library };
+        let app_id = CrateStableId { root_path: "app/main.rs".into(), kind: SourceRootKind::Local };
+
+        let mut graph1 = CrateGraph::default();
+        let core1 = add_crate(&mut graph1, FileId(1));
+        graph1.set_stable_id(core1, core_id.clone());
+        let app1 = add_crate(&mut graph1, FileId(2));
+        graph1.set_stable_id(app1, app_id.clone());
+
+        let mut graph2 = CrateGraph::default();
+        let app2 = add_crate(&mut graph2, FileId(2));
+        graph2.set_stable_id(app2, app_id.clone());
+        let core2 = add_crate(&mut graph2, FileId(1));
+        graph2.set_stable_id(core2, core_id.clone());
+
+        assert_ne!(core1, core2);
+        assert_eq!(graph1.stable_id(core1), graph2.stable_id(core2));
+        for id in [core_id, app_id].iter() {
+            let crate1 = graph1.by_stable_id(id).unwrap();
+            let crate2 = graph2.by_stable_id(id).unwrap();
+            assert_eq!(graph1.crate_root(crate1), graph2.crate_root(crate2));
+        }
+        let unknown =
+            CrateStableId { root_path: "core/lib.rs".into(), kind: SourceRootKind::Local };
+        assert_eq!(graph1.by_stable_id(&unknown), None);
+    }
+
+    #[test]
+    fn iter_topological_puts_dependencies_first() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let crate3 = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(crate1, SmolStr::new("crate3"), crate3).is_ok());
+        assert!(graph.add_dep(crate3, SmolStr::new("crate2"), crate2).is_ok());
+        assert_eq!(graph.iter_topological().collect::<Vec<_>>(), vec![crate2, crate3, crate1]);
+    }
+
+    #[test]
+    fn serialization_order_ignores_insertion_order() {
+        let paths = ["core/lib.rs", "std/lib.rs", "app/main.rs", "z/lib.rs"];
+        let stable_id =
+            |path: &str| CrateStableId { root_path: path.into(), kind: SourceRootKind::Local };
+        let serialized = |graph: &CrateGraph| {
+            graph
+                .serialization_order()
+                .into_iter()
+                .map(|it| graph.stable_id(it).unwrap().root_path.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        let mut graph1 = CrateGraph::default();
+        let mut crates1 = Vec::new();
+        for (idx, path) in paths.iter().enumerate() {
+            let file_id = FileId(idx as u32);
+            let crate_id = add_crate(&mut graph1, file_id);
+            graph1.set_stable_id(crate_id, stable_id(path));
+            crates1.push(crate_id);
+        }
+        let (core, libstd, app) = (crates1[0], crates1[1], crates1[2]);
+        graph1.add_dep(libstd, SmolStr::new("core"), core).unwrap();
+        graph1.add_dep(app, SmolStr::new("core"), core).unwrap();
+        graph1.add_dep(app, SmolStr::new("std"), libstd).unwrap();
+
+        let mut graph2 = CrateGraph::default();
+        let mut crates2 = vec![CrateId(0); paths.len()];
+        for &idx in [2, 3, 1, 0].iter() {
+            let file_id = FileId(idx as u32);
+            let crate_id = add_crate(&mut graph2, file_id);
+            graph2.set_stable_id(crate_id, stable_id(paths[idx]));
+            crates2[idx] = crate_id;
+        }
+        let (core, libstd, app) = (crates2[0], crates2[1], crates2[2]);
+        graph2.add_dep(app, SmolStr::new("std"), libstd).unwrap();
+        graph2.add_dep(app, SmolStr::new("core"), core).unwrap();
+        graph2.add_dep(libstd, SmolStr::new("core"), core).unwrap();
+
+        assert_ne!(crates1, crates2);
+        let expected = vec!["core/lib.rs", "std/lib.rs", "app/main.rs", "z/lib.rs"];
+        assert_eq!(serialized(&graph1), expected);
+        assert_eq!(serialized(&graph2), expected);
+    }
+
+    #[test]
+    fn edit_distance_counts_edits() {
+        assert_eq!(edit_distance("foo", "foo"), 0);
+        assert_eq!(edit_distance("fooo", "foo"), 1);
+        assert_eq!(edit_distance("bar", "baz"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+        assert_eq!(edit_distance("kitten", "sitting"), 3);
+    }
+
+    #[test]
+    fn suggest_similar_module_files() {
+        let mut source_root = SourceRoot::new();
+        for (idx, path) in ["lib.rs", "foo.rs", "bar.rs", "baz/mod.rs", "sub/fooo.rs", "notes.txt"]
+            .iter()
+            .enumerate()
+        {
+            source_root.insert_file(RelativePathBuf::from(*path), FileId(idx as u32));
+        }
+        let suggest = |dir: &str, name: &str, max: usize| {
+            source_root
+                .suggest_similar(RelativePath::new(dir), name, max)
+                .into_iter()
+                .map(|it| it.to_string())
+                .collect::<Vec<_>>()
+        };
+
+        assert_eq!(suggest("", "fooo", 3), vec!["foo.rs"]);
+        assert_eq!(suggest("", "bax", 3), vec!["bar.rs", "baz/mod.rs"]);
+        assert_eq!(suggest("", "bax", 1), vec!["bar.rs"]);
+        assert_eq!(suggest("sub", "foo", 3), vec!["sub/fooo.rs"]);
+        assert!(suggest("", "notes", 3).is_empty());
+        assert!(suggest("", "xyz", 3).is_empty());
+    }
+
+    #[cfg(feature = "petgraph")]
+    #[test]
+    fn petgraph_reachability() {
+        let mut graph = CrateGraph::default();
+        let crate1 = add_crate(&mut graph, FileId(1));
+        let crate2 = add_crate(&mut graph, FileId(2));
+        let crate3 = add_crate(&mut graph, FileId(3));
+        let crate4 = add_crate(&mut graph, FileId(4));
         assert!(graph.add_dep(crate1, SmolStr::new("crate2"), crate2).is_ok());
         assert!(graph.add_dep(crate2, SmolStr::new("crate3"), crate3).is_ok());
+
+        let (petgraph, nodes) = graph.to_petgraph();
+        assert_eq!(petgraph.node_count(), 4);
+        assert_eq!(petgraph.edge_count(), 2);
+        assert_eq!(petgraph[nodes[&crate3]], crate3);
+        let reaches = |from: CrateId, to: CrateId| {
+            petgraph::algo::has_path_connecting(&petgraph, nodes[&from], nodes[&to], None)
+        };
+        assert!(reaches(crate1, crate3));
+        assert!(!reaches(crate3, crate1));
+        assert!(!reaches(crate1, crate4));
+    }
+
+    #[test]
+    fn leaves_have_no_dependencies() {
+        let mut graph = CrateGraph::default();
+        let core = add_crate(&mut graph, FileId(1));
+        let std = add_crate(&mut graph, FileId(2));
+        let lib = add_crate(&mut graph, FileId(3));
+        let bin = add_crate(&mut graph, FileId(4));
+        assert!(graph.add_dep(std, SmolStr::new("core"), core).is_ok());
+        assert!(graph.add_dep(lib, SmolStr::new("std"), std).is_ok());
+        assert!(graph.add_dep(bin, SmolStr::new("std"), std).is_ok());
+        assert!(graph.add_dep(bin, SmolStr::new("lib"), lib).is_ok());
+        assert_eq!(graph.leaves().collect::<Vec<_>>(), vec![core]);
+
+        graph.remove_crate(core);
+        assert_eq!(graph.leaves().collect::<Vec<_>>(), vec![std]);
+    }
+
+    #[test]
+    fn editions_in_use_are_deduplicated() {
+        let mut graph = CrateGraph::default();
+        assert!(graph.editions_in_use().is_empty());
+        add_crate(&mut graph, FileId(1));
+        graph.add_crate_root(
+            FileId(2u32),
+            Edition::Edition2015,
+            CfgOptions::default(),
+            Env::default(),
+        );
+        add_crate(&mut graph, FileId(3));
+        let expected: FxHashSet<_> = [Edition::Edition2015, Edition2018].iter().copied().collect();
+        assert_eq!(graph.editions_in_use(), expected);
+    }
+
+    #[test]
+    fn conflicting_editions_are_rejected() {
+        let mut graph = CrateGraph::default();
+        graph
+            .try_add_crate_root(
+                FileId(1u32),
+                Edition::Edition2015,
+                CfgOptions::default(),
+                Env::default(),
+            )
+            .unwrap();
+        let expected = ConflictingEditionError {
+            file_id: FileId(1u32),
+            existing: Edition::Edition2015,
+            new: Edition2018,
+        };
+        assert_eq!(
+            graph.try_add_crate_root(
+                FileId(1u32),
+                Edition2018,
+                CfgOptions::default(),
+                Env::default()
+            ),
+            Err(expected.clone())
+        );
+        // Same edition is fine, the crates might differ in cfg options.
+        assert!(graph
+            .try_add_crate_root(
+                FileId(1u32),
+                Edition::Edition2015,
+                CfgOptions::default(),
+                Env::default()
+            )
+            .is_ok());
+
+        let mut other = CrateGraph::default();
+        add_crate(&mut other, FileId(2));
+        add_crate(&mut other, FileId(1));
+        assert_eq!(graph.extend(other), Err(expected));
+        assert_eq!(graph.iter().count(), 2);
+
+        // The root is free again once its crates are removed.
+        for crate_id in graph.iter().collect::<Vec<_>>() {
+            graph.remove_crate(crate_id);
+        }
+        graph
+            .try_add_crate_root(FileId(1u32), Edition2018, CfgOptions::default(), Env::default())
+            .unwrap();
+        graph.renumber_by_stable_id();
+        assert_eq!(
+            graph.try_add_crate_root(
+                FileId(1u32),
+                Edition::Edition2015,
+                CfgOptions::default(),
+                Env::default()
+            ),
+            Err(ConflictingEditionError {
+                file_id: FileId(1u32),
+                existing: Edition2018,
+                new: Edition::Edition2015,
+            })
+        );
+        assert_eq!(graph.crate_id_for_crate_root(FileId(1u32)), Some(CrateId(0)));
+    }
+
+    #[test]
+    #[should_panic(expected = "registered with editions")]
+    fn add_crate_root_panics_on_conflicting_edition() {
+        let mut graph = CrateGraph::default();
+        add_crate(&mut graph, FileId(1));
+        graph.add_crate_root(
+            FileId(1u32),
+            Edition::Edition2015,
+            CfgOptions::default(),
+            Env::default(),
+        );
+    }
+
+    #[test]
+    fn source_root_diff() {
+        let mut old = SourceRoot::new();
+        old.insert_file("main.rs".into(), FileId(0u32));
+        old.insert_file("foo.rs".into(), FileId(1u32));
+        old.insert_file("bar.rs".into(), FileId(2u32));
+
+        let mut new = old.clone();
+        new.remove_file(RelativePath::new("foo.rs"));
+        new.insert_file("baz.rs".into(), FileId(3u32));
+        new.remove_file(RelativePath::new("bar.rs"));
+        new.insert_file("bar/mod.rs".into(), FileId(2u32));
+
+        let diff = old.diff(&new);
+        assert_eq!(
+            diff,
+            SourceRootDiff {
+                added: vec![FileId(3u32)],
+                removed: vec![FileId(1u32)],
+                moved: vec![(FileId(2u32), "bar.rs".into(), "bar/mod.rs".into())],
+            }
+        );
+        assert_eq!(old.diff(&old), SourceRootDiff::default());
+    }
+
+    #[test]
+    fn max_depth_follows_longest_chain() {
+        let mut graph = CrateGraph::default();
+        let a = add_crate(&mut graph, FileId(1));
+        let b = add_crate(&mut graph, FileId(2));
+        let c = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(a, SmolStr::new("b"), b).is_ok());
+        assert!(graph.add_dep(b, SmolStr::new("c"), c).is_ok());
+        assert!(graph.add_dep(a, SmolStr::new("c"), c).is_ok());
+        assert_eq!(graph.max_depth(a), 2);
+        assert_eq!(graph.max_depth(b), 1);
+        assert_eq!(graph.max_depth(c), 0);
+    }
+
+    #[test]
+    fn file_by_path_normalized_accepts_backslashes() {
+        let mut source_root = SourceRoot::new();
+        source_root.insert_file("src/lib.rs".into(), FileId(1u32));
+        assert_eq!(source_root.file_by_path_normalized("src/lib.rs"), Some(FileId(1u32)));
+        assert_eq!(source_root.file_by_path_normalized("src\\lib.rs"), Some(FileId(1u32)));
+        assert_eq!(source_root.file_by_path_normalized("src\\foo\\..\\lib.rs"), Some(FileId(1u32)));
+        assert_eq!(source_root.file_by_relative_path(RelativePath::new("src\\lib.rs")), None);
+    }
+
+    #[test]
+    fn semantically_eq_ignores_cosmetic_fields() {
+        let mut graph = CrateGraph::default();
+        let core = add_crate(&mut graph, FileId(1));
+        let std = add_crate(&mut graph, FileId(2));
+        assert!(graph.add_dep(std, SmolStr::new("core"), core).is_ok());
+
+        let mut refreshed = graph.clone();
+        refreshed.set_display_name(std, "std".to_string());
+        refreshed.set_version(std, "0.0.0".to_string());
+        refreshed.set_crate_priority(core, 1);
+        assert_ne!(graph, refreshed);
+        assert!(graph.semantically_eq(&refreshed));
+        assert_ne!(graph.display_data(), refreshed.display_data());
+        assert_eq!(refreshed.display_data().display_name(std), Some("std"));
+        assert_eq!(refreshed.display_data().version(std), Some("0.0.0"));
+        assert_eq!(refreshed.display_data().crate_priority(core), 1);
+
+        let mut other = refreshed.clone();
+        other.set_is_member(core, true);
+        assert!(!graph.semantically_eq(&other));
+
+        let mut cfg = CfgOptions::default();
+        cfg.insert_atom("test".into());
+        let mut other = CrateGraph::default();
+        let core = other.add_crate_root(FileId(1u32), Edition2018, cfg, Env::default());
+        let std = add_crate(&mut other, FileId(2));
+        assert!(other.add_dep(std, SmolStr::new("core"), core).is_ok());
+        assert!(!graph.semantically_eq(&other));
+    }
+
+    #[test]
+    fn crate_names_are_interned() {
+        let mut graph = CrateGraph::default();
+        let std1 = add_crate(&mut graph, FileId(1));
+        let std2 = add_crate(&mut graph, FileId(2));
+        let core = add_crate(&mut graph, FileId(3));
+        graph.set_display_name(std1, "std".to_string());
+        graph.set_display_name(std2, "std".to_string());
+        graph.set_display_name(core, "core".to_string());
+
+        assert!(graph.crate_name(std1).is_some());
+        assert_eq!(graph.crate_name(std1), graph.crate_name(std2));
+        assert_ne!(graph.crate_name(std1), graph.crate_name(core));
+        assert_eq!(graph.display_name(std2), Some("std"));
+
+        let mut other = CrateGraph::default();
+        let other_core = add_crate(&mut other, FileId(4));
+        other.set_display_name(other_core, "core".to_string());
+        let shift = graph.extend(other).unwrap();
+        assert_eq!(graph.crate_name(other_core.shift(shift)), graph.crate_name(core));
+    }
+
+    #[test]
+    fn equality_compares_display_names_by_text() {
+        let mut graph = CrateGraph::default();
+        let core = add_crate(&mut graph, FileId(1));
+        let std = add_crate(&mut graph, FileId(2));
+        let mut other = graph.clone();
+
+        graph.set_display_name(core, "core".to_string());
+        graph.set_display_name(std, "std".to_string());
+        other.set_display_name(std, "alloc".to_string());
+        other.set_display_name(std, "std".to_string());
+        other.set_display_name(core, "core".to_string());
+        assert_eq!(graph, other);
+
+        other.set_display_name(core, "std".to_string());
+        assert_ne!(graph, other);
+    }
+
+    #[test]
+    fn dependency_kinds_are_preserved() {
+        let mut graph = CrateGraph::default();
+        let app = add_crate(&mut graph, FileId(1));
+        let serde = add_crate(&mut graph, FileId(2));
+        let derive = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(app, SmolStr::new("serde"), serde).is_ok());
+        assert!(graph
+            .add_dep_with_kind(app, SmolStr::new("serde_derive"), derive, DependencyKind::ProcMacro)
+            .is_ok());
+        let kinds =
+            graph.dependencies(app).map(|dep| (dep.crate_id(), dep.kind())).collect::<Vec<_>>();
+        assert_eq!(
+            kinds,
+            vec![(serde, DependencyKind::Normal), (derive, DependencyKind::ProcMacro)]
+        );
+    }
+
+    #[test]
+    fn source_root_contains_path() {
+        let mut source_root = SourceRoot::new();
+        source_root.insert_file("src/lib.rs".into(), FileId(1u32));
+        assert!(source_root.contains_path(RelativePath::new("src/lib.rs")));
+        assert!(!source_root.contains_path(RelativePath::new("src/foo.rs")));
+        assert!(!source_root.contains_path(RelativePath::new("src")));
+    }
+
+    #[test]
+    fn graph_stats() {
+        let mut graph = CrateGraph::default();
+        let core = add_crate(&mut graph, FileId(1));
+        let std = add_crate(&mut graph, FileId(2));
+        let lib = add_crate(&mut graph, FileId(3));
+        let bin = add_crate(&mut graph, FileId(4));
+        add_crate(&mut graph, FileId(5));
+        assert!(graph.add_dep(std, SmolStr::new("core"), core).is_ok());
+        assert!(graph.add_dep(lib, SmolStr::new("std"), std).is_ok());
+        assert!(graph.add_dep(bin, SmolStr::new("std"), std).is_ok());
+        assert!(graph.add_dep(bin, SmolStr::new("lib"), lib).is_ok());
+        assert_eq!(graph.stats(), GraphStats { crates: 5, edges: 4, roots: 2, sinks: 2 });
+    }
+
+    #[test]
+    fn transitive_dependencies() {
+        let mut graph = CrateGraph::default();
+        let core = add_crate(&mut graph, FileId(1));
+        let std = add_crate(&mut graph, FileId(2));
+        let bin = add_crate(&mut graph, FileId(3));
+        assert!(graph.add_dep(std, SmolStr::new("core"), core).is_ok());
+        assert!(graph.add_dep(bin, SmolStr::new("std"), std).is_ok());
+        assert!(graph.add_dep(bin, SmolStr::new("core"), core).is_ok());
+        let expected: FxHashSet<_> = [std, core].iter().copied().collect();
+        assert_eq!(graph.transitive_dependencies(bin), expected);
+        assert!(graph.transitive_dependencies(core).is_empty());
+    }
+
+    #[test]
+    fn update_crate_keeps_ids() {
+        let mut graph = CrateGraph::default();
+        let bin = add_crate(&mut graph, FileId(1));
+        let lib = add_crate(&mut graph, FileId(2));
+        let util = add_crate(&mut graph, FileId(3));
+        graph.add_dep(bin, SmolStr::new("lib"), lib).unwrap();
+        graph.add_dep(lib, SmolStr::new("util"), util).unwrap();
+        graph.set_display_name(lib, "lib".to_string());
+
+        let mut cfg = CfgOptions::default();
+        cfg.insert_atom("unix".into());
+        let mut new = CrateData::new(FileId(2), Edition2015, cfg.clone(), Env::default());
+        new.add_dep(SmolStr::new("util2"), util, DependencyKind::Build);
+        graph.update_crate(lib, new).unwrap();
+
+        assert_eq!(graph.edition(lib), Edition2015);
+        assert_eq!(graph.cfg_options(lib), &cfg);
+        assert_eq!(graph.display_name(lib), Some("lib"));
+        let deps =
+            graph.dependencies(lib).map(|dep| (dep.name.clone(), dep.kind)).collect::<Vec<_>>();
+        assert_eq!(deps, vec![(SmolStr::new("util2"), DependencyKind::Build)]);
+        assert_eq!(graph.dependencies(bin).map(|dep| dep.crate_id).collect::<Vec<_>>(), vec![lib]);
+    }
+
+    #[test]
+    fn update_crate_rejects_bad_deps() {
+        let mut graph = CrateGraph::default();
+        let bin = add_crate(&mut graph, FileId(1));
+        let lib = add_crate(&mut graph, FileId(2));
+        graph.add_dep(bin, SmolStr::new("lib"), lib).unwrap();
+        let before = graph.clone();
+
+        let mut dangling =
+            CrateData::new(FileId(2), Edition2018, CfgOptions::default(), Env::default());
+        dangling.add_dep(SmolStr::new("ghost"), CrateId(92), DependencyKind::Normal);
+        assert_eq!(
+            graph.update_crate(lib, dangling),
+            Err(GraphError::DanglingDependency { from: lib, to: CrateId(92) })
+        );
+
+        let mut cyclic =
+            CrateData::new(FileId(2), Edition2015, CfgOptions::default(), Env::default());
+        cyclic.add_dep(SmolStr::new("bin"), bin, DependencyKind::Normal);
+        assert_eq!(
+            graph.update_crate(lib, cyclic),
+            Err(GraphError::Cycle { crates: vec![lib, bin] })
+        );
+        assert_eq!(graph, before);
+    }
+
+    #[test]
+    fn crate_lint_levels() {
+        let mut graph = CrateGraph::default();
+        let krate = add_crate(&mut graph, FileId(1));
+        assert_eq!(graph.crate_lint_level(krate, "warnings"), None);
+
+        graph.set_lint_level(krate, "warnings", LintLevel::Deny);
+        graph.set_lint_level(krate, "dead_code", LintLevel::Warn);
+        graph.set_lint_level(krate, "dead_code", LintLevel::Allow);
+        assert_eq!(graph.crate_lint_level(krate, "warnings"), Some(LintLevel::Deny));
+        assert_eq!(graph.crate_lint_level(krate, "dead_code"), Some(LintLevel::Allow));
+        assert_eq!(graph.crate_lint_level(krate, "missing_docs"), None);
+    }
+
+    #[test]
+    fn dominators_of_diamond() {
+        let mut graph = CrateGraph::default();
+        let mut add = |n| add_crate(&mut graph, FileId(n));
+        let (bin, a, b, c, d, e, unrelated) =
+            (add(1), add(2), add(3), add(4), add(5), add(6), add(7));
+        graph.add_dep(bin, SmolStr::new("a"), a).unwrap();
+        graph.add_dep(bin, SmolStr::new("b"), b).unwrap();
+        graph.add_dep(a, SmolStr::new("c"), c).unwrap();
+        graph.add_dep(b, SmolStr::new("c"), c).unwrap();
+        graph.add_dep(c, SmolStr::new("d"), d).unwrap();
+        graph.add_dep(a, SmolStr::new("e"), e).unwrap();
+        graph.add_dep(unrelated, SmolStr::new("d"), d).unwrap();
+
+        let dominators = graph.dominators(bin);
+        let mut dominators = dominators.into_iter().collect::<Vec<_>>();
+        dominators.sort();
+        assert_eq!(dominators, vec![(a, bin), (b, bin), (c, bin), (d, c), (e, a)]);
+        assert!(graph.dominators(d).is_empty());
+    }
+
+    #[test]
+    fn transitive_edge_count_matches_transitive_dependencies() {
+        let mut graph = CrateGraph::default();
+        let mut add = |n| add_crate(&mut graph, FileId(n));
+        let (bin, a, b, c, core) = (add(1), add(2), add(3), add(4), add(5));
+        graph.add_dep(bin, SmolStr::new("a"), a).unwrap();
+        graph.add_dep(bin, SmolStr::new("b"), b).unwrap();
+        graph.add_dep(a, SmolStr::new("c"), c).unwrap();
+        graph.add_dep(b, SmolStr::new("c"), c).unwrap();
+        graph.add_dep(c, SmolStr::new("core"), core).unwrap();
+        // bin: 4, a: 2, b: 2, c: 1, core: 0
+        assert_eq!(graph.transitive_edge_count(), 9);
+
+        add_dep_unchecked(&mut graph, c, "a", a);
+        let expected = graph
+            .iter()
+            .map(|crate_id| graph.transitive_dependencies(crate_id).len())
+            .sum::<usize>();
+        assert_eq!(graph.transitive_edge_count(), expected);
+        // bin: 4, a, b, c: 3 each, as `a` and `c` now depend on themselves.
+        assert_eq!(expected, 13);
+    }
+
+    #[test]
+    fn source_root_exclude() {
+        let mut source_root = SourceRoot::new();
+        source_root.insert_file("/src/lib.rs".into(), FileId(0));
+        source_root.insert_file("/target/debug/build/out.rs".into(), FileId(1));
+        source_root.insert_file("/target.rs".into(), FileId(2));
+        source_root.insert_exclude("/target".into());
+
+        assert!(source_root.is_excluded(RelativePath::new("/target/debug/build/out.rs")));
+        assert!(!source_root.is_excluded(RelativePath::new("/target.rs")));
+
+        let mut walked = source_root.walk().collect::<Vec<_>>();
+        walked.sort();
+        assert_eq!(walked, vec![FileId(0), FileId(2)]);
+
+        let excluded = RelativePath::new("/target/debug/build/out.rs");
+        assert_eq!(source_root.file_by_relative_path(excluded), None);
+        assert!(!source_root.contains_path(excluded));
+        assert_eq!(
+            source_root.file_by_relative_path(RelativePath::new("/target.rs")),
+            Some(FileId(2))
+        );
+    }
+
+    #[test]
+    fn reachability_matches_bfs_on_random_graph() {
+        // A fixed linear congruential generator, so that failures reproduce.
+        let mut seed = 0x2545_f491u64;
+        let mut next = |bound: u64| {
+            seed = seed.wrapping_mul(6_364_136_223_846_793_005).wrapping_add(1);
+            (seed >> 33) % bound
+        };
+
+        let mut graph = CrateGraph::default();
+        let crates = (0..100).map(|n| add_crate(&mut graph, FileId(n))).collect::<Vec<_>>();
+        for _ in 0..150 {
+            let from = crates[next(100) as usize];
+            let to = crates[next(100) as usize];
+            // Cycles must be handled too.
+            add_dep_unchecked(&mut graph, from, "dep", to);
+        }
+        assert!(graph.sccs().iter().any(|scc| scc.len() > 1));
+
+        let reachability = graph.reachability();
+        for &a in crates.iter() {
+            let mut reached = FxHashSet::default();
+            let mut queue = std::collections::VecDeque::new();
+            queue.push_back(a);
+            while let Some(crate_id) = queue.pop_front() {
+                for dep in graph.dependencies(crate_id) {
+                    if reached.insert(dep.crate_id) {
+                        queue.push_back(dep.crate_id);
+                    }
+                }
+            }
+            for &b in crates.iter() {
+                assert_eq!(reachability.can_reach(a, b), reached.contains(&b), "{:?} {:?}", a, b);
+            }
+        }
+        assert!(!reachability.can_reach(crates[0], CrateId(1000)));
+    }
+
+    #[test]
+    fn crate_kinds() {
+        let mut graph = CrateGraph::default();
+        let lib = add_crate(&mut graph, FileId(1));
+        let tool = add_crate(&mut graph, FileId(2));
+        assert_eq!(graph.crate_kinds(lib), &[CrateKind::Lib]);
+
+        let kinds = ["bin", "lib"].iter().map(|it| it.parse().unwrap()).collect::<Vec<_>>();
+        graph.set_crate_kinds(tool, kinds);
+        assert_eq!(graph.crate_kinds(tool), &[CrateKind::Bin, CrateKind::Lib]);
+        assert!(graph.crate_kinds(tool).contains(&CrateKind::Bin));
+        assert!(!graph.crate_kinds(lib).contains(&CrateKind::Bin));
+
+        assert!("executable".parse::<CrateKind>().is_err());
+    }
+
+    #[test]
+    fn crate_priority() {
+        let mut graph = CrateGraph::default();
+        let focused = add_crate(&mut graph, FileId(1));
+        let other = add_crate(&mut graph, FileId(2));
+        let before = graph.clone();
+        assert_eq!(graph.crate_priority(focused), 0);
+
+        graph.set_crate_priority(focused, 10);
+        graph.set_crate_priority(other, -1);
+        assert_eq!(graph.crate_priority(focused), 10);
+        assert_eq!(graph.crate_priority(other), -1);
+        assert!(graph.semantically_eq(&before));
+    }
+
+    #[test]
+    fn resolve_dep_name_uses_edge_names() {
+        let mut graph = CrateGraph::default();
+        let app = add_crate(&mut graph, FileId(1));
+        let serde_json = add_crate(&mut graph, FileId(2));
+        let log = add_crate(&mut graph, FileId(3));
+        graph.set_display_name(serde_json, "serde_json".to_string());
+        // `json = { package = "serde_json" }`
+        graph.add_dep(app, SmolStr::new("json"), serde_json).unwrap();
+        graph.add_dep(app, SmolStr::new("log"), log).unwrap();
+
+        assert_eq!(graph.resolve_dep_name(app, "json"), Some(serde_json));
+        assert_eq!(graph.resolve_dep_name(app, "log"), Some(log));
+        assert_eq!(graph.resolve_dep_name(app, "serde_json"), None);
+        assert_eq!(graph.resolve_dep_name(log, "json"), None);
+        assert_eq!(graph.resolve_dep_name(CrateId(92), "log"), None);
+    }
+
+    #[test]
+    fn transaction_commits_or_rolls_back() {
+        let mut graph = CrateGraph::default();
+        let core = add_crate(&mut graph, FileId(1));
+        let before = graph.clone();
+
+        let res = graph.transaction(|builder| {
+            let std = builder.add_crate_root(
+                FileId(2),
+                Edition2018,
+                CfgOptions::default(),
+                Env::default(),
+            );
+            builder.add_dep(std, SmolStr::new("core"), core);
+            Err(GraphError::DanglingDependency { from: std, to: CrateId(92) })
+        });
+        assert!(res.is_err());
+        assert_eq!(graph, before);
+
+        // `core` -> `std` -> `core`, caught once the closure is done.
+        let res: Result<(), GraphError> = graph.transaction(|builder| {
+            let std = builder.add_crate_root(
+                FileId(2),
+                Edition2018,
+                CfgOptions::default(),
+                Env::default(),
+            );
+            builder.add_dep(std, SmolStr::new("core"), core);
+            builder.add_dep(core, SmolStr::new("std"), std);
+            Ok(())
+        });
+        match res {
+            Err(GraphError::Cycle { .. }) => (),
+            _ => panic!("expected a cycle: {:?}", res),
+        }
+        assert_eq!(graph, before);
+
+        let res: Result<(), GraphError> = graph.transaction(|builder| {
+            builder.add_dep(core, SmolStr::new("alloc"), CrateId(92));
+            Ok(())
+        });
+        assert_eq!(res, Err(GraphError::DanglingDependency { from: core, to: CrateId(92) }));
+        assert_eq!(graph, before);
+
+        let mut added = None;
+        let res: Result<(), GraphError> = graph.transaction(|builder| {
+            let id = builder.add_crate_root(
+                FileId(2),
+                Edition2018,
+                CfgOptions::default(),
+                Env::default(),
+            );
+            builder.add_dep(id, SmolStr::new("core"), core);
+            builder.graph_mut().set_is_member(id, true);
+            added = Some(id);
+            Ok(())
+        });
+        assert!(res.is_ok());
+        let std = added.unwrap();
+        assert_eq!(graph.dependencies(std).map(|dep| dep.crate_id).collect::<Vec<_>>(), vec![core]);
+        assert!(graph.is_member(std));
+    }
+
+    #[test]
+    fn proc_macro_handle() {
+        let mut graph = CrateGraph::default();
+        let lib = add_crate(&mut graph, FileId(1));
+        let derive = add_crate(&mut graph, FileId(2));
+        graph.set_crate_kinds(derive, vec![CrateKind::ProcMacro]);
+        assert_eq!(graph.proc_macro_handle(derive), None);
+
+        let before = graph.clone();
+        graph.set_proc_macro_handle(derive, ProcMacroId(7));
+        assert_eq!(graph.proc_macro_handle(derive), Some(ProcMacroId(7)));
+        assert_eq!(graph.proc_macro_handle(lib), None);
+        assert!(!graph.semantically_eq(&before));
+
+        graph.set_crate_kinds(derive, vec![CrateKind::Lib]);
+        assert_eq!(graph.proc_macro_handle(derive), None);
+    }
+
+    #[test]
+    fn active_dependencies_need_the_feature() {
+        let mut graph = CrateGraph::default();
+        let app = add_crate(&mut graph, FileId(1));
+        let log = add_crate(&mut graph, FileId(2));
+        let serde = add_crate(&mut graph, FileId(3));
+        graph.add_dep(app, SmolStr::new("log"), log).unwrap();
+        graph.add_optional_dep(app, SmolStr::new("serde"), serde, SmolStr::new("serde1")).unwrap();
+        assert_eq!(graph.dependencies(app).filter(|dep| dep.is_optional()).count(), 1);
+
+        let active = |features: &[&str]| {
+            let features = features.iter().map(|it| SmolStr::new(*it)).collect::<FxHashSet<_>>();
+            graph.active_dependencies(app, &features).map(|dep| dep.crate_id).collect::<Vec<_>>()
+        };
+        assert_eq!(active(&[]), vec![log]);
+        assert_eq!(active(&["std"]), vec![log]);
+        assert_eq!(active(&["serde1"]), vec![log, serde]);
+    }
+
+    #[test]
+    fn source_root_common_prefix() {
+        let mut source_root = SourceRoot::new();
+        assert_eq!(source_root.common_prefix(), RelativePathBuf::from(""));
+        source_root.insert_file("src/lib.rs".into(), FileId(0));
+        assert_eq!(source_root.common_prefix(), RelativePathBuf::from("src"));
+        source_root.insert_file("src/foo/bar.rs".into(), FileId(1));
+        source_root.insert_file("src/foo.rs".into(), FileId(2));
+        assert_eq!(source_root.common_prefix(), RelativePathBuf::from("src"));
+        source_root.insert_file("target/out.rs".into(), FileId(3));
+        source_root.insert_exclude("target".into());
+        assert_eq!(source_root.common_prefix(), RelativePathBuf::from("src"));
+
+        let mut source_root = SourceRoot::new();
+        source_root.insert_file("src/lib.rs".into(), FileId(0));
+        source_root.insert_file("benches/bench.rs".into(), FileId(1));
+        assert_eq!(source_root.common_prefix(), RelativePathBuf::from(""));
+    }
+
+    #[test]
+    fn depends_on_is_transitive() {
+        let mut graph = CrateGraph::default();
+        let a = add_crate(&mut graph, FileId(1));
+        let b = add_crate(&mut graph, FileId(2));
+        let c = add_crate(&mut graph, FileId(3));
+        graph.add_dep(a, SmolStr::new("b"), b).unwrap();
+        graph.add_dep(b, SmolStr::new("c"), c).unwrap();
+
+        assert!(graph.depends_on(a, b));
+        assert!(graph.depends_on(a, c));
+        assert!(!graph.depends_on(c, a));
+        assert!(!graph.depends_on(a, a));
+        assert!(!graph.depends_on(a, CrateId(92)));
+    }
+
+    /// Adds the dependency without any checks. `add_dep` refuses to create
+    /// cycles and dangling edges, which graph algorithms must handle anyway.
+    fn add_dep_unchecked(graph: &mut CrateGraph, from: CrateId, name: &str, to: CrateId) {
+        graph.arena.get_mut(&from).unwrap().add_dep(SmolStr::new(name), to, DependencyKind::Normal);
     }
 }
@@ -1,16 +1,46 @@
 //! ra_db defines basic database traits. The concrete DB is defined by ra_ide.
 mod cancellation;
+mod disk_loader;
+mod initial_state;
 mod input;
+mod query_stats;
 pub mod fixture;
+#[cfg(test)]
+mod test_db;
+#[cfg(test)]
+mod tests;
 
-use std::{panic, sync::Arc};
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    panic,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
 
+use ra_cfg::CfgOptions;
 use ra_prof::profile;
-use ra_syntax::{ast, Parse, SourceFile, TextRange, TextUnit};
+use ra_syntax::{
+    algo, ast, AstNode, AstToken, Location, NodeOrToken, Parse, ParseError, ParserFeatures,
+    SmolStr, SourceFile, SyntaxKind, SyntaxNode, SyntaxToken, SyntaxTreeBuilder, TextRange,
+    TextUnit,
+};
+use ra_text_edit::{apply_edit_to_offset, AtomTextEdit};
+use rustc_hash::{FxHashMap, FxHashSet, FxHasher};
 
 pub use crate::{
-    cancellation::Canceled,
-    input::{CrateGraph, CrateId, Dependency, Edition, Env, FileId, SourceRoot, SourceRootId},
+    cancellation::{restore_panic_hook, set_quiet_panic_hook, Canceled, QueryGuard},
+    disk_loader::DiskFileTextLoader,
+    initial_state::InitialState,
+    input::{
+        ConflictingEditionError, CrateData, CrateDepMetrics, CrateDisplayData, CrateGraph,
+        CrateGraphBuilder, CrateId, CrateKind, CrateName, CrateOrigin, CrateStableId, Dependency,
+        DependencyKind, Edition, EditionedFileId, Env, FileId, GraphError, GraphStats, LintLevel,
+        ProcMacroId, Reachability, SourceRoot, SourceRootDiff, SourceRootId, SourceRootKind,
+        ANONYMOUS_SOURCE_ROOT, SYNTHETIC_SOURCE_ROOT,
+    },
+    query_stats::QueryStats,
 };
 pub use relative_path::{RelativePath, RelativePathBuf};
 pub use salsa;
@@ -45,40 +75,231 @@ pub trait CheckCanceled {
     /// it on the API boundary. Salsa explicitly supports this use-case.
     fn check_canceled(&self);
 
+    /// Returns a guard which checks for cancellation when created and when
+    /// dropped, to mark units of work inside of long custom queries.
+    fn query_guard(&self) -> QueryGuard<'_, Self>
+    where
+        Self: Sized,
+    {
+        QueryGuard::new(self)
+    }
+
+    /// Runs `f` with `check_canceled` turned into a no-op on the current
+    /// thread, for short steps which must not be left half-done, like updating
+    /// a cache consistently. Pending cancellation takes effect at the next
+    /// check after `f` returns.
+    fn uncancellable<T>(&self, f: impl FnOnce() -> T) -> T
+    where
+        Self: Sized,
+    {
+        cancellation::uncancellable(f)
+    }
+
     fn catch_canceled<F, T>(&self, f: F) -> Result<T, Canceled>
     where
         Self: Sized + panic::RefUnwindSafe,
         F: FnOnce(&Self) -> T + panic::UnwindSafe,
     {
-        panic::catch_unwind(|| f(self)).map_err(|err| match err.downcast::<Canceled>() {
-            Ok(canceled) => *canceled,
-            Err(payload) => panic::resume_unwind(payload),
-        })
+        cancellation::catch_canceled_unwind(|| f(self))
     }
 }
 
 impl<T: salsa::Database> CheckCanceled for T {
     fn check_canceled(&self) {
-        if self.salsa_runtime().is_current_revision_canceled() {
+        if self.salsa_runtime().is_current_revision_canceled() && !cancellation::is_uncancellable()
+        {
             Canceled::throw()
         }
     }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct FilePosition {
     pub file_id: FileId,
     pub offset: TextUnit,
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 pub struct FileRange {
     pub file_id: FileId,
     pub range: TextRange,
 }
 
+/// What name resolution needs to know about the file it starts in, as
+/// computed by `SourceDatabaseExt::resolution_context`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ResolutionContext {
+    pub crate_id: CrateId,
+    pub edition: Edition,
+    pub source_root: SourceRootId,
+    pub is_library: bool,
+}
+
+/// How a file indents its code, as guessed by
+/// `SourceDatabase::file_indent_style`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum IndentStyle {
+    Tabs,
+    /// The number of spaces per indentation level.
+    Spaces(u8),
+}
+
+impl Default for IndentStyle {
+    fn default() -> IndentStyle {
+        IndentStyle::Spaces(4)
+    }
+}
+
+/// Whether the text of a file is available, see
+/// `SourceDatabaseExt::file_load_status`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum FileLoadStatus {
+    Loaded,
+    /// The file is there, but its text is empty.
+    Empty,
+    /// The text of the file was never loaded, because its loader doesn't have
+    /// it, for example because the file was removed from disk.
+    Missing,
+}
+
+impl FileRange {
+    /// The range between two positions, or `None` if they are in different
+    /// files or `end` comes before `start`.
+    pub fn from_positions(start: FilePosition, end: FilePosition) -> Option<FileRange> {
+        if start.file_id != end.file_id || end.offset < start.offset {
+            return None;
+        }
+        Some(FileRange {
+            file_id: start.file_id,
+            range: TextRange::from_to(start.offset, end.offset),
+        })
+    }
+
+    /// Where the range ends up after the edit is applied to its file, moving
+    /// both ends with `apply_edit_to_offset`: an insertion at the start of the
+    /// range ends up inside it, one at the end outside of it. `None` if the
+    /// range is inside the deleted text, or starts or ends in it. Edits of
+    /// other files are not checked for.
+    pub fn rebase(self, edit: &AtomTextEdit) -> Option<FileRange> {
+        let deleted = edit.delete;
+        let in_deleted = |offset: TextUnit| deleted.start() < offset && offset < deleted.end();
+        let (start, end) = (self.range.start(), self.range.end());
+        if in_deleted(start) || in_deleted(end) || (!deleted.is_empty() && self.range == deleted) {
+            return None;
+        }
+        let range =
+            TextRange::from_to(apply_edit_to_offset(start, edit), apply_edit_to_offset(end, edit));
+        Some(FileRange { file_id: self.file_id, range })
+    }
+}
+
 pub const DEFAULT_LRU_CAP: usize = 128;
 
+/// Provides the text of files on first access, for embedders which don't want
+/// to eagerly `set_file_text` every file.
+pub trait FileTextLoader: Send + Sync {
+    fn load_text(&self, file_id: FileId) -> String;
+
+    /// Whether the loader has no text for the file, so `load_text` can only
+    /// return an empty string. Unlike `load_text`, this isn't cached.
+    fn is_missing(&self, _file_id: FileId) -> bool {
+        false
+    }
+}
+
+/// Shared handle to a `FileTextLoader`. Handles are equal if they point to
+/// the same loader.
+#[derive(Clone)]
+pub struct FileTextLoaderHandle(pub Arc<dyn FileTextLoader>);
+
+impl PartialEq for FileTextLoaderHandle {
+    fn eq(&self, other: &FileTextLoaderHandle) -> bool {
+        let this = &*self.0 as *const dyn FileTextLoader as *const u8;
+        let other = &*other.0 as *const dyn FileTextLoader as *const u8;
+        this == other
+    }
+}
+
+impl Eq for FileTextLoaderHandle {}
+
+impl fmt::Debug for FileTextLoaderHandle {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("FileTextLoaderHandle")
+    }
+}
+
+/// Wall time of the last parse of each file, written by `parse` behind salsa's
+/// back. Tables are equal if they are the same table, so the durations never
+/// invalidate anything.
+#[derive(Clone)]
+pub struct ParseTimings(Option<Arc<Mutex<FxHashMap<FileId, Duration>>>>);
+
+impl Default for ParseTimings {
+    fn default() -> ParseTimings {
+        ParseTimings(Some(Default::default()))
+    }
+}
+
+impl ParseTimings {
+    /// A table which records nothing, for targets without a clock, like wasm.
+    pub fn disabled() -> ParseTimings {
+        ParseTimings(None)
+    }
+
+    fn time<T>(&self, file_id: FileId, f: impl FnOnce() -> T) -> T {
+        let table = match &self.0 {
+            Some(it) => it,
+            None => return f(),
+        };
+        let start = Instant::now();
+        let res = f();
+        let duration = start.elapsed();
+        table.lock().unwrap().insert(file_id, duration);
+        res
+    }
+
+    fn get(&self, file_id: FileId) -> Option<Duration> {
+        self.0.as_ref()?.lock().unwrap().get(&file_id).copied()
+    }
+}
+
+impl PartialEq for ParseTimings {
+    fn eq(&self, other: &ParseTimings) -> bool {
+        match (&self.0, &other.0) {
+            (Some(this), Some(other)) => Arc::ptr_eq(this, other),
+            (None, None) => true,
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ParseTimings {}
+
+impl fmt::Debug for ParseTimings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ParseTimings")
+    }
+}
+
+/// A count of the changes noted by `record_file_text_changes`. Unlike salsa's
+/// own revisions, this only moves when some file text changes.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FileTextRevision(u64);
+
+/// The change in which the text of each file was last changed.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FileTextRevisions {
+    current: FileTextRevision,
+    changed_at: FxHashMap<FileId, FileTextRevision>,
+}
+
+impl FileTextRevisions {
+    /// The revision of the last noted change.
+    pub fn current(&self) -> FileTextRevision {
+        self.current
+    }
+}
+
 pub trait FileLoader {
     /// Text of the file.
     fn file_text(&self, file_id: FileId) -> Arc<String>;
@@ -95,15 +316,732 @@ pub trait SourceDatabase: CheckCanceled + FileLoader + std::fmt::Debug {
     #[salsa::invoke(parse_query)]
     fn parse(&self, file_id: FileId) -> Parse<ast::SourceFile>;
 
+    /// Files whose trees must survive the LRU of `parse`, see `pin_files`.
+    #[salsa::input]
+    fn pinned_files(&self) -> Arc<FxHashSet<FileId>>;
+
+    /// Whether the file is in `pinned_files`. Unlike `pinned_files`, this
+    /// doesn't change when other files are pinned.
+    fn file_is_pinned(&self, file_id: FileId) -> bool;
+
+    /// The tree of a pinned file. `parse` delegates to it for pinned files,
+    /// and it has no LRU, so the tree is not recomputed when `parse` is
+    /// evicted. The garbage collection of the IDE doesn't sweep it either, so
+    /// the last tree of a file stays around after the file is unpinned.
+    fn pinned_parse(&self, file_id: FileId) -> Parse<ast::SourceFile>;
+
+    /// Parses the files in order, checking for cancellation before each one,
+    /// so that a bulk parse doesn't block changes. Files after the point of
+    /// cancellation are not parsed.
+    #[salsa::transparent]
+    fn parse_all(&self, files: &[FileId]) -> Result<Vec<Parse<ast::SourceFile>>, Canceled>;
+
+    /// Root node of the tree of `parse`, for callers which don't care about
+    /// the errors or the typed `SourceFile`.
+    #[salsa::transparent]
+    fn parse_root(&self, file_id: FileId) -> SyntaxNode;
+
+    /// Unstable syntax accepted by `parse`.
+    #[salsa::input]
+    fn parser_features(&self) -> ParserFeatures;
+
+    /// Where `parse` records how long it took, see `last_parse_duration`.
+    #[salsa::input]
+    fn parse_timings(&self) -> ParseTimings;
+
+    /// Wall time of the last execution of `parse` for the file, including the
+    /// loading of its text if that's lazy. `None` if the file wasn't parsed
+    /// since `parse_timings` was set, or if the table is disabled. Look at it
+    /// together with `file_text_len` to tell slow files from large ones.
+    #[salsa::transparent]
+    fn last_parse_duration(&self, file_id: FileId) -> Option<Duration>;
+
+    /// Range of the syntax error which starts earliest in the file. Errors
+    /// reported at an offset are represented by an empty range.
+    fn first_parse_error(&self, file_id: FileId) -> Option<TextRange>;
+
+    /// The smallest node of the (cached) syntax tree which covers the range.
+    #[salsa::transparent]
+    fn parse_subtree(&self, frange: FileRange) -> Option<SyntaxNode>;
+
+    /// Range of the innermost item, like a function or an impl, which contains
+    /// the position. `None` if the position is outside of any item, like in
+    /// the whitespace between two of them.
+    #[salsa::transparent]
+    fn enclosing_item_range(&self, position: FilePosition) -> Option<FileRange>;
+
+    /// The token under the cursor. When the position is at the boundary of two
+    /// tokens, it's the one on the left, which ends at the position, as the
+    /// cursor usually sits right after what was just typed. At the very start
+    /// of the file that's the first token. `None` for an empty file or a
+    /// position past its end.
+    #[salsa::transparent]
+    fn token_at(&self, position: FilePosition) -> Option<SyntaxToken>;
+
+    /// The file of the module at `mod_path` within the crate, found by the
+    /// `foo.rs` / `foo/mod.rs` convention alone: `#[path]` attributes and
+    /// inline modules are not taken into account.
+    #[salsa::transparent]
+    fn module_file(&self, crate_id: CrateId, mod_path: &[SmolStr]) -> Option<FileId>;
+
+    /// The file of a `#[path = "..."] mod foo;` declared in `anchor`. Like
+    /// rustc, the path is relative to the directory of `anchor`, whatever
+    /// the name of the file; `..` is allowed.
+    #[salsa::transparent]
+    fn resolve_path_attr_mod(&self, anchor: FileId, explicit_path: &str) -> Option<FileId>;
+
+    /// Files reachable from the crate root through `mod foo;` declarations,
+    /// resolved like `module_file` does it. This is purely syntactic, so
+    /// modules behind an inactive `#[cfg]` are included.
+    fn crate_reachable_files(&self, crate_id: CrateId) -> Arc<FxHashSet<FileId>>;
+
+    /// Length of the file's text in bytes. Queries which only care about the
+    /// size of the file should depend on this rather than on the text itself.
+    fn file_text_len(&self, file_id: FileId) -> usize;
+
+    /// Whether the file's text ends with `\n`. An empty file doesn't.
+    fn file_ends_with_newline(&self, file_id: FileId) -> bool;
+
+    /// Whether the file has both `\n` and `\r\n` line endings.
+    fn mixed_line_endings(&self, file_id: FileId) -> bool;
+
+    /// Whether the file's text is all ASCII, so that byte offsets, char
+    /// offsets and utf-16 columns coincide.
+    fn file_is_ascii(&self, file_id: FileId) -> bool;
+
+    /// Indentation of the file, for edits which insert new code. It's guessed
+    /// from the first indented lines, falling back to the default of four
+    /// spaces if there are none or tabs and spaces are used as much.
+    fn file_indent_style(&self, file_id: FileId) -> IndentStyle;
+
+    /// Approximate number of bytes taken by the green tree of the file, for
+    /// tuning the LRU capacity of `parse`.
+    fn parse_tree_size(&self, file_id: FileId) -> usize;
+
+    /// Range of the shebang line and of the inner doc comments, `//!` or `/*!`,
+    /// at the very start of the file, up to the first blank line. `None` if
+    /// the file doesn't start with either.
+    fn file_header_range(&self, file_id: FileId) -> Option<FileRange>;
+
+    /// Ranges of all the comments of the file, doc comments included, in
+    /// order, together with their shape.
+    fn file_comment_ranges(&self, file_id: FileId) -> Arc<Vec<(FileRange, ast::CommentShape)>>;
+
     /// The crate graph.
     #[salsa::input]
     fn crate_graph(&self) -> Arc<CrateGraph>;
+
+    /// Display names, versions and priorities of the crates. They are set
+    /// apart from `crate_graph`, which is kept if a new graph differs from it
+    /// only in these.
+    #[salsa::input]
+    fn crate_display_data(&self) -> Arc<CrateDisplayData>;
+
+    /// Whether the file is the root module of some crate.
+    #[salsa::transparent]
+    fn is_crate_root(&self, file_id: FileId) -> bool;
+
+    /// All of the cfg options the crate could be compiled with, for toggling
+    /// features. Falls back to the active options.
+    fn crate_potential_cfg(&self, crate_id: CrateId) -> Arc<CfgOptions>;
+
+    /// Whether the crate is analyzed with `cfg(test)` enabled.
+    fn crate_cfg_test(&self, crate_id: CrateId) -> bool;
+
+    /// The crate the file is analyzed as a part of: the one with the smallest
+    /// id among the `relevant_crates` whose module tree contains the file, or
+    /// among all of the `relevant_crates` if none does.
+    fn file_crate(&self, file_id: FileId) -> Option<CrateId>;
+
+    /// The cfg options `#[cfg]`s in the file are evaluated against: those of
+    /// the `file_crate`, with `test` added if the crate is analyzed with
+    /// `cfg(test)`. Empty if the file belongs to no crate.
+    fn effective_cfg(&self, file_id: FileId) -> Arc<CfgOptions>;
+
+    /// Number of direct and transitive dependencies of the crate, and the
+    /// length of its longest dependency chain.
+    fn crate_dep_metrics(&self, crate_id: CrateId) -> CrateDepMetrics;
+
+    /// Name by which the crate is referred to in `use` and `extern crate`:
+    /// the display name with `-` replaced by `_`, so `serde-json` becomes
+    /// `serde_json`. `None` if the crate has no display name.
+    fn crate_import_name(&self, crate_id: CrateId) -> Option<SmolStr>;
+
+    /// The dependency which provides the implicit prelude of the crate: `core`
+    /// for `#![no_std]` crates and `std` otherwise, falling back to `core` if
+    /// there's no `std`. The prelude crate is the same in every edition, only
+    /// the contents of the prelude differ.
+    fn prelude_crate(&self, crate_id: CrateId) -> Option<CrateId>;
+
+    /// Whether the crate root has `#![no_std]`, or `#![no_core]`, which
+    /// implies it.
+    fn crate_is_no_std(&self, crate_id: CrateId) -> bool;
+
+    /// Crates which are neither lang crates nor `#![no_std]`, but don't depend
+    /// on the `std` of the sysroot (a lang crate with the display name `std`),
+    /// sorted by id. This points to a broken project model, like a sysroot
+    /// which failed to load.
+    fn crates_missing_std(&self) -> Arc<Vec<CrateId>>;
+
+    /// Distinct editions of all the crates in the crate graph.
+    fn project_editions(&self) -> Arc<FxHashSet<Edition>>;
+
+    /// Edition of the crate, guessing it from the `CrateGraph::origin` of the
+    /// crate if the project model doesn't know it (see
+    /// `CrateGraph::mark_edition_unknown`):
+    ///
+    /// * a known edition is always used as is,
+    /// * `Lang` crates get the latest edition, as the sysroot is kept up to
+    ///   date with the toolchain,
+    /// * `Member` crates get 2018, the edition `cargo new` writes into new
+    ///   manifests,
+    /// * `Dependency` crates get 2015, which is what Cargo assumes for a
+    ///   manifest without an `edition` key, as is the case for the older
+    ///   crates on crates.io.
+    fn inferred_edition(&self, crate_id: CrateId) -> Edition;
+
+    /// Every dependency edge of the crate graph as `(from, to, name)`, sorted
+    /// by `from`, and in the order the dependencies were added within a crate.
+    fn all_dependency_edges(&self) -> Arc<Vec<(CrateId, CrateId, SmolStr)>>;
+
+    /// Crates of the graph grouped by `CrateGraph::origin`, each group sorted
+    /// by id. Origins without crates are left out.
+    fn crates_by_origin(&self) -> Arc<FxHashMap<CrateOrigin, Vec<CrateId>>>;
+
+    /// `CrateGraph::reachability`, rebuilt only when the crate graph changes.
+    fn crate_reachability(&self) -> Arc<Reachability>;
+
+    /// Member crates, together with everything they transitively depend on,
+    /// whether through other members or through dependencies.
+    fn crates_reachable_from_workspace(&self) -> Arc<FxHashSet<CrateId>>;
+
+    /// Whether the crate is a member crate or a dependency of one, directly or
+    /// not. Crates for which this is `false` are never used by the workspace,
+    /// like a dependency of a crate which is no longer a member.
+    #[salsa::transparent]
+    fn is_reachable_from_workspace(&self, crate_id: CrateId) -> bool;
+
+    /// Edition of the crate rooted at the file or, failing that, of the first of
+    /// its relevant crates. Files outside of any crate are assumed to be 2018.
+    #[salsa::transparent]
+    fn file_edition(&self, file_id: FileId) -> Edition;
+
+    #[salsa::transparent]
+    fn editioned(&self, file_id: FileId) -> EditionedFileId;
 }
 
 fn parse_query(db: &impl SourceDatabase, file_id: FileId) -> Parse<ast::SourceFile> {
+    if db.file_is_pinned(file_id) {
+        return db.pinned_parse(file_id);
+    }
+    parse_file(db, file_id)
+}
+
+fn last_parse_duration(db: &impl SourceDatabase, file_id: FileId) -> Option<Duration> {
+    db.parse_timings().get(file_id)
+}
+
+fn parse_root(db: &impl SourceDatabase, file_id: FileId) -> SyntaxNode {
+    db.parse(file_id).syntax_node()
+}
+
+fn file_is_pinned(db: &impl SourceDatabase, file_id: FileId) -> bool {
+    db.pinned_files().contains(&file_id)
+}
+
+fn pinned_parse(db: &impl SourceDatabase, file_id: FileId) -> Parse<ast::SourceFile> {
+    parse_file(db, file_id)
+}
+
+fn parse_file(db: &impl SourceDatabase, file_id: FileId) -> Parse<ast::SourceFile> {
     let _p = profile("parse_query");
+    db.parse_timings().time(file_id, || {
+        let text = db.file_text(file_id);
+        let features = db.parser_features();
+        parse_or_recover(&text, || SourceFile::parse_with_features(&*text, features))
+    })
+}
+
+fn parse_all(
+    db: &impl SourceDatabase,
+    files: &[FileId],
+) -> Result<Vec<Parse<ast::SourceFile>>, Canceled> {
+    let mut res = Vec::with_capacity(files.len());
+    for &file_id in files {
+        cancellation::catch_canceled_unwind(panic::AssertUnwindSafe(|| db.check_canceled()))?;
+        res.push(db.parse(file_id));
+    }
+    Ok(res)
+}
+
+/// Runs `parse`, turning a panic into a parse with a single error token which
+/// covers the whole text, so that one file which trips up the parser doesn't
+/// take down the analysis of its crate. Cancellation is propagated as usual.
+fn parse_or_recover(
+    text: &str,
+    parse: impl FnOnce() -> Parse<ast::SourceFile> + panic::UnwindSafe,
+) -> Parse<ast::SourceFile> {
+    let payload = match panic::catch_unwind(parse) {
+        Ok(parse) => return parse,
+        Err(payload) => payload,
+    };
+    if payload.is::<Canceled>() {
+        panic::resume_unwind(payload);
+    }
+    let mut builder = SyntaxTreeBuilder::default();
+    builder.start_node(SyntaxKind::SOURCE_FILE);
+    if !text.is_empty() {
+        builder.token(SyntaxKind::ERROR, text.into());
+    }
+    builder.finish_node();
+    builder.error(ParseError("the parser crashed on this file".to_string()), 0.into());
+    builder.finish().cast().unwrap()
+}
+
+fn first_parse_error(db: &impl SourceDatabase, file_id: FileId) -> Option<TextRange> {
+    let parse = db.parse(file_id);
+    parse
+        .errors()
+        .iter()
+        .map(|error| match error.location() {
+            Location::Offset(offset) => TextRange::offset_len(offset, 0.into()),
+            Location::Range(range) => range,
+        })
+        .min_by_key(|range| range.start())
+}
+
+fn parse_subtree(db: &impl SourceDatabase, frange: FileRange) -> Option<SyntaxNode> {
+    let root = db.parse(frange.file_id).syntax_node();
+    if !frange.range.is_subrange(&root.text_range()) {
+        return None;
+    }
+    let node = match algo::find_covering_element(&root, frange.range) {
+        NodeOrToken::Node(node) => node,
+        NodeOrToken::Token(token) => token.parent(),
+    };
+    Some(node)
+}
+
+fn enclosing_item_range(db: &impl SourceDatabase, position: FilePosition) -> Option<FileRange> {
+    let root = db.parse(position.file_id).syntax_node();
+    if position.offset > root.text_range().end() {
+        return None;
+    }
+    // At the boundary of two tokens, either of them may be in the item.
+    root.token_at_offset(position.offset)
+        .filter_map(|token| {
+            token.parent().ancestors().find(|node| ast::ModuleItem::can_cast(node.kind()))
+        })
+        .min_by_key(|node| node.text_range().len())
+        .map(|node| FileRange { file_id: position.file_id, range: node.text_range() })
+}
+
+fn token_at(db: &impl SourceDatabase, position: FilePosition) -> Option<SyntaxToken> {
+    let root = db.parse(position.file_id).syntax_node();
+    if position.offset > root.text_range().end() {
+        return None;
+    }
+    root.token_at_offset(position.offset).left_biased()
+}
+
+fn file_text_len(db: &impl SourceDatabase, file_id: FileId) -> usize {
+    db.file_text(file_id).len()
+}
+
+fn file_ends_with_newline(db: &impl SourceDatabase, file_id: FileId) -> bool {
+    db.file_text(file_id).ends_with('\n')
+}
+
+fn file_is_ascii(db: &impl SourceDatabase, file_id: FileId) -> bool {
+    db.file_text(file_id).is_ascii()
+}
+
+fn file_indent_style(db: &impl SourceDatabase, file_id: FileId) -> IndentStyle {
+    const SAMPLE: usize = 32;
     let text = db.file_text(file_id);
-    SourceFile::parse(&*text)
+    let (mut tabs, mut spaces) = (0, 0);
+    let mut width = None;
+    for line in text.lines() {
+        let content = line.trim_start();
+        // Blank lines, and lines of block comments aligned on their `*`.
+        if content.is_empty() || content.starts_with('*') || content.len() == line.len() {
+            continue;
+        }
+        if line.starts_with('\t') {
+            tabs += 1;
+        } else {
+            spaces += 1;
+            let indent = line.len() - content.len();
+            width = Some(width.map_or(indent, |it: usize| it.min(indent)));
+        }
+        if tabs + spaces == SAMPLE {
+            break;
+        }
+    }
+    if tabs > spaces {
+        return IndentStyle::Tabs;
+    }
+    match width {
+        Some(width) if spaces > tabs && width <= 8 => IndentStyle::Spaces(width as u8),
+        _ => IndentStyle::default(),
+    }
+}
+
+fn mixed_line_endings(db: &impl SourceDatabase, file_id: FileId) -> bool {
+    let text = db.file_text(file_id);
+    let (mut lf, mut crlf) = (false, false);
+    let mut prev = None;
+    for b in text.bytes() {
+        if b == b'\n' {
+            if prev == Some(b'\r') {
+                crlf = true;
+            } else {
+                lf = true;
+            }
+            if lf && crlf {
+                return true;
+            }
+        }
+        prev = Some(b);
+    }
+    false
+}
+
+fn parse_tree_size(db: &impl SourceDatabase, file_id: FileId) -> usize {
+    // Mirrors the layout of rowan's green tree: nodes and tokens live behind
+    // an `Arc`, nodes store their children as a boxed slice of `Arc`s, and
+    // token text is a `SmolStr`, which only allocates when it's long.
+    const ARC_HEADER: usize = 2 * std::mem::size_of::<usize>();
+    const CHILD: usize = 2 * std::mem::size_of::<usize>();
+    const INLINE_TEXT: usize = 22;
+
+    let parse = db.parse(file_id);
+    parse
+        .tree()
+        .syntax()
+        .descendants_with_tokens()
+        .map(|element| match element {
+            NodeOrToken::Node(node) => {
+                ARC_HEADER
+                    + std::mem::size_of::<(SyntaxKind, TextUnit, usize)>()
+                    + node.children_with_tokens().count() * CHILD
+            }
+            NodeOrToken::Token(token) => {
+                let text_len = token.text().len();
+                ARC_HEADER
+                    + std::mem::size_of::<(SyntaxKind, SmolStr)>()
+                    + if text_len > INLINE_TEXT { text_len } else { 0 }
+            }
+        })
+        .sum()
+}
+
+fn file_header_range(db: &impl SourceDatabase, file_id: FileId) -> Option<FileRange> {
+    let parse = db.parse(file_id);
+    // Leading comments are attached to the first item, so the tokens are
+    // walked rather than the children of the file.
+    let tokens = parse.tree().syntax().descendants_with_tokens().filter_map(|it| it.into_token());
+    let mut end = None;
+    for token in tokens {
+        let is_header = match token.kind() {
+            SyntaxKind::SHEBANG => true,
+            SyntaxKind::COMMENT => {
+                ast::Comment::cast(token.clone()).and_then(|it| it.kind().doc)
+                    == Some(ast::CommentPlacement::Inner)
+            }
+            // A blank line ends the header. Whitespace at the very start of
+            // the file means there's no header at all.
+            SyntaxKind::WHITESPACE if end.is_some() && token.text().matches('\n').count() < 2 => {
+                continue
+            }
+            _ => false,
+        };
+        if !is_header {
+            break;
+        }
+        // Line comments keep the `\r` of a `\r\n` line ending.
+        end = Some(token.text_range().start() + TextUnit::of_str(token.text().trim_end()));
+    }
+    Some(FileRange { file_id, range: TextRange::from_to(0.into(), end?) })
+}
+
+fn file_comment_ranges(
+    db: &impl SourceDatabase,
+    file_id: FileId,
+) -> Arc<Vec<(FileRange, ast::CommentShape)>> {
+    let res = db
+        .parse(file_id)
+        .tree()
+        .syntax()
+        .descendants_with_tokens()
+        .filter_map(|it| it.into_token())
+        .filter_map(ast::Comment::cast)
+        .map(|comment| {
+            let range = comment.syntax().text_range();
+            (FileRange { file_id, range }, comment.kind().shape)
+        })
+        .collect();
+    Arc::new(res)
+}
+
+fn is_crate_root(db: &impl SourceDatabase, file_id: FileId) -> bool {
+    db.crate_graph().crate_id_for_crate_root(file_id).is_some()
+}
+
+fn crate_potential_cfg(db: &impl SourceDatabase, crate_id: CrateId) -> Arc<CfgOptions> {
+    Arc::new(db.crate_graph().potential_cfg(crate_id).clone())
+}
+
+fn file_crate(db: &impl SourceDatabase, file_id: FileId) -> Option<CrateId> {
+    let candidates = db.relevant_crates(file_id);
+    candidates
+        .iter()
+        .copied()
+        .filter(|&crate_id| db.crate_reachable_files(crate_id).contains(&file_id))
+        .min()
+        .or_else(|| candidates.iter().copied().min())
+}
+
+fn effective_cfg(db: &impl SourceDatabase, file_id: FileId) -> Arc<CfgOptions> {
+    let crate_id = match db.file_crate(file_id) {
+        Some(it) => it,
+        None => return Default::default(),
+    };
+    let graph = db.crate_graph();
+    let mut res = graph.cfg_options(crate_id).clone();
+    if graph.cfg_test(crate_id) {
+        res.insert_atom("test".into());
+    }
+    Arc::new(res)
+}
+
+fn crate_cfg_test(db: &impl SourceDatabase, crate_id: CrateId) -> bool {
+    db.crate_graph().cfg_test(crate_id)
+}
+
+fn crate_import_name(db: &impl SourceDatabase, crate_id: CrateId) -> Option<SmolStr> {
+    let name = db.crate_display_data().display_name(crate_id)?.replace('-', "_");
+    Some(name.into())
+}
+
+fn crate_dep_metrics(db: &impl SourceDatabase, crate_id: CrateId) -> CrateDepMetrics {
+    let graph = db.crate_graph();
+    CrateDepMetrics {
+        direct: graph.dependencies(crate_id).count(),
+        transitive: graph.transitive_dependencies(crate_id).len(),
+        max_depth: graph.max_depth(crate_id),
+    }
+}
+
+fn crate_has_inner_attr(db: &impl SourceDatabase, crate_id: CrateId, name: &str) -> bool {
+    let root = db.parse(db.crate_graph().crate_root(crate_id)).tree();
+    // Attributes directly in the file are the inner ones, i.e. the crate's.
+    root.syntax()
+        .children()
+        .filter_map(ast::Attr::cast)
+        .any(|attr| attr.as_simple_atom().map_or(false, |it| it.as_str() == name))
+}
+
+fn prelude_crate(db: &impl SourceDatabase, crate_id: CrateId) -> Option<CrateId> {
+    let graph = db.crate_graph();
+    let dep = |name: &str| {
+        graph.dependencies(crate_id).find(|dep| dep.name.as_str() == name).map(|dep| dep.crate_id)
+    };
+    if crate_has_inner_attr(db, crate_id, "no_core") {
+        return None;
+    }
+    if crate_has_inner_attr(db, crate_id, "no_std") {
+        return dep("core");
+    }
+    dep("std").or_else(|| dep("core"))
+}
+
+fn crate_is_no_std(db: &impl SourceDatabase, crate_id: CrateId) -> bool {
+    crate_has_inner_attr(db, crate_id, "no_std") || crate_has_inner_attr(db, crate_id, "no_core")
+}
+
+fn crates_missing_std(db: &impl SourceDatabase) -> Arc<Vec<CrateId>> {
+    let graph = db.crate_graph();
+    let display_data = db.crate_display_data();
+    let is_sysroot_std = |crate_id: CrateId| {
+        graph.origin(crate_id) == CrateOrigin::Lang
+            && display_data.display_name(crate_id) == Some("std")
+    };
+    let mut res = graph
+        .iter()
+        .filter(|&crate_id| graph.origin(crate_id) != CrateOrigin::Lang)
+        .filter(|&crate_id| !graph.dependencies(crate_id).any(|dep| is_sysroot_std(dep.crate_id)))
+        .filter(|&crate_id| !db.crate_is_no_std(crate_id))
+        .collect::<Vec<_>>();
+    res.sort();
+    Arc::new(res)
+}
+
+fn project_editions(db: &impl SourceDatabase) -> Arc<FxHashSet<Edition>> {
+    Arc::new(db.crate_graph().editions_in_use())
+}
+
+fn inferred_edition(db: &impl SourceDatabase, crate_id: CrateId) -> Edition {
+    let graph = db.crate_graph();
+    if graph.is_edition_known(crate_id) {
+        return graph.edition(crate_id);
+    }
+    match graph.origin(crate_id) {
+        // The latest edition.
+        CrateOrigin::Lang => Edition::Edition2018,
+        CrateOrigin::Member => Edition::Edition2018,
+        CrateOrigin::Dependency => Edition::Edition2015,
+    }
+}
+
+fn all_dependency_edges(db: &impl SourceDatabase) -> Arc<Vec<(CrateId, CrateId, SmolStr)>> {
+    let graph = db.crate_graph();
+    let mut crates = graph.iter().collect::<Vec<_>>();
+    crates.sort();
+    let res = crates
+        .into_iter()
+        .flat_map(|from| {
+            graph.dependencies(from).map(move |dep| (from, dep.crate_id(), dep.name.clone()))
+        })
+        .collect();
+    Arc::new(res)
+}
+
+fn crate_reachability(db: &impl SourceDatabase) -> Arc<Reachability> {
+    Arc::new(db.crate_graph().reachability())
+}
+
+fn crates_reachable_from_workspace(db: &impl SourceDatabase) -> Arc<FxHashSet<CrateId>> {
+    let graph = db.crate_graph();
+    let mut res = FxHashSet::default();
+    for crate_id in graph.iter().filter(|&crate_id| graph.origin(crate_id) == CrateOrigin::Member) {
+        // A member already reached through another one has its dependencies
+        // in the set as well.
+        if res.insert(crate_id) {
+            res.extend(graph.transitive_dependencies(crate_id));
+        }
+    }
+    Arc::new(res)
+}
+
+fn is_reachable_from_workspace(db: &impl SourceDatabase, crate_id: CrateId) -> bool {
+    db.crates_reachable_from_workspace().contains(&crate_id)
+}
+
+fn crates_by_origin(db: &impl SourceDatabase) -> Arc<FxHashMap<CrateOrigin, Vec<CrateId>>> {
+    let graph = db.crate_graph();
+    let mut res: FxHashMap<CrateOrigin, Vec<CrateId>> = FxHashMap::default();
+    for crate_id in graph.iter() {
+        res.entry(graph.origin(crate_id)).or_default().push(crate_id);
+    }
+    for crates in res.values_mut() {
+        crates.sort();
+    }
+    Arc::new(res)
+}
+
+fn file_edition(db: &impl SourceDatabase, file_id: FileId) -> Edition {
+    let crate_graph = db.crate_graph();
+    let crate_id = crate_graph
+        .crate_id_for_crate_root(file_id)
+        .or_else(|| db.relevant_crates(file_id).first().copied());
+    match crate_id {
+        Some(crate_id) => crate_graph.edition(crate_id),
+        None => Edition::Edition2018,
+    }
+}
+
+fn editioned(db: &impl SourceDatabase, file_id: FileId) -> EditionedFileId {
+    EditionedFileId::new(file_id, db.file_edition(file_id))
+}
+
+fn module_file(
+    db: &impl SourceDatabase,
+    crate_id: CrateId,
+    mod_path: &[SmolStr],
+) -> Option<FileId> {
+    let mut file_id = db.crate_graph().crate_root(crate_id);
+    // Directory of the child modules, relative to the directory of `file_id`.
+    let mut mod_dir = RelativePathBuf::default();
+    for name in mod_path {
+        let (child, child_mod_dir) =
+            child_module_files(db, file_id, &mod_dir, name).into_iter().next()?;
+        file_id = child;
+        mod_dir = child_mod_dir;
+    }
+    Some(file_id)
+}
+
+/// Existing files which could back `mod name;` declared in `anchor`,
+/// `name.rs` first and `name/mod.rs` second, together with the directories
+/// of their own child modules, relative to them. `mod_dir` is that directory
+/// for `anchor`.
+fn child_module_files(
+    db: &impl SourceDatabase,
+    anchor: FileId,
+    mod_dir: &RelativePath,
+    name: &str,
+) -> Vec<(FileId, RelativePathBuf)> {
+    let candidates = [
+        (format!("{}.rs", name), RelativePathBuf::from(name)),
+        (format!("{}/mod.rs", name), RelativePathBuf::default()),
+    ];
+    candidates
+        .iter()
+        .filter_map(|(candidate, child_mod_dir)| {
+            let file_id = db.resolve_relative_path(anchor, &mod_dir.join(candidate))?;
+            Some((file_id, child_mod_dir.clone()))
+        })
+        .collect()
+}
+
+fn crate_reachable_files(db: &impl SourceDatabase, crate_id: CrateId) -> Arc<FxHashSet<FileId>> {
+    use ra_syntax::ast::{ModuleItemOwner, NameOwner};
+
+    let mut res = FxHashSet::default();
+    let root = db.crate_graph().crate_root(crate_id);
+    res.insert(root);
+    let mut work = vec![(root, Vec::new())];
+    while let Some((file_id, mod_path)) = work.pop() {
+        for item in db.parse(file_id).tree().items() {
+            let module = match item {
+                ast::ModuleItem::Module(it) if it.item_list().is_none() => it,
+                _ => continue,
+            };
+            let name = match module.name() {
+                Some(it) => it.text().clone(),
+                None => continue,
+            };
+            let mut child_path: Vec<SmolStr> = mod_path.clone();
+            child_path.push(name);
+            if let Some(child) = db.module_file(crate_id, &child_path) {
+                // A malformed module tree can lead back to a visited file.
+                if res.insert(child) {
+                    work.push((child, child_path));
+                }
+            }
+        }
+    }
+    Arc::new(res)
+}
+
+fn resolve_path_attr_mod(
+    db: &impl SourceDatabase,
+    anchor: FileId,
+    explicit_path: &str,
+) -> Option<FileId> {
+    db.resolve_relative_path(anchor, RelativePath::new(explicit_path))
+}
+
+/// Per-file data which most files don't have, kept in a single input so that
+/// registering a file doesn't have to set it. See
+/// `SourceDatabaseExt::file_metadata`.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct FileMetadata {
+    /// See `SourceDatabaseExt::file_is_generated`.
+    pub generated: FxHashSet<FileId>,
+    /// See `SourceDatabaseExt::file_original_path`.
+    pub original_paths: FxHashMap<FileId, PathBuf>,
 }
 
 /// We don't want to give HIR knowledge of source roots, hence we extract these
@@ -112,17 +1050,199 @@ fn parse_query(db: &impl SourceDatabase, file_id: FileId) -> Parse<ast::SourceFi
 pub trait SourceDatabaseExt: SourceDatabase {
     #[salsa::input]
     fn file_text(&self, file_id: FileId) -> Arc<String>;
+    /// Files whose text comes from a loader, see `set_file_text_loader`.
+    #[salsa::input]
+    fn lazy_files(&self) -> Arc<FxHashSet<FileId>>;
+    /// Loader of a file in `lazy_files`. It's not set for other files.
+    #[salsa::input]
+    fn lazy_file_loader(&self, file_id: FileId) -> FileTextLoaderHandle;
+    /// Loader of the file's text, for files whose `file_text` is not set.
+    /// `None` for ordinary files, which is the default.
+    fn file_text_loader(&self, file_id: FileId) -> Option<FileTextLoaderHandle>;
+    /// Text of a file with a `file_text_loader`. The loader is called once,
+    /// and then only when the loader of the file is replaced, or when the
+    /// text was evicted by the LRU of the query.
+    fn lazy_file_text(&self, file_id: FileId) -> Arc<String>;
+    /// Tells an empty file from one whose text couldn't be loaded, which both
+    /// have an empty `file_text`. Files with an eagerly set text are never
+    /// `Missing`. This asks the loader every time, so it's not memoized.
+    #[salsa::transparent]
+    fn file_load_status(&self, file_id: FileId) -> FileLoadStatus;
+    /// Optional data of all files. Read it through the per-file queries, like
+    /// `file_is_generated`, which don't change when other files do.
+    #[salsa::input]
+    fn file_metadata(&self) -> Arc<FileMetadata>;
+    /// Whether the file is produced by a tool, like a build script, rather than
+    /// written by hand. This is only stored here, it's up to the features to
+    /// decide what to do about it. Files aren't generated by default, see
+    /// `set_file_is_generated`.
+    fn file_is_generated(&self, file_id: FileId) -> bool;
+    /// Absolute path the file was loaded from, for showing it to the user and
+    /// opening it in an editor. Unlike `file_relative_path`, the analysis never
+    /// looks at it. `None` for files which are not on disk, like synthetic
+    /// ones.
+    fn file_original_path(&self, file_id: FileId) -> Option<PathBuf>;
     /// Path to a file, relative to the root of its source root.
     #[salsa::input]
     fn file_relative_path(&self, file_id: FileId) -> RelativePathBuf;
+    /// `file_relative_path` split into directories and the file name, so
+    /// `src/foo/bar.rs` becomes `["src", "foo", "bar.rs"]`.
+    fn file_path_components(&self, file_id: FileId) -> Arc<Vec<SmolStr>>;
+    /// Whether the file lies in the `tests` or `benches` directory at the top
+    /// of its source root, where Cargo looks for integration tests and
+    /// benchmarks. This is only a guess from the path: the project model
+    /// might say otherwise, and `#[cfg(test)]` modules elsewhere don't count.
+    fn file_is_integration_test(&self, file_id: FileId) -> bool;
+    /// The change in which the text of each file was last changed, as noted
+    /// by `record_file_text_changes`.
+    #[salsa::input]
+    fn file_text_revisions(&self) -> Arc<FileTextRevisions>;
+    /// Files whose text changed after `rev`, sorted by id.
+    ///
+    /// `rev` is meant to be the `current` revision of `file_text_revisions`
+    /// as it was when the caller last looked at the files. Only the changes
+    /// noted by `record_file_text_changes` are seen.
+    #[salsa::transparent]
+    fn files_changed_since(&self, rev: FileTextRevision) -> Vec<FileId>;
+    /// Files registered with `add_synthetic_file` or `intern_anonymous_file`,
+    /// in order of registration.
+    #[salsa::input]
+    fn synthetic_files(&self) -> Arc<Vec<FileId>>;
     /// Source root of the file.
     #[salsa::input]
     fn file_source_root(&self, file_id: FileId) -> SourceRootId;
     /// Contents of the source root.
     #[salsa::input]
     fn source_root(&self, id: SourceRootId) -> Arc<SourceRoot>;
+    /// Directories of the source roots, for `source_root_for_path`. All the
+    /// paths must be relative to the same directory, or absolute. Roots may
+    /// be missing, and then are never found by path.
+    #[salsa::input]
+    fn source_root_paths(&self) -> Arc<FxHashMap<SourceRootId, RelativePathBuf>>;
+    /// The source root with the deepest directory containing the path, which
+    /// is relative to the same directory as `source_root_paths`. Absolute
+    /// paths work like relative ones, as `RelativePath` ignores leading `/`s.
+    #[salsa::transparent]
+    fn source_root_for_path(&self, path: &RelativePath) -> Option<SourceRootId>;
 
     fn source_root_crates(&self, id: SourceRootId) -> Arc<Vec<CrateId>>;
+
+    /// Crates rooted in the source root, sorted by id. Unlike
+    /// `source_root_crates`, the order doesn't depend on the layout of the
+    /// source root.
+    fn crate_roots_in_source_root(&self, id: SourceRootId) -> Arc<Vec<CrateId>>;
+
+    /// Other crates rooted in the source root of the crate, like the binaries
+    /// of a package with a library, sorted by id.
+    fn sibling_crates(&self, crate_id: CrateId) -> Arc<Vec<CrateId>>;
+
+    /// The edition of the crates in the source root, if there are any and
+    /// they all agree on it.
+    fn source_root_edition(&self, id: SourceRootId) -> Option<Edition>;
+
+    /// The crate, its edition, and the source root of the position's file, in
+    /// a single call for the entry points of navigation. `None` if the file
+    /// belongs to no crate. Only the file of the position matters.
+    #[salsa::transparent]
+    fn resolution_context(&self, position: FilePosition) -> Option<ResolutionContext>;
+    fn file_resolution_context(&self, file_id: FileId) -> Option<ResolutionContext>;
+
+    /// Whether the file is still listed in its source root, under its path.
+    /// Removed files keep their `FileId`s, so this is not a given.
+    #[salsa::transparent]
+    fn file_exists(&self, file_id: FileId) -> bool;
+
+    /// Crates whose root file no longer exists, sorted by id.
+    fn crates_with_missing_roots(&self) -> Arc<Vec<CrateId>>;
+
+    /// Root file of the `file_crate` of the file.
+    fn crate_root_of_file(&self, file_id: FileId) -> Option<FileId>;
+
+    /// Source root and path of the file. Unlike `FileId`s, these stay the same
+    /// across sessions, so they can be used to persist data about files.
+    #[salsa::transparent]
+    fn file_stable_key(&self, file_id: FileId) -> (SourceRootId, RelativePathBuf);
+
+    /// Inverse of `file_stable_key`, `None` if there's no such file currently.
+    #[salsa::transparent]
+    fn file_by_stable_key(&self, root: SourceRootId, path: &RelativePath) -> Option<FileId>;
+
+    /// Files of the source root with an `extern crate name` item, or a `use`
+    /// item whose path starts with `name`, sorted by id. This only looks at
+    /// the syntax, so it's a coarse filter: it doesn't know if `name` is the
+    /// crate or a module of the same name, and misses plain paths, like
+    /// `name::foo()`.
+    #[salsa::transparent]
+    fn files_referencing_crate(&self, name: &str, scope: SourceRootId) -> Vec<FileId>;
+
+    /// Hash of the structure of the project: the crates, identified by the
+    /// `file_stable_key` of their roots, with their editions, cfg options and
+    /// dependencies, and the paths of the files in the source roots of the
+    /// crates. Contents of files, `FileId`s, `CrateId`s and cosmetic data, like
+    /// display names or versions, are left out, so it is the same across
+    /// sessions for an unchanged project model, and doesn't depend on the
+    /// order in which the graph was built.
+    fn project_fingerprint(&self) -> u64;
+
+    /// Like `resolve_relative_path`, but with control over symlinks: without
+    /// `follow_symlinks`, files behind a symlink of the source root are not
+    /// resolved.
+    #[salsa::transparent]
+    fn resolve_relative_path_with(
+        &self,
+        anchor: FileId,
+        relative_path: &RelativePath,
+        follow_symlinks: bool,
+    ) -> Option<FileId>;
+
+    /// The nearest file called `filename` in the directory of the anchor or in
+    /// one of its parents, up to the root of the anchor's source root.
+    #[salsa::transparent]
+    fn find_up(&self, anchor: FileId, filename: &str) -> Option<FileId>;
+
+    /// Existing files which could back `mod name;` declared in `anchor`,
+    /// `name.rs` first and `name/mod.rs` second, ignoring `#[path]`. For
+    /// anchors which are neither crate roots nor `mod.rs`, the files are
+    /// looked for in the directory named after the anchor, like rustc does
+    /// it. Two candidates mean the module is ambiguous.
+    #[salsa::transparent]
+    fn module_file_candidates(&self, anchor: FileId, name: &str) -> Arc<Vec<FileId>>;
+
+    /// `mod foo;` declarations of the file for which there is no file, with
+    /// the ranges of the declarations and the names of the modules. Only the
+    /// top-level items of the file are looked at; `#[path]` is respected.
+    fn unresolved_mods(&self, file_id: FileId) -> Arc<Vec<(TextRange, String)>>;
+
+    /// Crates of the source root whose root file lies under the `prefix`
+    /// directory, sorted by `CrateId`. The prefix is matched by whole path
+    /// components, so `crates` doesn't match `crates2/foo/lib.rs`.
+    #[salsa::transparent]
+    fn crate_roots_under(&self, root: SourceRootId, prefix: &RelativePath) -> Arc<Vec<CrateId>>;
+
+    /// Every crate with its root file and the source root of that file, sorted
+    /// by `CrateId`.
+    fn crate_source_roots(&self) -> Arc<Vec<(CrateId, FileId, SourceRootId)>>;
+
+    /// Checks that `file_source_root` and `source_root` agree with each other:
+    /// every file listed in one of the `source_root_paths` roots says it
+    /// belongs to that root, and every such file and every crate root is
+    /// listed in the root it says it belongs to, under its path. Also checks
+    /// that exactly the crates rooted in non-library source roots are members.
+    ///
+    /// A mismatch makes `resolve_relative_path` silently misbehave, so this
+    /// panics with a description of the first inconsistency found. The checks
+    /// are `debug_assert!`s, release builds only walk the files.
+    #[salsa::transparent]
+    fn check_invariants(&self) -> ();
+}
+
+fn source_root_for_path(db: &impl SourceDatabaseExt, path: &RelativePath) -> Option<SourceRootId> {
+    db.source_root_paths()
+        .iter()
+        .filter(|(_, root_path)| path.starts_with(root_path))
+        // Deepest first, ties are broken by id to be deterministic.
+        .min_by_key(|&(&id, root_path)| (std::cmp::Reverse(root_path.components().count()), id))
+        .map(|(&id, _)| id)
 }
 
 fn source_root_crates(
@@ -135,27 +1255,671 @@ fn source_root_crates(
     Arc::new(res)
 }
 
+fn crate_roots_in_source_root(db: &impl SourceDatabaseExt, id: SourceRootId) -> Arc<Vec<CrateId>> {
+    let mut res = Vec::clone(&db.source_root_crates(id));
+    res.sort();
+    Arc::new(res)
+}
+
+fn sibling_crates(db: &impl SourceDatabaseExt, crate_id: CrateId) -> Arc<Vec<CrateId>> {
+    let source_root = db.file_source_root(db.crate_graph().crate_root(crate_id));
+    let mut res = db
+        .source_root_crates(source_root)
+        .iter()
+        .copied()
+        .filter(|&it| it != crate_id)
+        .collect::<Vec<_>>();
+    res.sort();
+    Arc::new(res)
+}
+
+fn resolution_context(
+    db: &impl SourceDatabaseExt,
+    position: FilePosition,
+) -> Option<ResolutionContext> {
+    db.file_resolution_context(position.file_id)
+}
+
+fn file_resolution_context(
+    db: &impl SourceDatabaseExt,
+    file_id: FileId,
+) -> Option<ResolutionContext> {
+    let crate_id = db.file_crate(file_id)?;
+    let source_root = db.file_source_root(file_id);
+    Some(ResolutionContext {
+        crate_id,
+        edition: db.inferred_edition(crate_id),
+        source_root,
+        is_library: db.source_root(source_root).is_library,
+    })
+}
+
+fn source_root_edition(db: &impl SourceDatabaseExt, id: SourceRootId) -> Option<Edition> {
+    let graph = db.crate_graph();
+    let mut editions =
+        db.source_root_crates(id).iter().map(|&it| graph.edition(it)).collect::<Vec<_>>();
+    editions.dedup();
+    match editions.as_slice() {
+        [edition] => Some(*edition),
+        _ => None,
+    }
+}
+
+fn file_exists(db: &impl SourceDatabaseExt, file_id: FileId) -> bool {
+    let source_root = db.source_root(db.file_source_root(file_id));
+    source_root.file_by_relative_path(&db.file_relative_path(file_id)) == Some(file_id)
+}
+
+fn crates_with_missing_roots(db: &impl SourceDatabaseExt) -> Arc<Vec<CrateId>> {
+    Arc::new(db.crate_graph().dangling_roots(db))
+}
+
+fn crate_root_of_file(db: &impl SourceDatabaseExt, file_id: FileId) -> Option<FileId> {
+    let crate_id = db.file_crate(file_id)?;
+    Some(db.crate_graph().crate_root(crate_id))
+}
+
+fn file_text_loader(db: &impl SourceDatabaseExt, file_id: FileId) -> Option<FileTextLoaderHandle> {
+    if !db.lazy_files().contains(&file_id) {
+        return None;
+    }
+    Some(db.lazy_file_loader(file_id))
+}
+
+fn lazy_file_text(db: &impl SourceDatabaseExt, file_id: FileId) -> Arc<String> {
+    // Not through `file_text_loader`, which is the same after `touch_file`.
+    let loader = db.lazy_file_loader(file_id);
+    Arc::new(loader.0.load_text(file_id))
+}
+
+fn file_load_status(db: &impl SourceDatabaseExt, file_id: FileId) -> FileLoadStatus {
+    if let Some(loader) = db.file_text_loader(file_id) {
+        if loader.0.is_missing(file_id) {
+            return FileLoadStatus::Missing;
+        }
+    }
+    if db.file_text_len(file_id) == 0 {
+        FileLoadStatus::Empty
+    } else {
+        FileLoadStatus::Loaded
+    }
+}
+
+fn file_stable_key(
+    db: &impl SourceDatabaseExt,
+    file_id: FileId,
+) -> (SourceRootId, RelativePathBuf) {
+    (db.file_source_root(file_id), db.file_relative_path(file_id))
+}
+
+fn project_fingerprint(db: &impl SourceDatabaseExt) -> u64 {
+    fn hash_of(value: impl Hash) -> u64 {
+        let mut hasher = FxHasher::default();
+        value.hash(&mut hasher);
+        hasher.finish()
+    }
+    let graph = db.crate_graph();
+    let crate_key = |crate_id| db.file_stable_key(graph.crate_root(crate_id));
+    let mut crates = graph
+        .iter()
+        .map(|crate_id| {
+            let cfg = graph.cfg_options(crate_id);
+            let mut atoms = cfg.atoms().collect::<Vec<_>>();
+            atoms.sort();
+            let mut key_values = cfg.key_values().collect::<Vec<_>>();
+            key_values.sort();
+            let mut deps = graph
+                .dependencies(crate_id)
+                .map(|dep| {
+                    (dep.name.clone(), crate_key(dep.crate_id), dep.kind, dep.feature.clone())
+                })
+                .collect::<Vec<_>>();
+            deps.sort_by(|(name1, to1, ..), (name2, to2, ..)| (name1, to1).cmp(&(name2, to2)));
+            let structure = (graph.edition(crate_id), atoms, key_values, graph.cfg_test(crate_id));
+            hash_of((crate_key(crate_id), structure, deps))
+        })
+        .collect::<Vec<_>>();
+    crates.sort();
+
+    let mut roots = graph
+        .iter()
+        .map(|crate_id| db.file_source_root(graph.crate_root(crate_id)))
+        .collect::<Vec<_>>();
+    roots.sort();
+    roots.dedup();
+    let roots = roots
+        .into_iter()
+        .map(|root_id| {
+            let source_root = db.source_root(root_id);
+            let mut paths = source_root
+                .walk()
+                .map(|file_id| db.file_relative_path(file_id))
+                .collect::<Vec<_>>();
+            paths.sort();
+            (root_id, source_root.is_library, paths)
+        })
+        .collect::<Vec<_>>();
+    hash_of((crates, roots))
+}
+
+fn files_referencing_crate(
+    db: &impl SourceDatabaseExt,
+    name: &str,
+    scope: SourceRootId,
+) -> Vec<FileId> {
+    fn starts_with(tree: ast::UseTree, name: &str) -> bool {
+        match tree.path() {
+            Some(mut path) => {
+                while let Some(qualifier) = path.qualifier() {
+                    path = qualifier;
+                }
+                let first = path.segment().and_then(|segment| segment.name_ref());
+                first.map_or(false, |name_ref| name_ref.text() == name)
+            }
+            // `use {name::foo, bar};`
+            None => tree
+                .use_tree_list()
+                .map_or(false, |list| list.use_trees().any(|tree| starts_with(tree, name))),
+        }
+    }
+    let mut res = db
+        .source_root(scope)
+        .walk()
+        .filter(|&file_id| {
+            db.parse(file_id).tree().syntax().descendants().any(|node| {
+                if let Some(extern_crate) = ast::ExternCrateItem::cast(node.clone()) {
+                    return extern_crate.name_ref().map_or(false, |it| it.text() == name);
+                }
+                ast::UseItem::cast(node)
+                    .and_then(|use_item| use_item.use_tree())
+                    .map_or(false, |tree| starts_with(tree, name))
+            })
+        })
+        .collect::<Vec<_>>();
+    res.sort();
+    res
+}
+
+fn file_by_stable_key(
+    db: &impl SourceDatabaseExt,
+    root: SourceRootId,
+    path: &RelativePath,
+) -> Option<FileId> {
+    db.source_root(root).file_by_relative_path(path)
+}
+
+fn resolve_relative_path_with(
+    db: &impl SourceDatabaseExt,
+    anchor: FileId,
+    relative_path: &RelativePath,
+    follow_symlinks: bool,
+) -> Option<FileId> {
+    let source_root_id = db.file_source_root(anchor);
+    if source_root_id == ANONYMOUS_SOURCE_ROOT {
+        return None;
+    }
+    let path = {
+        let mut path = db.file_relative_path(anchor);
+        assert!(path.pop());
+        path.push(relative_path);
+        path.normalize()
+    };
+    let source_root = db.source_root(source_root_id);
+    if !follow_symlinks && source_root.is_behind_symlink(&path) {
+        return None;
+    }
+    source_root.file_by_relative_path(&path)
+}
+
+fn files_changed_since(db: &impl SourceDatabaseExt, rev: FileTextRevision) -> Vec<FileId> {
+    let mut res = db
+        .file_text_revisions()
+        .changed_at
+        .iter()
+        .filter(|&(_, &changed_at)| changed_at > rev)
+        .map(|(&file_id, _)| file_id)
+        .collect::<Vec<_>>();
+    res.sort();
+    res
+}
+
+fn find_up(db: &impl SourceDatabaseExt, anchor: FileId, filename: &str) -> Option<FileId> {
+    let source_root = db.source_root(db.file_source_root(anchor));
+    let mut dir = db.file_relative_path(anchor);
+    while dir.pop() {
+        let candidate = dir.join(filename).normalize();
+        if let Some(file_id) = source_root.file_by_relative_path(&candidate) {
+            return Some(file_id);
+        }
+    }
+    None
+}
+
+fn module_file_candidates(
+    db: &impl SourceDatabaseExt,
+    anchor: FileId,
+    name: &str,
+) -> Arc<Vec<FileId>> {
+    let mod_dir = child_mod_dir(db, anchor);
+    let res = child_module_files(db, anchor, &mod_dir, name)
+        .into_iter()
+        .map(|(file_id, _)| file_id)
+        .collect();
+    Arc::new(res)
+}
+
+/// The directory of the child modules of `file_id`, relative to its own, as
+/// far as the file alone tells: `module_file` knows better, as it knows how
+/// the file was reached.
+fn child_mod_dir(db: &impl SourceDatabaseExt, file_id: FileId) -> RelativePathBuf {
+    let path = db.file_relative_path(file_id);
+    // Children of `foo.rs` live in `foo/`, children of crate roots and of
+    // `mod.rs` files live next to them.
+    let owns_dir = path.file_name() == Some("mod.rs")
+        || db.crate_graph().crate_id_for_crate_root(file_id).is_some();
+    match path.file_stem() {
+        Some(stem) if !owns_dir => RelativePathBuf::from(stem),
+        _ => RelativePathBuf::default(),
+    }
+}
+
+fn unresolved_mods(db: &impl SourceDatabaseExt, file_id: FileId) -> Arc<Vec<(TextRange, String)>> {
+    use ra_syntax::ast::{AttrsOwner, ModuleItemOwner, NameOwner};
+
+    let mod_dir = child_mod_dir(db, file_id);
+    let mut res = Vec::new();
+    for item in db.parse(file_id).tree().items() {
+        let module = match item {
+            ast::ModuleItem::Module(it) if it.item_list().is_none() => it,
+            _ => continue,
+        };
+        let name = match module.name() {
+            Some(it) => it.text().to_string(),
+            None => continue,
+        };
+        let explicit_path = module
+            .attrs()
+            .filter_map(|attr| attr.as_simple_key_value())
+            .find(|(key, _)| key.as_str() == "path")
+            .map(|(_, value)| value);
+        let resolved = match explicit_path {
+            Some(explicit_path) => db.resolve_path_attr_mod(file_id, &explicit_path),
+            None => child_module_files(db, file_id, &mod_dir, &name)
+                .into_iter()
+                .next()
+                .map(|(file_id, _)| file_id),
+        };
+        if resolved.is_none() {
+            res.push((module.syntax().text_range(), name));
+        }
+    }
+    Arc::new(res)
+}
+
+fn crate_roots_under(
+    db: &impl SourceDatabaseExt,
+    root: SourceRootId,
+    prefix: &RelativePath,
+) -> Arc<Vec<CrateId>> {
+    let graph = db.crate_graph();
+    let mut res = db
+        .source_root_crates(root)
+        .iter()
+        .copied()
+        .filter(|&crate_id| db.file_relative_path(graph.crate_root(crate_id)).starts_with(prefix))
+        .collect::<Vec<_>>();
+    res.sort();
+    Arc::new(res)
+}
+
+fn file_path_components(db: &impl SourceDatabaseExt, file_id: FileId) -> Arc<Vec<SmolStr>> {
+    let path = db.file_relative_path(file_id);
+    let res = path
+        .components()
+        .filter_map(|it| match it {
+            relative_path::Component::Normal(name) => Some(SmolStr::new(name)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    Arc::new(res)
+}
+
+fn file_is_integration_test(db: &impl SourceDatabaseExt, file_id: FileId) -> bool {
+    let components = db.file_path_components(file_id);
+    // A file right at the top of the source root is never in a directory.
+    components.len() > 1 && (components[0] == "tests" || components[0] == "benches")
+}
+
+fn crate_source_roots(
+    db: &(impl SourceDatabaseExt + SourceDatabase),
+) -> Arc<Vec<(CrateId, FileId, SourceRootId)>> {
+    let graph = db.crate_graph();
+    let mut res = graph
+        .crate_roots()
+        .map(|(crate_id, file_id)| (crate_id, file_id, db.file_source_root(file_id)))
+        .collect::<Vec<_>>();
+    res.sort();
+    Arc::new(res)
+}
+
+/// Registers a file which isn't backed by a path, like the output of a build
+/// script, and returns its fresh id.
+///
+/// Synthetic ids are allocated downwards from `u32::MAX`, so they don't clash
+/// with the ids of the files on disk. The file gets the `<synthetic>` path and
+/// belongs to `SYNTHETIC_SOURCE_ROOT`.
+pub fn add_synthetic_file(db: &mut impl SourceDatabaseExt, text: Arc<String>) -> FileId {
+    add_detached_file(db, text, RelativePathBuf::from("<synthetic>"), SYNTHETIC_SOURCE_ROOT)
+}
+
+/// Registers a piece of text which is not a file at all, like a macro
+/// expansion or the preview of a fix, so that it can be parsed.
+///
+/// The file has an empty path and belongs to `ANONYMOUS_SOURCE_ROOT`. Paths
+/// are never resolved to or from it. Ids are shared with synthetic files.
+pub fn intern_anonymous_file(db: &mut impl SourceDatabaseExt, text: Arc<String>) -> FileId {
+    add_detached_file(db, text, RelativePathBuf::default(), ANONYMOUS_SOURCE_ROOT)
+}
+
+fn add_detached_file(
+    db: &mut impl SourceDatabaseExt,
+    text: Arc<String>,
+    path: RelativePathBuf,
+    source_root_id: SourceRootId,
+) -> FileId {
+    let mut synthetic_files = Vec::clone(&db.synthetic_files());
+    if !synthetic_files.iter().any(|&it| db.file_source_root(it) == source_root_id) {
+        db.set_source_root(source_root_id, Default::default());
+    }
+    let file_id = FileId(u32::MAX - synthetic_files.len() as u32);
+    db.set_file_text(file_id, text);
+    db.set_file_relative_path(file_id, path);
+    db.set_file_source_root(file_id, source_root_id);
+    synthetic_files.push(file_id);
+    db.set_synthetic_files(Arc::new(synthetic_files));
+    set_file_is_generated(db, file_id, true);
+    file_id
+}
+
+/// Notes that the text of the files was just set, for `files_changed_since`.
+pub fn record_file_text_changes(
+    db: &mut impl SourceDatabaseExt,
+    file_ids: impl IntoIterator<Item = FileId>,
+) {
+    let mut file_ids = file_ids.into_iter().peekable();
+    if file_ids.peek().is_none() {
+        return;
+    }
+    let mut revisions = FileTextRevisions::clone(&db.file_text_revisions());
+    revisions.current = FileTextRevision(revisions.current.0 + 1);
+    let rev = revisions.current;
+    for file_id in file_ids {
+        revisions.changed_at.insert(file_id, rev);
+    }
+    db.set_file_text_revisions(Arc::new(revisions));
+}
+
+/// Marks the text of the file as changed without changing it, so that `parse`
+/// and everything else derived from the file is recomputed on next access. A
+/// lazily loaded file is read anew.
+pub fn touch_file(db: &mut impl SourceDatabaseExt, file_id: FileId) {
+    // A lazily loaded file usually has no `file_text` set at all.
+    match db.file_text_loader(file_id) {
+        Some(loader) => set_file_text_loader(db, file_id, Some(loader)),
+        None => {
+            let text = SourceDatabaseExt::file_text(db, file_id);
+            db.set_file_text(file_id, text);
+        }
+    }
+    record_file_text_changes(db, Some(file_id));
+}
+
+/// An edit passed to `edit_file` which is not within the text left by the
+/// edits before it, or doesn't start or end on a character boundary.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvalidEditError {
+    pub file_id: FileId,
+    /// The position of the edit in the list.
+    pub index: usize,
+    pub range: TextRange,
+}
+
+impl fmt::Display for InvalidEditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "edit #{} of {:?} has an invalid range {:?}",
+            self.index, self.file_id, self.range
+        )
+    }
+}
+
+impl std::error::Error for InvalidEditError {}
+
+/// Applies the edits to the text of the file one after the other, each to the
+/// text left by the previous ones, as editors send them, instead of setting
+/// the whole new text. The text is copied at most once, and not at all if
+/// nothing else holds on to it. A lazily loaded file keeps the edited text, as
+/// if it was set with `set_file_text`.
+///
+/// If one of the edits is not within the text or not on character boundaries,
+/// none of them are applied.
+pub fn edit_file(
+    db: &mut impl SourceDatabaseExt,
+    file_id: FileId,
+    edits: Vec<AtomTextEdit>,
+) -> Result<(), InvalidEditError> {
+    let mut text = FileLoader::file_text(&*db, file_id);
+    {
+        let text = Arc::make_mut(&mut text);
+        for (index, edit) in edits.into_iter().enumerate() {
+            let range = edit.delete.start().to_usize()..edit.delete.end().to_usize();
+            if range.end > text.len()
+                || !text.is_char_boundary(range.start)
+                || !text.is_char_boundary(range.end)
+            {
+                return Err(InvalidEditError { file_id, index, range: edit.delete });
+            }
+            text.replace_range(range, &edit.insert);
+        }
+    }
+    let durability = if db.source_root(db.file_source_root(file_id)).is_library {
+        salsa::Durability::HIGH
+    } else {
+        salsa::Durability::LOW
+    };
+    db.set_file_text_with_durability(file_id, text, durability);
+    set_file_text_loader(db, file_id, None);
+    record_file_text_changes(db, Some(file_id));
+    Ok(())
+}
+
+/// Keeps the trees of the files, like crate roots or files open in the editor,
+/// in memory for as long as they are pinned, regardless of the LRU capacity
+/// of `parse`.
+pub fn pin_files(db: &mut impl SourceDatabase, files: &[FileId]) {
+    set_files_pinned(db, files, true)
+}
+
+/// Undoes `pin_files`, the trees are subject to the LRU of `parse` again.
+pub fn unpin_files(db: &mut impl SourceDatabase, files: &[FileId]) {
+    set_files_pinned(db, files, false)
+}
+
+fn set_files_pinned(db: &mut impl SourceDatabase, files: &[FileId], pinned: bool) {
+    let pinned_files = db.pinned_files();
+    if files.iter().all(|file_id| pinned_files.contains(file_id) == pinned) {
+        return;
+    }
+    let mut pinned_files = FxHashSet::clone(&pinned_files);
+    for file_id in files {
+        if pinned {
+            pinned_files.insert(*file_id);
+        } else {
+            pinned_files.remove(file_id);
+        }
+    }
+    // Files are pinned as they are opened in the editor.
+    db.set_pinned_files_with_durability(Arc::new(pinned_files), salsa::Durability::LOW);
+}
+
+fn file_is_generated(db: &impl SourceDatabaseExt, file_id: FileId) -> bool {
+    db.file_metadata().generated.contains(&file_id)
+}
+
+fn file_original_path(db: &impl SourceDatabaseExt, file_id: FileId) -> Option<PathBuf> {
+    db.file_metadata().original_paths.get(&file_id).cloned()
+}
+
+/// Calls `f` for every crate, dependencies first, checking for cancellation
+/// before each one. `f` may check for cancellation too.
+pub fn for_each_crate_canceled<DB, F>(db: &DB, f: F) -> Result<(), Canceled>
+where
+    DB: SourceDatabase + panic::RefUnwindSafe,
+    F: FnMut(CrateId),
+{
+    let graph = db.crate_graph();
+    // `f` is not called again once it was interrupted.
+    let mut f = panic::AssertUnwindSafe(f);
+    db.catch_canceled(move |db| {
+        for crate_id in graph.iter_topological() {
+            db.check_canceled();
+            (f.0)(crate_id);
+        }
+    })
+}
+
+/// Marks the file as produced by a tool, or as written by hand again, see
+/// `SourceDatabaseExt::file_is_generated`.
+pub fn set_file_is_generated(db: &mut impl SourceDatabaseExt, file_id: FileId, is_generated: bool) {
+    let metadata = db.file_metadata();
+    if metadata.generated.contains(&file_id) == is_generated {
+        return;
+    }
+    let mut metadata = FileMetadata::clone(&metadata);
+    if is_generated {
+        metadata.generated.insert(file_id);
+    } else {
+        metadata.generated.remove(&file_id);
+    }
+    db.set_file_metadata(Arc::new(metadata));
+}
+
+/// Has the text of the file come from the loader, or from the `file_text`
+/// input again with `None`. Files have no loader unless one is set here.
+pub fn set_file_text_loader(
+    db: &mut impl SourceDatabaseExt,
+    file_id: FileId,
+    loader: Option<FileTextLoaderHandle>,
+) {
+    let is_lazy = loader.is_some();
+    if let Some(loader) = loader {
+        // Like the text.
+        let durability = if db.source_root(db.file_source_root(file_id)).is_library {
+            salsa::Durability::HIGH
+        } else {
+            salsa::Durability::LOW
+        };
+        db.set_lazy_file_loader_with_durability(file_id, loader, durability);
+    }
+    let lazy_files = db.lazy_files();
+    if lazy_files.contains(&file_id) == is_lazy {
+        return;
+    }
+    let mut lazy_files = FxHashSet::clone(&lazy_files);
+    if is_lazy {
+        lazy_files.insert(file_id);
+    } else {
+        lazy_files.remove(&file_id);
+    }
+    // Loaders are installed with the project, mostly for libraries.
+    db.set_lazy_files_with_durability(Arc::new(lazy_files), salsa::Durability::HIGH);
+}
+
+/// Records the absolute path the file was loaded from, see
+/// `SourceDatabaseExt::file_original_path`.
+pub fn set_file_original_path(
+    db: &mut impl SourceDatabaseExt,
+    file_id: FileId,
+    path: Option<PathBuf>,
+) {
+    let metadata = db.file_metadata();
+    if metadata.original_paths.get(&file_id) == path.as_ref() {
+        return;
+    }
+    let mut metadata = FileMetadata::clone(&metadata);
+    match path {
+        Some(path) => metadata.original_paths.insert(file_id, path),
+        None => metadata.original_paths.remove(&file_id),
+    };
+    db.set_file_metadata(Arc::new(metadata));
+}
+
+fn check_invariants(db: &impl SourceDatabaseExt) {
+    let mut roots = db.source_root_paths().keys().copied().collect::<Vec<_>>();
+    roots.sort();
+    let mut files = Vec::new();
+    for root_id in roots {
+        for file_id in db.source_root(root_id).walk() {
+            let declared_root = db.file_source_root(file_id);
+            debug_assert!(
+                declared_root == root_id,
+                "{:?} is listed in {:?}, but its file_source_root is {:?}",
+                file_id,
+                root_id,
+                declared_root,
+            );
+            files.push(file_id);
+        }
+    }
+    let crate_graph = db.crate_graph();
+    let mut crates = crate_graph.crate_roots().collect::<Vec<_>>();
+    crates.sort();
+    for &(crate_id, root) in crates.iter() {
+        let is_library = db.source_root(db.file_source_root(root)).is_library;
+        debug_assert!(
+            crate_graph.is_member(crate_id) != is_library,
+            "{:?} is rooted in a source root with is_library {}, but is_member is {}",
+            crate_id,
+            is_library,
+            crate_graph.is_member(crate_id),
+        );
+    }
+    // Crate roots might not be listed anywhere.
+    files.extend(crates.into_iter().map(|(_, root)| root));
+    for file_id in files {
+        let root_id = db.file_source_root(file_id);
+        // Detached files are listed nowhere, see `add_synthetic_file`.
+        if root_id == SYNTHETIC_SOURCE_ROOT || root_id == ANONYMOUS_SOURCE_ROOT {
+            continue;
+        }
+        let path = db.file_relative_path(file_id);
+        debug_assert!(
+            db.source_root(root_id).file_by_relative_path(&path) == Some(file_id),
+            "{:?} is not listed in its source root {:?} under its path {}",
+            file_id,
+            root_id,
+            path,
+        );
+    }
+}
+
 /// Silly workaround for cyclic deps between the traits
 pub struct FileLoaderDelegate<T>(pub T);
 
 impl<T: SourceDatabaseExt> FileLoader for FileLoaderDelegate<&'_ T> {
     fn file_text(&self, file_id: FileId) -> Arc<String> {
-        SourceDatabaseExt::file_text(self.0, file_id)
+        match self.0.file_text_loader(file_id) {
+            Some(_) => self.0.lazy_file_text(file_id),
+            None => SourceDatabaseExt::file_text(self.0, file_id),
+        }
     }
     fn resolve_relative_path(
         &self,
         anchor: FileId,
         relative_path: &RelativePath,
     ) -> Option<FileId> {
-        let path = {
-            let mut path = self.0.file_relative_path(anchor);
-            assert!(path.pop());
-            path.push(relative_path);
-            path.normalize()
-        };
-        let source_root = self.0.file_source_root(anchor);
-        let source_root = self.0.source_root(source_root);
-        source_root.file_by_relative_path(&path)
+        self.0.resolve_relative_path_with(anchor, relative_path, true)
     }
 
     fn relevant_crates(&self, file_id: FileId) -> Arc<Vec<CrateId>> {
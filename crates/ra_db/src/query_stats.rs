@@ -0,0 +1,80 @@
+//! Counters of query recomputations.
+//!
+//! Queries which are recomputed much more often than expected are a sign of
+//! over-invalidation. `QueryStats` is fed with salsa events from
+//! `salsa::Database::salsa_event` and counts executions of each query. It is
+//! disabled by default, and costs a single atomic load per event in this
+//! state.
+
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Mutex,
+};
+
+use rustc_hash::FxHashMap;
+
+#[derive(Debug, Default)]
+pub struct QueryStats {
+    enabled: AtomicBool,
+    counts: Mutex<FxHashMap<String, usize>>,
+}
+
+impl QueryStats {
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    pub fn record<DB: salsa::Database>(&self, event: impl Fn() -> salsa::Event<DB>) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let salsa::EventKind::WillExecute { database_key } = event().kind {
+            // `Debug` is the only way to find out which query a `DatabaseKey`
+            // belongs to.
+            let name = query_name(&format!("{:?}", database_key)).to_string();
+            *self.counts.lock().unwrap().entry(name).or_default() += 1;
+        }
+    }
+
+    /// Recomputation counts per query name, the most recomputed queries first.
+    pub fn recompute_counts(&self) -> Vec<(String, usize)> {
+        let mut res = self
+            .counts
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, &count)| (name.clone(), count))
+            .collect::<Vec<_>>();
+        res.sort_by(|(name1, count1), (name2, count2)| {
+            count2.cmp(count1).then_with(|| name1.cmp(name2))
+        });
+        res
+    }
+
+    pub fn clear(&self) {
+        self.counts.lock().unwrap().clear();
+    }
+}
+
+/// Extracts `parse` from something like `SourceDatabaseStorage(parse(FileId(0)))`:
+/// query group storages and keys are `CamelCase`, while queries are named after
+/// `snake_case` methods.
+fn query_name(database_key: &str) -> &str {
+    let mut start = 0;
+    for (idx, c) in database_key.char_indices() {
+        if c == '(' {
+            let ident = &database_key[start..idx];
+            if ident.starts_with(|c: char| c.is_ascii_lowercase()) {
+                return ident;
+            }
+        }
+        if !(c.is_alphanumeric() || c == '_') {
+            start = idx + c.len_utf8();
+        }
+    }
+    database_key
+}
@@ -0,0 +1,67 @@
+//! Database and crate graph helpers used for testing `ra_db`.
+
+use std::{panic, sync::Arc};
+
+use ra_cfg::CfgOptions;
+
+use crate::{
+    salsa, CrateGraph, CrateId, Edition, Env, FileId, FileLoader, FileLoaderDelegate, QueryStats,
+    RelativePath,
+};
+
+#[salsa::database(crate::SourceDatabaseExtStorage, crate::SourceDatabaseStorage)]
+#[derive(Debug, Default)]
+pub struct TestDB {
+    runtime: salsa::Runtime<TestDB>,
+    pub(crate) query_stats: Arc<QueryStats>,
+}
+
+impl salsa::Database for TestDB {
+    fn salsa_runtime(&self) -> &salsa::Runtime<Self> {
+        &self.runtime
+    }
+    fn salsa_runtime_mut(&mut self) -> &mut salsa::Runtime<Self> {
+        &mut self.runtime
+    }
+    fn salsa_event(&self, event: impl Fn() -> salsa::Event<TestDB>) {
+        self.query_stats.record(event);
+    }
+}
+
+impl salsa::ParallelDatabase for TestDB {
+    fn snapshot(&self) -> salsa::Snapshot<TestDB> {
+        salsa::Snapshot::new(TestDB {
+            runtime: self.runtime.snapshot(self),
+            query_stats: Arc::clone(&self.query_stats),
+        })
+    }
+}
+
+impl panic::RefUnwindSafe for TestDB {}
+
+impl FileLoader for TestDB {
+    fn file_text(&self, file_id: FileId) -> Arc<String> {
+        FileLoaderDelegate(self).file_text(file_id)
+    }
+    fn resolve_relative_path(
+        &self,
+        anchor: FileId,
+        relative_path: &RelativePath,
+    ) -> Option<FileId> {
+        FileLoaderDelegate(self).resolve_relative_path(anchor, relative_path)
+    }
+    fn relevant_crates(&self, file_id: FileId) -> Arc<Vec<CrateId>> {
+        FileLoaderDelegate(self).relevant_crates(file_id)
+    }
+}
+
+/// Adds a 2018 crate without cfg options or env vars, which is all most tests
+/// need.
+pub(crate) fn add_crate(graph: &mut CrateGraph, root: FileId) -> CrateId {
+    graph.add_crate_root(root, Edition::Edition2018, CfgOptions::default(), Env::default())
+}
+
+/// The crate with the given root, which must be the only one.
+pub(crate) fn crate_of(graph: &CrateGraph, root: FileId) -> CrateId {
+    graph.crate_id_for_crate_root(root).unwrap()
+}
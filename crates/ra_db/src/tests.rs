@@ -0,0 +1,1910 @@
+use std::{
+    panic,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        mpsc, Arc, Mutex,
+    },
+    thread,
+};
+
+use ra_cfg::CfgOptions;
+use ra_syntax::{
+    ast::CommentShape, AstNode, ParserFeatures, SmolStr, SyntaxKind, TextRange, TextUnit,
+};
+use ra_text_edit::AtomTextEdit;
+use rustc_hash::{FxHashMap, FxHashSet};
+use salsa::{Database, Durability, ParallelDatabase};
+
+use crate::{
+    add_synthetic_file, edit_file,
+    fixture::{WithFixture, WORKSPACE},
+    for_each_crate_canceled, intern_anonymous_file, parse_or_recover, pin_files,
+    record_file_text_changes, set_file_is_generated, set_file_original_path, set_file_text_loader,
+    test_db::{add_crate, crate_of, TestDB},
+    touch_file, unpin_files, Canceled, CheckCanceled, CrateDepMetrics, CrateGraph, CrateId,
+    CrateOrigin, CrateStableId, Edition, EditionedFileId, FileId, FileLoadStatus, FileLoader,
+    FilePosition, FileRange, FileTextLoader, FileTextLoaderHandle, IndentStyle, InitialState,
+    InvalidEditError, ParseQuery, ParseTimings, RelativePath, RelativePathBuf, ResolutionContext,
+    SourceDatabase, SourceDatabaseExt, SourceRoot, SourceRootId, SourceRootKind,
+    ANONYMOUS_SOURCE_ROOT,
+};
+
+#[test]
+fn invariants_hold_for_fixture() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod foo;
+        //- /foo.rs
+        struct Foo;
+        ",
+    );
+    db.check_invariants();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(
+    expected = "is listed in SourceRootId(0), but its file_source_root is SourceRootId(1)"
+)]
+fn invariants_detect_wrong_source_root() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod foo;
+        //- /foo.rs
+        struct Foo;
+        ",
+    );
+    db.set_source_root(SourceRootId(1), Arc::new(SourceRoot::new()));
+    db.set_file_source_root(FileId(1), SourceRootId(1));
+    db.check_invariants();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(expected = "FileId(0) is not listed in its source root SourceRootId(1)")]
+fn invariants_detect_unlisted_crate_root() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod foo;
+        //- /foo.rs
+        struct Foo;
+        ",
+    );
+    let mut root = SourceRoot::new();
+    root.insert_file(db.file_relative_path(FileId(1)), FileId(1));
+    db.set_source_root(WORKSPACE, Arc::new(root));
+    db.set_source_root(SourceRootId(1), Arc::new(SourceRoot::new()));
+    db.set_file_source_root(FileId(0), SourceRootId(1));
+    db.check_invariants();
+}
+
+#[cfg(debug_assertions)]
+#[test]
+#[should_panic(
+    expected = "is rooted in a source root with is_library false, but is_member is false"
+)]
+fn invariants_detect_wrong_membership() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs
+        ",
+    );
+    let mut graph = CrateGraph::clone(&db.crate_graph());
+    graph.set_is_member(db.test_crate(), false);
+    db.set_crate_graph(Arc::new(graph));
+    db.check_invariants();
+}
+
+#[test]
+fn crate_graph_can_be_swapped_and_restored() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        //- /foo/lib.rs crate:foo
+        ",
+    );
+    let original = db.crate_graph();
+    let crates_before = db.source_root_crates(WORKSPACE);
+    assert_eq!(crates_before.len(), 2);
+
+    let mut speculative = CrateGraph::default();
+    add_crate(&mut speculative, FileId(0));
+    db.set_crate_graph(Arc::new(speculative));
+    assert_eq!(db.source_root_crates(WORKSPACE).len(), 1);
+
+    db.set_crate_graph(original);
+    assert_eq!(db.source_root_crates(WORKSPACE), crates_before);
+}
+
+fn subtree_kind(text: &str, start: &str, end: &str) -> Option<SyntaxKind> {
+    let (db, file_id) = TestDB::with_single_file(text);
+    let start = TextUnit::from_usize(text.find(start).unwrap());
+    let end = TextUnit::from_usize(text.find(end).unwrap() + end.len());
+    let range = TextRange::from_to(start, end);
+    db.parse_subtree(FileRange { file_id, range }).map(|it| it.kind())
+}
+
+#[test]
+fn parse_subtree_inside_function_body() {
+    let text = "fn foo() { let x = 92; }\nfn bar() {}\n";
+    assert_eq!(subtree_kind(text, "let", ";"), Some(SyntaxKind::LET_STMT));
+    assert_eq!(subtree_kind(text, "92", "92"), Some(SyntaxKind::LITERAL));
+}
+
+#[test]
+fn parse_subtree_spanning_two_items() {
+    let text = "fn foo() { let x = 92; }\nfn bar() {}\n";
+    assert_eq!(subtree_kind(text, "x", "bar"), Some(SyntaxKind::SOURCE_FILE));
+}
+
+#[test]
+fn parse_subtree_out_of_bounds() {
+    let (db, file_id) = TestDB::with_single_file("fn foo() {}");
+    let range = TextRange::offset_len(TextUnit::from_usize(5), TextUnit::from_usize(100));
+    assert_eq!(db.parse_subtree(FileRange { file_id, range }), None);
+}
+
+#[test]
+fn enclosing_item_range_finds_nested_fn() {
+    let text =
+        "fn outer() {\n    let x = 1;\n    fn inner() {\n        92\n    }\n}\n\nstruct S;\n";
+    let (db, file_id) = TestDB::with_single_file(text);
+    let item_range = |offset: usize| {
+        let position = FilePosition { file_id, offset: TextUnit::from_usize(offset) };
+        db.enclosing_item_range(position).map(|frange| &text[frange.range])
+    };
+    let inner = "fn inner() {\n        92\n    }";
+    assert_eq!(item_range(text.find("92").unwrap()), Some(inner));
+    assert_eq!(item_range(text.find("fn inner").unwrap()), Some(inner));
+    assert_eq!(item_range(text.find("let").unwrap()), Some(&text[..text.find("\n\n").unwrap()]));
+    assert_eq!(item_range(text.find("\n\n").unwrap() + 1), None);
+    assert_eq!(item_range(text.find("S;").unwrap()), Some("struct S;"));
+    assert_eq!(item_range(1000), None);
+}
+
+#[test]
+fn token_at_prefers_left_token() {
+    let text = "fn foo(x: u32) {}";
+    let (db, file_id) = TestDB::with_single_file(text);
+    let token_at = |offset: usize| {
+        let position = FilePosition { file_id, offset: TextUnit::from_usize(offset) };
+        db.token_at(position).map_or(String::new(), |token| token.text().to_string())
+    };
+    assert_eq!(token_at(0), "fn");
+    assert_eq!(token_at(1), "fn");
+    assert_eq!(token_at(text.find("foo").unwrap() + 1), "foo");
+    // Between `foo` and `(`, and between `(` and `x`.
+    assert_eq!(token_at(text.find('(').unwrap()), "foo");
+    assert_eq!(token_at(text.find('x').unwrap()), "(");
+    assert_eq!(token_at(text.len()), "}");
+    assert!(db.token_at(FilePosition { file_id, offset: 1000.into() }).is_none());
+
+    let (db, file_id) = TestDB::with_single_file("");
+    assert!(db.token_at(FilePosition { file_id, offset: 0.into() }).is_none());
+}
+
+#[test]
+fn module_file_follows_foo_rs_layout() {
+    let db = TestDB::with_files(
+        "
+        //- /lib.rs
+        mod foo;
+        //- /foo.rs
+        mod bar;
+        //- /foo/bar.rs
+        struct Bar;
+        ",
+    );
+    let krate = db.test_crate();
+    assert_eq!(db.module_file(krate, &[]), Some(FileId(0)));
+    assert_eq!(db.module_file(krate, &["foo".into()]), Some(FileId(1)));
+    assert_eq!(db.module_file(krate, &["foo".into(), "bar".into()]), Some(FileId(2)));
+    assert_eq!(db.module_file(krate, &["foo".into(), "baz".into()]), None);
+}
+
+#[test]
+fn module_file_follows_mod_rs_layout() {
+    let db = TestDB::with_files(
+        "
+        //- /lib.rs
+        mod foo;
+        //- /foo/mod.rs
+        mod bar;
+        //- /foo/bar/mod.rs
+        struct Bar;
+        ",
+    );
+    let krate = db.test_crate();
+    assert_eq!(db.module_file(krate, &["foo".into()]), Some(FileId(1)));
+    assert_eq!(db.module_file(krate, &["foo".into(), "bar".into()]), Some(FileId(2)));
+    assert_eq!(db.module_file(krate, &["bar".into()]), None);
+}
+
+#[test]
+fn is_crate_root_only_for_roots() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        mod bar;
+        //- /bar.rs
+        //- /foo/lib.rs crate:foo
+        ",
+    );
+    assert!(db.is_crate_root(FileId(0)));
+    assert!(!db.is_crate_root(FileId(1)));
+    assert!(db.is_crate_root(FileId(2)));
+}
+
+#[test]
+fn editioned_uses_crate_edition() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main edition:2015
+        //- /lib.rs crate:lib edition:2018
+        ",
+    );
+    assert_eq!(db.editioned(FileId(0)).edition(), Edition::Edition2015);
+    assert_eq!(db.editioned(FileId(0)).file_id(), FileId(0));
+    assert_eq!(db.editioned(FileId(1)).edition(), Edition::Edition2018);
+}
+
+#[test]
+fn editioned_file_id_equality_considers_both_fields() {
+    let file_2015 = EditionedFileId::new(FileId(0), Edition::Edition2015);
+    let file_2018 = EditionedFileId::new(FileId(0), Edition::Edition2018);
+    let other_file = EditionedFileId::new(FileId(1), Edition::Edition2015);
+    assert_eq!(file_2015, EditionedFileId::new(FileId(0), Edition::Edition2015));
+    assert_ne!(file_2015, file_2018);
+    assert_ne!(file_2015, other_file);
+}
+
+#[test]
+fn crate_source_roots_for_two_roots() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        //- root /foo/
+        //- /foo/lib.rs crate:foo
+        ",
+    );
+    let main = db.crate_graph().crate_id_for_crate_root(FileId(0)).unwrap();
+    let foo = db.crate_graph().crate_id_for_crate_root(FileId(1)).unwrap();
+    let mut expected = vec![(main, FileId(0), WORKSPACE), (foo, FileId(1), SourceRootId(1))];
+    expected.sort();
+    assert_eq!(*db.crate_source_roots(), expected);
+}
+
+#[test]
+fn query_guard_unwinds_on_cancellation() {
+    let mut db = TestDB::default();
+    let snapshot = db.snapshot();
+    let worker = thread::spawn(move || {
+        snapshot.catch_canceled(|db| loop {
+            let _guard = db.query_guard();
+        })
+    });
+    // Blocks until the worker gives up its snapshot.
+    db.salsa_runtime_mut().synthetic_write(Durability::LOW);
+    assert!(worker.join().unwrap().is_err());
+}
+
+fn header(text: &str) -> Option<&str> {
+    let (db, file_id) = TestDB::with_single_file(text);
+    let frange = db.file_header_range(file_id)?;
+    assert_eq!(frange.file_id, file_id);
+    Some(&text[frange.range])
+}
+
+#[test]
+fn file_header_range_covers_shebang_and_module_docs() {
+    assert_eq!(
+        header("#!/usr/bin/env run-cargo-script\n//! Docs.\n\n//! More docs.\nfn main() {}\n"),
+        Some("#!/usr/bin/env run-cargo-script\n//! Docs.")
+    );
+    assert_eq!(header("//! Docs.\n#![no_std]\n//! Not a header.\n"), Some("//! Docs."));
+    assert_eq!(
+        header("/*! Block docs. */\n//! Docs.\nfn main() {}\n"),
+        Some("/*! Block docs. */\n//! Docs.")
+    );
+    assert_eq!(header("//! Docs.\n// Not a header.\n"), Some("//! Docs."));
+    assert_eq!(header("//! Docs.\r\n\r\n//! Not a header.\r\n"), Some("//! Docs."));
+}
+
+#[test]
+fn file_header_range_none_for_code() {
+    assert_eq!(header("fn main() {}\n"), None);
+    assert_eq!(header("#![no_std]\n//! Docs.\n"), None);
+    assert_eq!(header("\n//! Docs.\n"), None);
+    assert_eq!(header("/// Docs.\nfn main() {}\n"), None);
+}
+
+#[test]
+fn query_stats_count_recomputations() {
+    let (mut db, file_id) = TestDB::with_single_file("fn foo() {}");
+    db.parse(file_id);
+    assert!(db.query_stats.recompute_counts().is_empty());
+
+    db.query_stats.set_enabled(true);
+    db.set_file_text(file_id, Arc::new("fn bar() {}".to_string()));
+    db.parse(file_id);
+    db.parse(file_id);
+    assert_eq!(db.query_stats.recompute_counts(), vec![("parse".to_string(), 1)]);
+
+    db.query_stats.clear();
+    db.query_stats.set_enabled(false);
+    db.set_file_text(file_id, Arc::new("fn baz() {}".to_string()));
+    db.parse(file_id);
+    assert!(db.query_stats.recompute_counts().is_empty());
+}
+
+#[test]
+fn file_text_len_is_in_bytes() {
+    let (mut db, file_id) = TestDB::with_single_file("fn main() {}");
+    assert_eq!(db.file_text_len(file_id), 12);
+    let text = "fn main() { \"привет\"; }";
+    db.set_file_text(file_id, Arc::new(text.to_string()));
+    assert_eq!(db.file_text_len(file_id), text.len());
+    assert_eq!(db.file_text_len(file_id), 29);
+}
+
+#[test]
+fn file_is_ascii() {
+    let (mut db, file_id) = TestDB::with_single_file("fn main() {}\n");
+    assert!(db.file_is_ascii(file_id));
+    db.set_file_text(file_id, Arc::new("fn main() { \"café\"; }\n".to_string()));
+    assert!(!db.file_is_ascii(file_id));
+}
+
+#[test]
+fn file_indent_style_is_inferred() {
+    let (mut db, file_id) =
+        TestDB::with_single_file("fn main() {\n\tif true {\n\t\tfoo();\n\t}\n}\n");
+    assert_eq!(db.file_indent_style(file_id), IndentStyle::Tabs);
+
+    let text = "/*\n * Header.\n */\nfn main() {\n  if true {\n    foo();\n  }\n}\n";
+    db.set_file_text(file_id, Arc::new(text.to_string()));
+    assert_eq!(db.file_indent_style(file_id), IndentStyle::Spaces(2));
+
+    db.set_file_text(file_id, Arc::new("struct S;\n".to_string()));
+    assert_eq!(db.file_indent_style(file_id), IndentStyle::Spaces(4));
+    db.set_file_text(file_id, Arc::new("fn f() {\n\ta();\n  b();\n}\n".to_string()));
+    assert_eq!(db.file_indent_style(file_id), IndentStyle::Spaces(4));
+}
+
+#[test]
+fn inline_const_needs_parser_feature() {
+    let (mut db, file_id) = TestDB::with_single_file("fn main() { let x = const { 92 }; }");
+    assert!(!db.parse(file_id).errors().is_empty());
+
+    db.set_parser_features(ParserFeatures { inline_const: true });
+    let parse = db.parse(file_id);
+    assert!(parse.errors().is_empty(), "{:?}", parse.errors());
+    assert!(parse.syntax_node().descendants().any(|it| it.kind() == SyntaxKind::BLOCK_EXPR
+        && it.first_token().map(|it| it.kind()) == Some(SyntaxKind::CONST_KW)));
+}
+
+#[test]
+fn file_path_components_splits_path() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod foo;
+        //- /foo/bar.rs
+        ",
+    );
+    assert_eq!(*db.file_path_components(FileId(0)), vec![SmolStr::new("main.rs")]);
+    assert_eq!(
+        *db.file_path_components(FileId(1)),
+        vec![SmolStr::new("foo"), SmolStr::new("bar.rs")]
+    );
+}
+
+#[test]
+fn for_each_crate_canceled_stops_on_cancellation() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        //- /foo/lib.rs crate:foo
+        ",
+    );
+    let snapshot = db.snapshot();
+    let (started_tx, started_rx) = mpsc::channel();
+    let worker = thread::spawn(move || {
+        let db = &*snapshot;
+        let mut visited = 0;
+        let res = for_each_crate_canceled(db, |_crate_id| {
+            visited += 1;
+            started_tx.send(()).unwrap();
+            while !db.salsa_runtime().is_current_revision_canceled() {
+                thread::yield_now();
+            }
+        });
+        (res, visited)
+    });
+    started_rx.recv().unwrap();
+    db.salsa_runtime_mut().synthetic_write(Durability::LOW);
+    let (res, visited) = worker.join().unwrap();
+    assert!(res.is_err());
+    assert_eq!(visited, 1);
+}
+
+/// Blocks in `load_text` until told to go on.
+struct BlockingLoader {
+    started: Mutex<mpsc::Sender<()>>,
+    go: Mutex<mpsc::Receiver<()>>,
+}
+
+impl FileTextLoader for BlockingLoader {
+    fn load_text(&self, file_id: FileId) -> String {
+        self.started.lock().unwrap().send(()).unwrap();
+        self.go.lock().unwrap().recv().unwrap();
+        format!("fn f{}() {{}}", file_id.0)
+    }
+}
+
+#[test]
+fn parse_root_shares_the_parse() {
+    let (db, file_id) = TestDB::with_single_file("fn foo() {}\nstruct S;\n");
+    db.query_stats.set_enabled(true);
+    let root = db.parse_root(file_id);
+    assert_eq!(root.green(), db.parse(file_id).tree().syntax().green());
+    assert_eq!(root.text().to_string(), "fn foo() {}\nstruct S;\n");
+    db.parse_root(file_id);
+    let counts = db.query_stats.recompute_counts();
+    assert!(counts.contains(&("parse".to_string(), 1)), "{:?}", counts);
+}
+
+#[test]
+fn last_parse_duration_is_recorded() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod foo;
+        //- /foo.rs
+        fn foo() {}
+        ",
+    );
+    let (main, foo) = (FileId(0), FileId(1));
+    assert_eq!(db.last_parse_duration(main), None);
+    db.parse(main);
+    assert!(db.last_parse_duration(main).is_some());
+    assert_eq!(db.last_parse_duration(foo), None);
+
+    db.set_parse_timings(ParseTimings::disabled());
+    db.parse(foo);
+    assert_eq!(db.last_parse_duration(foo), None);
+}
+
+#[test]
+fn parse_all_stops_on_cancellation() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod a;
+        mod b;
+        //- /a.rs
+        //- /b.rs
+        ",
+    );
+    let (started_tx, started_rx) = mpsc::channel();
+    let (go_tx, go_rx) = mpsc::channel();
+    let blocking = BlockingLoader { started: Mutex::new(started_tx), go: Mutex::new(go_rx) };
+    set_file_text_loader(&mut db, FileId(1), Some(FileTextLoaderHandle(Arc::new(blocking))));
+    let counting = Arc::new(CountingLoader::default());
+    set_file_text_loader(&mut db, FileId(2), Some(FileTextLoaderHandle(counting.clone())));
+    assert_eq!(db.parse_all(&[FileId(0)]).unwrap().len(), 1);
+
+    let snapshot = db.snapshot();
+    let worker = thread::spawn(move || snapshot.parse_all(&[FileId(0), FileId(1), FileId(2)]));
+    started_rx.recv().unwrap();
+
+    // The write waits for the worker, so it has to happen on another thread.
+    let observer = db.snapshot();
+    let writer = thread::spawn(move || db.salsa_runtime_mut().synthetic_write(Durability::LOW));
+    while !observer.salsa_runtime().is_current_revision_canceled() {
+        thread::yield_now();
+    }
+    drop(observer);
+    go_tx.send(()).unwrap();
+
+    assert!(worker.join().unwrap().is_err());
+    writer.join().unwrap();
+    assert_eq!(counting.loads.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn project_editions_are_deduplicated() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo,bar
+        //- /foo/lib.rs crate:foo edition:2015
+        //- /bar/lib.rs crate:bar edition:2018
+        ",
+    );
+    let mut editions = db.project_editions().iter().map(|it| it.to_string()).collect::<Vec<_>>();
+    editions.sort();
+    assert_eq!(editions, vec!["2015", "2018"]);
+
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        //- /foo/lib.rs crate:foo
+        ",
+    );
+    assert_eq!(db.project_editions().len(), 1);
+}
+
+#[test]
+fn first_parse_error_is_the_earliest_one() {
+    let (db, file_id) = TestDB::with_single_file("fn foo( {}\nfn bar() { 1 + }\n");
+    let first_line_end = TextUnit::from(11);
+    let parse = db.parse(file_id);
+    assert!(parse.errors().iter().any(|it| it.offset() >= first_line_end));
+    let range = db.first_parse_error(file_id).unwrap();
+    assert!(range.start() < first_line_end, "{:?}", range);
+
+    let (db, file_id) = TestDB::with_single_file("fn foo() {}\n");
+    assert_eq!(db.first_parse_error(file_id), None);
+}
+
+#[test]
+fn file_original_path_is_separate_from_relative_path() {
+    let (mut db, file_id) = TestDB::with_single_file("fn foo() {}");
+    assert_eq!(db.file_original_path(file_id), None);
+
+    let path = std::env::temp_dir().join("project").join("src").join("main.rs");
+    set_file_original_path(&mut db, file_id, Some(path.clone()));
+    assert_eq!(db.file_original_path(file_id), Some(path));
+    assert_eq!(db.file_relative_path(file_id), RelativePathBuf::from("/main.rs"));
+
+    let synthetic = add_synthetic_file(&mut db, Arc::new("struct S;".to_string()));
+    assert_eq!(db.file_original_path(synthetic), None);
+}
+
+#[test]
+fn file_is_generated_can_be_toggled() {
+    let (mut db, file_id) = TestDB::with_single_file("fn foo() {}");
+    assert!(!db.file_is_generated(file_id));
+
+    set_file_is_generated(&mut db, file_id, true);
+    assert!(db.file_is_generated(file_id));
+
+    set_file_is_generated(&mut db, file_id, false);
+    assert!(!db.file_is_generated(file_id));
+}
+
+#[test]
+fn stable_ids_come_from_root_paths() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        //- root /foo/
+        //- /foo/lib.rs crate:foo
+        ",
+    );
+    let mut library = SourceRoot::new_library();
+    library.insert_file(db.file_relative_path(FileId(1)), FileId(1));
+    db.set_source_root(SourceRootId(1), Arc::new(library));
+    let graph = db.crate_graph();
+    let main = graph.crate_id_for_crate_root(FileId(0)).unwrap();
+    let foo = graph.crate_id_for_crate_root(FileId(1)).unwrap();
+    assert_eq!(
+        CrateStableId::for_crate(&db, main),
+        CrateStableId { root_path: "main.rs".into(), kind: SourceRootKind::Local }
+    );
+    assert_eq!(CrateStableId::for_crate(&db, foo).kind, SourceRootKind::Library);
+
+    // The same crates, added the other way around.
+    let mut rebuilt = CrateGraph::default();
+    let foo2 = add_crate(&mut rebuilt, FileId(1));
+    let main2 = add_crate(&mut rebuilt, FileId(0));
+    rebuilt.add_dep(main2, SmolStr::new("foo"), foo2).unwrap();
+    rebuilt.assign_stable_ids(&db);
+    assert_eq!(rebuilt.stable_id(main2), Some(&CrateStableId::for_crate(&db, main)));
+
+    let mut graph = CrateGraph::clone(&graph);
+    graph.assign_stable_ids(&db);
+    let mapping = graph.renumber_by_stable_id();
+    let rebuilt_mapping = rebuilt.renumber_by_stable_id();
+    assert_eq!(mapping[&main], rebuilt_mapping[&main2]);
+    assert_eq!(mapping[&foo], rebuilt_mapping[&foo2]);
+}
+
+#[test]
+fn synthetic_files_get_fresh_ids() {
+    let (mut db, file_id) = TestDB::with_single_file("fn main() {}");
+    let a = add_synthetic_file(&mut db, Arc::new("fn a() {}".to_string()));
+    let b = add_synthetic_file(&mut db, Arc::new("struct B;".to_string()));
+    assert_ne!(a, b);
+    assert_ne!(a, file_id);
+    assert_eq!(*db.synthetic_files(), vec![a, b]);
+
+    assert_eq!(*SourceDatabaseExt::file_text(&db, b), "struct B;");
+    let parse = db.parse(a);
+    assert!(parse.errors().is_empty());
+    assert_eq!(parse.syntax_node().text().to_string(), "fn a() {}");
+    assert!(db.file_is_generated(a));
+}
+
+#[test]
+fn synthetic_files_resolve_no_paths() {
+    let (mut db, _) = TestDB::with_single_file("fn main() {}");
+    let file_id = add_synthetic_file(&mut db, Arc::new("mod foo;".to_string()));
+    assert_eq!(db.resolve_relative_path(file_id, RelativePath::new("main.rs")), None);
+    assert_eq!(db.resolve_relative_path(file_id, RelativePath::new("foo.rs")), None);
+    assert!(db.relevant_crates(file_id).is_empty());
+}
+
+#[test]
+fn crate_roots_under_prefix() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo,bar,baz
+        //- /crates/foo/lib.rs crate:foo
+        //- /crates/bar/src/lib.rs crate:bar
+        //- /crates2/baz/lib.rs crate:baz
+        ",
+    );
+    let graph = db.crate_graph();
+    let mut expected = vec![crate_of(&graph, FileId(1)), crate_of(&graph, FileId(2))];
+    expected.sort();
+    assert_eq!(*db.crate_roots_under(WORKSPACE, RelativePath::new("crates")), expected);
+    assert_eq!(
+        *db.crate_roots_under(WORKSPACE, RelativePath::new("crates/bar")),
+        vec![crate_of(&graph, FileId(2))]
+    );
+    assert_eq!(db.crate_roots_under(WORKSPACE, RelativePath::new("tests")).len(), 0);
+    assert_eq!(db.crate_roots_under(WORKSPACE, RelativePath::new("")).len(), 4);
+}
+
+#[test]
+fn dangling_roots_after_root_file_removal() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        //- /foo/lib.rs crate:foo
+        ",
+    );
+    let foo = db.crate_graph().crate_id_for_crate_root(FileId(1)).unwrap();
+    assert!(db.crate_graph().dangling_roots(&db).is_empty());
+
+    let mut source_root = SourceRoot::clone(&db.source_root(WORKSPACE));
+    source_root.remove_file(&db.file_relative_path(FileId(1)));
+    db.set_source_root(WORKSPACE, Arc::new(source_root));
+    assert_eq!(db.crate_graph().dangling_roots(&db), vec![foo]);
+}
+
+#[test]
+fn find_up_walks_to_the_source_root() {
+    let db = TestDB::with_files(
+        "
+        //- /Cargo.toml
+        //- /src/lib.rs crate:foo
+        mod foo;
+        //- /src/foo/mod.rs
+        mod bar;
+        //- /src/foo/bar.rs
+        //- /src/foo/rustfmt.toml
+        ",
+    );
+    assert_eq!(db.find_up(FileId(3), "Cargo.toml"), Some(FileId(0)));
+    assert_eq!(db.find_up(FileId(3), "rustfmt.toml"), Some(FileId(4)));
+    assert_eq!(db.find_up(FileId(1), "rustfmt.toml"), None);
+    assert_eq!(db.find_up(FileId(0), "Cargo.lock"), None);
+}
+
+#[test]
+fn parser_panic_turns_into_error_parse() {
+    let text = "fn main() {}";
+    let parse = parse_or_recover(text, || panic!("parser bug"));
+    assert_eq!(parse.syntax_node().text().to_string(), text);
+    assert_eq!(parse.syntax_node().kind(), SyntaxKind::SOURCE_FILE);
+    assert_eq!(parse.errors().len(), 1);
+
+    let parse = parse_or_recover(text, || ra_syntax::SourceFile::parse(text));
+    assert!(parse.errors().is_empty());
+}
+
+#[test]
+fn parser_cancellation_is_not_caught() {
+    let err = panic::catch_unwind(|| parse_or_recover("", || Canceled::throw())).unwrap_err();
+    assert!(err.is::<Canceled>());
+}
+
+#[test]
+fn crate_potential_cfg_falls_back_to_active_cfg() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        //- /foo/lib.rs crate:foo cfg:feature=std
+        ",
+    );
+    let foo = db.crate_graph().crate_id_for_crate_root(FileId(1)).unwrap();
+    let mut active = CfgOptions::default();
+    active.insert_features(vec![SmolStr::new("std")]);
+    assert_eq!(*db.crate_potential_cfg(foo), active);
+
+    let mut potential = active.clone();
+    potential.insert_features(vec![SmolStr::new("alloc"), SmolStr::new("serde")]);
+    let mut crate_graph = CrateGraph::clone(&db.crate_graph());
+    crate_graph.set_potential_cfg(foo, potential.clone());
+    db.set_crate_graph(Arc::new(crate_graph));
+    assert_eq!(*db.crate_potential_cfg(foo), potential);
+    assert_eq!(*db.crate_graph().cfg_options(foo), active);
+}
+
+#[test]
+fn effective_cfg_of_crate_files() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        //- /foo/lib.rs crate:foo cfg:feature=x
+        mod bar;
+        //- /foo/bar.rs
+        ",
+    );
+    let mut expected = CfgOptions::default();
+    expected.insert_features(vec![SmolStr::new("x")]);
+    assert_eq!(*db.effective_cfg(FileId(1)), expected);
+    assert_eq!(*db.effective_cfg(FileId(2)), expected);
+    assert_eq!(*db.effective_cfg(FileId(0)), CfgOptions::default());
+
+    let foo = db.crate_graph().crate_id_for_crate_root(FileId(1)).unwrap();
+    let mut crate_graph = CrateGraph::clone(&db.crate_graph());
+    crate_graph.set_cfg_test(foo, true);
+    db.set_crate_graph(Arc::new(crate_graph));
+    expected.insert_atom("test".into());
+    assert_eq!(*db.effective_cfg(FileId(2)), expected);
+}
+
+#[test]
+fn file_stable_key_roundtrip() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod foo;
+        //- /foo.rs
+        //- root /lib/
+        //- /lib/lib.rs
+        ",
+    );
+    for &file_id in [FileId(0), FileId(1), FileId(2)].iter() {
+        let (root, path) = db.file_stable_key(file_id);
+        assert_eq!(db.file_by_stable_key(root, &path), Some(file_id));
+    }
+    assert_eq!(db.file_stable_key(FileId(2)).0, SourceRootId(1));
+    assert_eq!(db.file_by_stable_key(WORKSPACE, RelativePath::new("/bar.rs")), None);
+    assert_eq!(db.file_by_stable_key(SourceRootId(1), &db.file_stable_key(FileId(1)).1), None);
+}
+
+#[test]
+fn crate_root_of_nested_module_file() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        mod util;
+        //- /util.rs
+        //- /foo/lib.rs crate:foo
+        mod bar;
+        //- /foo/bar/mod.rs
+        mod baz;
+        //- /foo/bar/baz.rs
+        //- root /other/
+        //- /other/orphan.rs
+        ",
+    );
+    assert_eq!(db.crate_root_of_file(FileId(4)), Some(FileId(2)));
+    assert_eq!(db.crate_root_of_file(FileId(3)), Some(FileId(2)));
+    assert_eq!(db.crate_root_of_file(FileId(2)), Some(FileId(2)));
+    assert_eq!(db.crate_root_of_file(FileId(1)), Some(FileId(0)));
+    assert_eq!(db.crate_root_of_file(FileId(5)), None);
+}
+
+#[test]
+fn crates_with_missing_roots_are_reported() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo
+        mod bar;
+        //- /bar.rs
+        //- /foo/lib.rs crate:foo
+        ",
+    );
+    assert!(db.crates_with_missing_roots().is_empty());
+    assert!(db.file_exists(FileId(1)));
+
+    let mut source_root = SourceRoot::clone(&db.source_root(WORKSPACE));
+    source_root.remove_file(&db.file_relative_path(FileId(1)));
+    db.set_source_root(WORKSPACE, Arc::new(source_root));
+    assert!(!db.file_exists(FileId(1)));
+    assert!(db.crates_with_missing_roots().is_empty());
+
+    let mut source_root = SourceRoot::clone(&db.source_root(WORKSPACE));
+    source_root.remove_file(&db.file_relative_path(FileId(0)));
+    db.set_source_root(WORKSPACE, Arc::new(source_root));
+    let main = db.crate_graph().crate_id_for_crate_root(FileId(0)).unwrap();
+    assert_eq!(*db.crates_with_missing_roots(), vec![main]);
+}
+
+#[test]
+fn prelude_crate_depends_on_no_std() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:std,core
+        //- /no_std.rs crate:no_std deps:std,core
+        #![no_std]
+        //- /no_core.rs crate:no_core deps:core
+        #![no_core]
+        //- /only_core.rs crate:only_core deps:core
+        //- /std/lib.rs crate:std deps:core
+        //- /core/lib.rs crate:core
+        ",
+    );
+    let graph = db.crate_graph();
+    let (std, core) = (crate_of(&graph, FileId(4)), crate_of(&graph, FileId(5)));
+    assert_eq!(db.prelude_crate(crate_of(&graph, FileId(0))), Some(std));
+    assert_eq!(db.prelude_crate(crate_of(&graph, FileId(1))), Some(core));
+    assert_eq!(db.prelude_crate(crate_of(&graph, FileId(2))), None);
+    assert_eq!(db.prelude_crate(crate_of(&graph, FileId(3))), Some(core));
+    assert_eq!(db.prelude_crate(std), Some(core));
+    assert_eq!(db.prelude_crate(core), None);
+}
+
+#[test]
+fn sibling_crates_share_a_source_root() {
+    let db = TestDB::with_files(
+        "
+        //- /src/lib.rs crate:lib
+        //- /src/bin/foo.rs crate:foo deps:lib
+        //- /src/bin/bar.rs crate:bar deps:lib
+        //- root /dep/
+        //- /dep/lib.rs crate:dep
+        ",
+    );
+    let graph = db.crate_graph();
+    let (lib, foo, bar, dep) = (
+        crate_of(&graph, FileId(0)),
+        crate_of(&graph, FileId(1)),
+        crate_of(&graph, FileId(2)),
+        crate_of(&graph, FileId(3)),
+    );
+    let sorted = |mut crates: Vec<CrateId>| {
+        crates.sort();
+        crates
+    };
+    assert_eq!(*db.sibling_crates(lib), sorted(vec![foo, bar]));
+    assert_eq!(*db.sibling_crates(foo), sorted(vec![lib, bar]));
+    assert!(db.sibling_crates(dep).is_empty());
+}
+
+#[test]
+fn uncancellable_scope_runs_to_completion() {
+    let mut db = TestDB::default();
+    let snapshot = db.snapshot();
+    let worker = thread::spawn(move || {
+        let completed = snapshot.uncancellable(|| {
+            while !snapshot.salsa_runtime().is_current_revision_canceled() {
+                thread::yield_now();
+            }
+            snapshot.check_canceled();
+            true
+        });
+        let canceled_after = snapshot.catch_canceled(|db| db.check_canceled()).is_err();
+        (completed, canceled_after)
+    });
+    // Blocks until the worker gives up its snapshot.
+    db.salsa_runtime_mut().synthetic_write(Durability::LOW);
+    assert_eq!(worker.join().unwrap(), (true, true));
+}
+
+#[derive(Default)]
+struct CountingLoader {
+    loads: AtomicUsize,
+}
+
+impl FileTextLoader for CountingLoader {
+    fn load_text(&self, file_id: FileId) -> String {
+        self.loads.fetch_add(1, Ordering::SeqCst);
+        format!("fn f{}() {{}}", file_id.0)
+    }
+}
+
+#[test]
+fn lazy_file_text_is_loaded_once() {
+    let (mut db, file_id) = TestDB::with_single_file("");
+    let loader = Arc::new(CountingLoader::default());
+    set_file_text_loader(&mut db, file_id, Some(FileTextLoaderHandle(loader.clone())));
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 0);
+
+    assert_eq!(*FileLoader::file_text(&db, file_id), "fn f0() {}");
+    assert!(db.parse(file_id).errors().is_empty());
+    assert_eq!(db.file_text_len(file_id), 10);
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+
+    set_file_text_loader(&mut db, file_id, None);
+    db.set_file_text(file_id, Arc::new("struct S;".to_string()));
+    assert_eq!(*FileLoader::file_text(&db, file_id), "struct S;");
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+}
+
+struct MissingLoader;
+
+impl FileTextLoader for MissingLoader {
+    fn load_text(&self, _file_id: FileId) -> String {
+        String::new()
+    }
+    fn is_missing(&self, _file_id: FileId) -> bool {
+        true
+    }
+}
+
+#[test]
+fn file_load_status() {
+    let (mut db, file_id) = TestDB::with_single_file("");
+    assert_eq!(db.file_load_status(file_id), FileLoadStatus::Empty);
+
+    db.set_file_text(file_id, Arc::new("struct S;".to_string()));
+    assert_eq!(db.file_load_status(file_id), FileLoadStatus::Loaded);
+
+    let loader = Arc::new(CountingLoader::default());
+    set_file_text_loader(&mut db, file_id, Some(FileTextLoaderHandle(loader)));
+    assert_eq!(db.file_load_status(file_id), FileLoadStatus::Loaded);
+
+    set_file_text_loader(&mut db, file_id, Some(FileTextLoaderHandle(Arc::new(MissingLoader))));
+    assert_eq!(db.file_load_status(file_id), FileLoadStatus::Missing);
+    assert_eq!(*FileLoader::file_text(&db, file_id), "");
+}
+
+#[test]
+fn resolve_relative_path_through_symlinks() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod foo;
+        //- /foo.rs
+        //- /vendor/dep/lib.rs
+        ",
+    );
+    let mut source_root = SourceRoot::clone(&db.source_root(WORKSPACE));
+    source_root.insert_symlink("/vendor".into());
+    db.set_source_root(WORKSPACE, Arc::new(source_root));
+
+    let vendored = RelativePath::new("vendor/dep/lib.rs");
+    assert_eq!(db.resolve_relative_path(FileId(0), vendored), Some(FileId(2)));
+    assert_eq!(db.resolve_relative_path_with(FileId(0), vendored, true), Some(FileId(2)));
+    assert_eq!(db.resolve_relative_path_with(FileId(0), vendored, false), None);
+
+    let regular = RelativePath::new("foo.rs");
+    assert_eq!(db.resolve_relative_path_with(FileId(0), regular, false), Some(FileId(1)));
+}
+
+#[test]
+fn source_root_edition_requires_agreement() {
+    let db = TestDB::with_files(
+        "
+        //- /src/lib.rs crate:lib edition:2015
+        //- /src/bin/foo.rs crate:foo edition:2015
+        //- root /mixed/
+        //- /mixed/a.rs crate:a edition:2015
+        //- /mixed/b.rs crate:b edition:2018
+        //- root /empty/
+        //- /empty/x.rs
+        ",
+    );
+    assert_eq!(db.source_root_edition(WORKSPACE), Some(Edition::Edition2015));
+    assert_eq!(db.source_root_edition(SourceRootId(1)), None);
+    assert_eq!(db.source_root_edition(SourceRootId(2)), None);
+}
+
+#[test]
+fn touched_file_is_reparsed() {
+    let (mut db, file_id) = TestDB::with_single_file("fn foo() {}");
+    db.parse(file_id);
+
+    db.query_stats.set_enabled(true);
+    db.parse(file_id);
+    assert!(db.query_stats.recompute_counts().is_empty());
+
+    touch_file(&mut db, file_id);
+    db.parse(file_id);
+    assert_eq!(db.query_stats.recompute_counts(), vec![("parse".to_string(), 1)]);
+    assert_eq!(*SourceDatabaseExt::file_text(&db, file_id), "fn foo() {}");
+}
+
+#[test]
+fn touched_lazy_file_is_loaded_again() {
+    let (mut db, _) = TestDB::with_single_file("fn main() {}");
+    // Only a loader, no `file_text`.
+    let file_id = FileId(7);
+    db.set_file_relative_path(file_id, "/lazy.rs".into());
+    db.set_file_source_root(file_id, WORKSPACE);
+    let loader = Arc::new(CountingLoader::default());
+    set_file_text_loader(&mut db, file_id, Some(FileTextLoaderHandle(loader.clone())));
+    assert_eq!(*FileLoader::file_text(&db, file_id), "fn f7() {}");
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 1);
+
+    touch_file(&mut db, file_id);
+    assert_eq!(*FileLoader::file_text(&db, file_id), "fn f7() {}");
+    assert_eq!(loader.loads.load(Ordering::SeqCst), 2);
+}
+
+#[test]
+fn anonymous_files_are_not_reachable_by_path() {
+    let (mut db, main) = TestDB::with_single_file("mod foo;");
+    let file_id = intern_anonymous_file(&mut db, Arc::new("struct Foo;".to_string()));
+    assert_eq!(*SourceDatabaseExt::file_text(&db, file_id), "struct Foo;");
+    assert!(db.parse(file_id).errors().is_empty());
+    assert!(db.file_relative_path(file_id).as_str().is_empty());
+    assert!(db.source_root(ANONYMOUS_SOURCE_ROOT).walk().next().is_none());
+
+    assert_eq!(db.resolve_relative_path(main, RelativePath::new("")), None);
+    assert_eq!(db.resolve_relative_path(file_id, RelativePath::new("main.rs")), None);
+
+    let other = add_synthetic_file(&mut db, Arc::new(String::new()));
+    assert_ne!(other, file_id);
+}
+
+#[test]
+fn file_comment_ranges_distinguish_shapes() {
+    let text = "//! docs\nfn foo() { /* block */ }\n/* multi\nline */\nstruct S; // trailing\n";
+    let (db, file_id) = TestDB::with_single_file(text);
+    let comments = db
+        .file_comment_ranges(file_id)
+        .iter()
+        .map(|(frange, shape)| {
+            assert_eq!(frange.file_id, file_id);
+            (&text[frange.range], *shape)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(
+        comments,
+        vec![
+            ("//! docs", CommentShape::Line),
+            ("/* block */", CommentShape::Block),
+            ("/* multi\nline */", CommentShape::Block),
+            ("// trailing", CommentShape::Line),
+        ]
+    );
+}
+
+#[test]
+fn crates_by_file_count_puts_biggest_first() {
+    let db = TestDB::with_files(
+        "
+        //- /lib.rs crate:small
+        //- root /big/
+        //- /big/lib.rs crate:big
+        //- /big/foo.rs
+        //- /big/bar.rs
+        //- root /medium/
+        //- /medium/lib.rs crate:medium
+        //- /medium/foo.rs
+        ",
+    );
+    let graph = db.crate_graph();
+    let (small, big, medium) =
+        (crate_of(&graph, FileId(0)), crate_of(&graph, FileId(1)), crate_of(&graph, FileId(4)));
+    assert_eq!(graph.crates_by_file_count(&db), vec![(big, 3), (medium, 2), (small, 1)]);
+}
+
+#[test]
+fn file_ends_with_newline() {
+    let (mut db, file_id) = TestDB::with_single_file("");
+    assert!(!db.file_ends_with_newline(file_id));
+    for &(text, expected) in
+        [("\n", true), ("fn main() {}", false), ("fn main() {}\r\n", true)].iter()
+    {
+        db.set_file_text(file_id, Arc::new(text.to_string()));
+        assert_eq!(db.file_ends_with_newline(file_id), expected, "{:?}", text);
+    }
+}
+
+#[test]
+fn parse_tree_size_grows_with_the_file() {
+    let (mut db, file_id) = TestDB::with_single_file("fn foo() {}");
+    let small = db.parse_tree_size(file_id);
+    assert!(small > 0);
+    db.set_file_text(
+        file_id,
+        Arc::new(
+            "fn foo() {}\nfn bar(x: u32) -> u32 { x + 1 }\nstruct S { field: i32 }".to_string(),
+        ),
+    );
+    assert!(db.parse_tree_size(file_id) > small);
+}
+
+#[test]
+fn all_dependency_edges_are_sorted_by_crate() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:std,foo
+        //- /foo.rs crate:foo deps:std
+        //- /std.rs crate:std
+        ",
+    );
+    let graph = db.crate_graph();
+    let (main, foo, std) =
+        (crate_of(&graph, FileId(0)), crate_of(&graph, FileId(1)), crate_of(&graph, FileId(2)));
+    let mut expected = vec![
+        (main, std, SmolStr::new("std")),
+        (main, foo, SmolStr::new("foo")),
+        (foo, std, SmolStr::new("std")),
+    ];
+    expected.sort_by_key(|&(from, _, _)| from);
+    assert_eq!(*db.all_dependency_edges(), expected);
+}
+
+#[test]
+fn file_range_from_positions() {
+    let pos =
+        |file_id, offset: u32| FilePosition { file_id: FileId(file_id), offset: offset.into() };
+    assert_eq!(
+        FileRange::from_positions(pos(0, 1), pos(0, 4)),
+        Some(FileRange { file_id: FileId(0), range: TextRange::from_to(1.into(), 4.into()) })
+    );
+    assert_eq!(
+        FileRange::from_positions(pos(0, 2), pos(0, 2)),
+        Some(FileRange { file_id: FileId(0), range: TextRange::offset_len(2.into(), 0.into()) })
+    );
+    assert_eq!(FileRange::from_positions(pos(0, 4), pos(0, 1)), None);
+    assert_eq!(FileRange::from_positions(pos(0, 1), pos(1, 4)), None);
+}
+
+#[test]
+fn mixed_line_endings() {
+    let (mut db, file_id) = TestDB::with_single_file("");
+    let cases = [
+        ("fn main() {}", false),
+        ("fn main() {\n}\n", false),
+        ("fn main() {\r\n}\r\n", false),
+        ("fn main() {\r\n}\n", true),
+        ("\n\r\n", true),
+    ];
+    for &(text, expected) in cases.iter() {
+        db.set_file_text(file_id, Arc::new(text.to_string()));
+        assert_eq!(db.mixed_line_endings(file_id), expected, "{:?}", text);
+    }
+}
+
+#[test]
+fn inferred_edition_depends_on_origin() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main edition:2015
+        //- /lib.rs crate:lib edition:2015
+        //- /dep.rs crate:dep edition:2018
+        //- /core.rs crate:core edition:2015
+        ",
+    );
+    let mut graph = CrateGraph::clone(&db.crate_graph());
+    let main = graph.crate_id_for_crate_root(FileId(0)).unwrap();
+    let lib = graph.crate_id_for_crate_root(FileId(1)).unwrap();
+    let dep = graph.crate_id_for_crate_root(FileId(2)).unwrap();
+    let core = graph.crate_id_for_crate_root(FileId(3)).unwrap();
+    for &crate_id in [lib, dep, core].iter() {
+        graph.mark_edition_unknown(crate_id);
+    }
+    graph.set_is_member(dep, false);
+    graph.set_is_member(core, false);
+    graph.set_is_lang(core, true);
+    db.set_crate_graph(Arc::new(graph));
+
+    assert_eq!(db.inferred_edition(main), Edition::Edition2015);
+    assert_eq!(db.inferred_edition(lib), Edition::Edition2018);
+    assert_eq!(db.crate_graph().edition(lib), Edition::Edition2015);
+    assert_eq!(db.inferred_edition(dep), Edition::Edition2015);
+    assert_eq!(db.inferred_edition(core), Edition::Edition2018);
+}
+
+#[test]
+fn crate_reachable_files_follow_mod_declarations() {
+    let db = TestDB::with_files(
+        "
+        //- /lib.rs crate:lib
+        mod foo;
+        mod bar;
+        mod missing;
+        mod inline {}
+        //- /foo/mod.rs
+        mod baz;
+        //- /foo/baz.rs
+        //- /bar.rs
+        //- /unrelated.rs
+        //- /inline.rs
+        ",
+    );
+    let lib = db.crate_graph().crate_id_for_crate_root(FileId(0)).unwrap();
+    let expected: FxHashSet<_> =
+        [FileId(0), FileId(1), FileId(2), FileId(3)].iter().copied().collect();
+    assert_eq!(*db.crate_reachable_files(lib), expected);
+}
+
+#[test]
+fn crate_cfg_test_can_be_toggled() {
+    let (mut db, file_id) = TestDB::with_single_file("#[cfg(test)] mod tests {}");
+    let krate = db.crate_graph().crate_id_for_crate_root(file_id).unwrap();
+    assert!(!db.crate_cfg_test(krate));
+
+    for &cfg_test in [true, false].iter() {
+        let mut graph = CrateGraph::clone(&db.crate_graph());
+        graph.set_cfg_test(krate, cfg_test);
+        db.set_crate_graph(Arc::new(graph));
+        assert_eq!(db.crate_cfg_test(krate), cfg_test);
+    }
+}
+
+#[test]
+fn quiet_panic_hook_only_silences_cancellation() {
+    static ORIGINAL_HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+    static CANCELED_HOOK_CALLS: AtomicUsize = AtomicUsize::new(0);
+    const MARKER: &str = "quiet_panic_hook_only_silences_cancellation";
+
+    let default_hook = Arc::new(panic::take_hook());
+    let hook = default_hook.clone();
+    panic::set_hook(Box::new(move |info| {
+        if info.payload().is::<Canceled>() {
+            CANCELED_HOOK_CALLS.fetch_add(1, Ordering::SeqCst);
+        } else if info.payload().downcast_ref::<String>().map(String::as_str) == Some(MARKER) {
+            ORIGINAL_HOOK_CALLS.fetch_add(1, Ordering::SeqCst);
+        } else {
+            hook(info)
+        }
+    }));
+    crate::set_quiet_panic_hook();
+    crate::set_quiet_panic_hook();
+
+    assert!(panic::catch_unwind(|| panic!("{}", MARKER)).is_err());
+    assert!(panic::catch_unwind(|| panic::panic_any(Canceled::new())).is_err());
+    assert_eq!(ORIGINAL_HOOK_CALLS.load(Ordering::SeqCst), 1);
+    assert_eq!(CANCELED_HOOK_CALLS.load(Ordering::SeqCst), 0);
+
+    crate::restore_panic_hook();
+    assert!(panic::catch_unwind(|| panic::panic_any(Canceled::new())).is_err());
+    assert_eq!(CANCELED_HOOK_CALLS.load(Ordering::SeqCst), 1);
+
+    panic::set_hook(Box::new(move |info| default_hook(info)));
+}
+
+#[test]
+fn crate_dep_metrics() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo,std
+        //- /foo.rs crate:foo deps:std
+        //- /std.rs crate:std deps:core
+        //- /core.rs crate:core
+        ",
+    );
+    let graph = db.crate_graph();
+    assert_eq!(
+        db.crate_dep_metrics(crate_of(&graph, FileId(0))),
+        CrateDepMetrics { direct: 2, transitive: 3, max_depth: 3 }
+    );
+    assert_eq!(
+        db.crate_dep_metrics(crate_of(&graph, FileId(3))),
+        CrateDepMetrics { direct: 0, transitive: 0, max_depth: 0 }
+    );
+}
+
+#[test]
+fn crate_import_name_replaces_hyphens() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:serde-json
+        //- /lib.rs crate:serde-json
+        ",
+    );
+    let mut graph = CrateGraph::clone(&db.crate_graph());
+    let main = graph.crate_id_for_crate_root(FileId(0)).unwrap();
+    let serde_json = graph.crate_id_for_crate_root(FileId(1)).unwrap();
+    graph.set_display_name(serde_json, "serde-json".to_string());
+    db.set_crate_display_data(Arc::new(graph.display_data()));
+    db.set_crate_graph(Arc::new(graph));
+
+    assert_eq!(db.crate_import_name(serde_json), Some(SmolStr::new("serde_json")));
+    assert_eq!(db.crate_import_name(main), None);
+}
+
+#[test]
+fn resolve_path_attr_mod_from_nested_file() {
+    let db = TestDB::with_files(
+        "
+        //- /src/lib.rs crate:foo
+        mod nested;
+        //- /src/nested/mod.rs
+        #[path = \"../shared/mod.rs\"]
+        mod shared;
+        //- /src/nested/inner.rs
+        //- /src/shared/mod.rs
+        //- /src/nested/sub/custom.rs
+        ",
+    );
+    let nested = FileId(1);
+    let inner = FileId(2);
+    assert_eq!(db.resolve_path_attr_mod(nested, "../shared/mod.rs"), Some(FileId(3)));
+    assert_eq!(db.resolve_path_attr_mod(inner, "../shared/mod.rs"), Some(FileId(3)));
+    assert_eq!(db.resolve_path_attr_mod(inner, "sub/custom.rs"), Some(FileId(4)));
+    assert_eq!(db.resolve_path_attr_mod(inner, "./inner.rs"), Some(inner));
+    assert_eq!(db.resolve_path_attr_mod(inner, "../missing.rs"), None);
+}
+
+#[test]
+fn files_changed_since_revision() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod foo;
+        mod bar;
+        //- /foo.rs
+        //- /bar.rs
+        ",
+    );
+    let start = db.file_text_revisions().current();
+    assert!(db.files_changed_since(start).is_empty());
+
+    db.set_file_text(FileId(2), Arc::new("struct Bar;".to_string()));
+    db.set_file_text(FileId(0), Arc::new("mod foo; mod bar; fn main() {}".to_string()));
+    record_file_text_changes(&mut db, vec![FileId(2), FileId(0)]);
+    assert_eq!(db.files_changed_since(start), vec![FileId(0), FileId(2)]);
+
+    let later = db.file_text_revisions().current();
+    assert!(db.files_changed_since(later).is_empty());
+    db.set_file_text(FileId(1), Arc::new("struct Foo;".to_string()));
+    record_file_text_changes(&mut db, vec![FileId(1)]);
+    assert_eq!(db.files_changed_since(later), vec![FileId(1)]);
+    assert_eq!(db.files_changed_since(start), vec![FileId(0), FileId(1), FileId(2)]);
+
+    let later = db.file_text_revisions().current();
+    touch_file(&mut db, FileId(2));
+    edit_file(&mut db, FileId(1), vec![AtomTextEdit::insert(0.into(), "pub ".to_string())])
+        .unwrap();
+    assert_eq!(db.files_changed_since(later), vec![FileId(1), FileId(2)]);
+}
+
+#[test]
+fn disk_loader_reads_library_file() {
+    use std::fs;
+
+    use crate::DiskFileTextLoader;
+
+    let dir = std::env::temp_dir().join(format!("ra_db_disk_loader_{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("lib.rs");
+    fs::write(&path, "pub fn from_disk() {}\n").unwrap();
+
+    let (mut db, file_id) = TestDB::with_single_file("");
+    let mut loader = DiskFileTextLoader::new();
+    loader.add_file(file_id, path);
+    set_file_text_loader(&mut db, file_id, Some(FileTextLoaderHandle(Arc::new(loader))));
+
+    assert_eq!(*FileLoader::file_text(&db, file_id), "pub fn from_disk() {}\n");
+    let parse = db.parse(file_id);
+    assert!(parse.errors().is_empty());
+    assert_eq!(parse.tree().syntax().children().count(), 1);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn unresolved_mods_reports_missing_files() {
+    let db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod present;
+        mod missing;
+        mod inline {}
+        //- /present.rs
+        mod nested;
+        mod gone;
+        //- /present/nested.rs
+        ",
+    );
+    let main_text = SourceDatabaseExt::file_text(&db, FileId(0));
+    let start = TextUnit::from_usize(main_text.find("mod missing;").unwrap());
+    let range = TextRange::offset_len(start, TextUnit::of_str("mod missing;"));
+    assert_eq!(*db.unresolved_mods(FileId(0)), vec![(range, "missing".to_string())]);
+
+    let names =
+        db.unresolved_mods(FileId(1)).iter().map(|(_, name)| name.clone()).collect::<Vec<_>>();
+    assert_eq!(names, vec!["gone".to_string()]);
+    assert!(db.unresolved_mods(FileId(2)).is_empty());
+}
+
+#[test]
+fn crates_reachable_from_open_files() {
+    let db = TestDB::with_files(
+        "
+        //- /bin/main.rs crate:bin deps:lib
+        mod cli;
+        //- /bin/cli.rs
+        //- root /lib/
+        //- /lib/lib.rs crate:lib deps:core
+        //- root /core/
+        //- /core/lib.rs crate:core
+        //- root /other/
+        //- /other/main.rs crate:other deps:core
+        ",
+    );
+    let graph = db.crate_graph();
+    let (bin, lib, core, other) = (
+        crate_of(&graph, FileId(0)),
+        crate_of(&graph, FileId(2)),
+        crate_of(&graph, FileId(3)),
+        crate_of(&graph, FileId(4)),
+    );
+
+    let reachable = graph.crates_reachable_from(&db, &[FileId(1)]);
+    assert_eq!(reachable, [bin, lib, core].iter().copied().collect::<FxHashSet<_>>());
+    let reachable = graph.crates_reachable_from(&db, &[FileId(2), FileId(4)]);
+    assert_eq!(reachable, [lib, core, other].iter().copied().collect::<FxHashSet<_>>());
+    assert!(graph.crates_reachable_from(&db, &[]).is_empty());
+}
+
+#[test]
+fn source_root_for_path_picks_innermost_root() {
+    let mut db = TestDB::with_files(
+        "
+        //- /ws/src/lib.rs crate:ws
+        //- root /ws/vendor/
+        //- /ws/vendor/dep/lib.rs
+        //- root /ws/vendor/dep/nested/
+        //- /ws/vendor/dep/nested/lib.rs
+        ",
+    );
+    let root_of = |db: &TestDB, path: &str| db.source_root_for_path(RelativePath::new(path));
+    assert_eq!(root_of(&db, "/ws/src/lib.rs"), Some(SourceRootId(0)));
+    assert_eq!(root_of(&db, "/ws/vendor/dep/lib.rs"), Some(SourceRootId(1)));
+    assert_eq!(root_of(&db, "/ws/vendor/dep/nested/lib.rs"), Some(SourceRootId(2)));
+    assert_eq!(root_of(&db, "ws/vendor/dep/nested/new.rs"), Some(SourceRootId(2)));
+    // Whole components only.
+    assert_eq!(root_of(&db, "/ws/vendor2/lib.rs"), Some(SourceRootId(0)));
+
+    let mut paths = FxHashMap::clone(&db.source_root_paths());
+    paths.remove(&SourceRootId(0));
+    db.set_source_root_paths(Arc::new(paths));
+    assert_eq!(root_of(&db, "/ws/src/lib.rs"), None);
+    assert_eq!(root_of(&db, "/ws/vendor/dep/lib.rs"), Some(SourceRootId(1)));
+}
+
+#[test]
+fn file_is_integration_test_looks_at_top_directory() {
+    let db = TestDB::with_files(
+        "
+        //- /src/lib.rs crate:foo
+        //- /src/foo.rs
+        //- /tests/foo.rs
+        //- /benches/bench.rs
+        //- /src/tests/foo.rs
+        //- /tests.rs
+        ",
+    );
+    let is_test = |file_id| db.file_is_integration_test(FileId(file_id));
+    assert!(!is_test(0));
+    assert!(!is_test(1));
+    assert!(is_test(2));
+    assert!(is_test(3));
+    assert!(!is_test(4));
+    assert!(!is_test(5));
+}
+
+#[test]
+fn crate_roots_in_source_root_lists_lib_and_bin() {
+    let db = TestDB::with_files(
+        "
+        //- /src/main.rs crate:bin deps:foo
+        //- /src/lib.rs crate:foo
+        //- root /dep/
+        //- /dep/lib.rs crate:dep
+        ",
+    );
+    let graph = db.crate_graph();
+    let bin = graph.crate_id_for_crate_root(FileId(0)).unwrap();
+    let lib = graph.crate_id_for_crate_root(FileId(1)).unwrap();
+    let dep = graph.crate_id_for_crate_root(FileId(2)).unwrap();
+    let mut expected = vec![bin, lib];
+    expected.sort();
+    assert_eq!(*db.crate_roots_in_source_root(SourceRootId(0)), expected);
+    assert_eq!(*db.crate_roots_in_source_root(SourceRootId(1)), vec![dep]);
+}
+
+#[test]
+fn pinned_files_survive_parse_lru() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs
+        mod a;
+        mod b;
+        //- /a.rs
+        struct A;
+        //- /b.rs
+        struct B;
+        ",
+    );
+    db.query_mut(ParseQuery).set_lru_capacity(1);
+    pin_files(&mut db, &[FileId(0)]);
+    assert!(db.file_is_pinned(FileId(0)));
+    db.parse(FileId(0));
+    db.parse(FileId(1));
+    db.parse(FileId(2));
+
+    db.query_stats.set_enabled(true);
+    db.parse(FileId(0));
+    let counts = db.query_stats.recompute_counts();
+    assert!(!counts.iter().any(|(name, _)| name == "pinned_parse"), "{:?}", counts);
+
+    unpin_files(&mut db, &[FileId(0)]);
+    db.query_stats.clear();
+    db.parse(FileId(0));
+    assert_eq!(
+        db.query_stats.recompute_counts(),
+        vec![("file_is_pinned".to_string(), 1), ("parse".to_string(), 1)]
+    );
+    // Pinning another file doesn't invalidate the tree.
+    pin_files(&mut db, &[FileId(1)]);
+    db.query_stats.clear();
+    db.parse(FileId(0));
+    assert_eq!(db.query_stats.recompute_counts(), vec![("file_is_pinned".to_string(), 1)]);
+}
+
+#[test]
+fn module_file_candidates_finds_ambiguity() {
+    let db = TestDB::with_files(
+        "
+        //- /lib.rs
+        mod foo;
+        mod bar;
+        //- /foo.rs
+        mod inner;
+        //- /foo/mod.rs
+        //- /bar/mod.rs
+        //- /foo/inner.rs
+        ",
+    );
+    assert_eq!(*db.module_file_candidates(FileId(0), "foo"), vec![FileId(1), FileId(2)]);
+    assert_eq!(*db.module_file_candidates(FileId(0), "bar"), vec![FileId(3)]);
+    assert!(db.module_file_candidates(FileId(0), "baz").is_empty());
+    assert_eq!(*db.module_file_candidates(FileId(1), "inner"), vec![FileId(4)]);
+}
+
+#[test]
+fn resolution_context_of_crate_files() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:foo edition:2015
+        mod cli;
+        //- /cli.rs
+        //- root /foo/
+        //- /foo/lib.rs crate:foo
+        //- root /orphan/
+        //- /orphan/lone.rs
+        ",
+    );
+    let graph = db.crate_graph();
+    let main = graph.crate_id_for_crate_root(FileId(0)).unwrap();
+    let foo = graph.crate_id_for_crate_root(FileId(2)).unwrap();
+    let position = |file_id| FilePosition { file_id: FileId(file_id), offset: 0.into() };
+
+    assert_eq!(
+        db.resolution_context(position(1)),
+        Some(ResolutionContext {
+            crate_id: main,
+            edition: Edition::Edition2015,
+            source_root: SourceRootId(0),
+            is_library: false,
+        })
+    );
+    let mut foo_root = SourceRoot::clone(&db.source_root(SourceRootId(1)));
+    foo_root.is_library = true;
+    db.set_source_root(SourceRootId(1), Arc::new(foo_root));
+    assert_eq!(
+        db.resolution_context(position(2)),
+        Some(ResolutionContext {
+            crate_id: foo,
+            edition: Edition::Edition2018,
+            source_root: SourceRootId(1),
+            is_library: true,
+        })
+    );
+    assert_eq!(db.resolution_context(position(3)), None);
+}
+
+#[test]
+fn initial_state_sets_up_two_crates() {
+    let mut state = InitialState::new();
+    state.add_root(SourceRootId(0), false);
+    state.add_root(SourceRootId(1), true);
+    let text = |it: &str| Arc::new(it.to_string());
+    state.add_file(SourceRootId(0), FileId(0), "src/main.rs".into(), text("mod cli;"));
+    state.add_file(SourceRootId(0), FileId(1), "src/cli.rs".into(), text("fn run() {}"));
+    state.add_file(SourceRootId(1), FileId(2), "lib.rs".into(), text("pub struct Dep;"));
+    let mut graph = CrateGraph::default();
+    let bin = add_crate(&mut graph, FileId(0));
+    let dep = add_crate(&mut graph, FileId(2));
+    graph.add_dep(bin, SmolStr::new("dep"), dep).unwrap();
+    state.set_crate_graph(graph);
+
+    let mut db = TestDB::default();
+    db.set_parser_features(ParserFeatures::default());
+    db.set_parse_timings(Default::default());
+    db.set_pinned_files(Default::default());
+    db.set_lazy_files(Default::default());
+    state.apply(&mut db);
+
+    assert!(db.parse(FileId(1)).errors().is_empty());
+    assert_eq!(db.module_file(bin, &[SmolStr::new("cli")]), Some(FileId(1)));
+    assert_eq!(*db.relevant_crates(FileId(1)), vec![bin]);
+    assert_eq!(db.file_crate(FileId(2)), Some(dep));
+    assert!(db.source_root(SourceRootId(1)).is_library);
+    assert_eq!(
+        db.crate_graph().dependencies(bin).map(|it| it.crate_id).collect::<Vec<_>>(),
+        vec![dep]
+    );
+}
+
+#[test]
+fn crates_by_origin_groups_crates() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:serde,std
+        //- /serde/lib.rs crate:serde deps:std
+        //- /std/lib.rs crate:std
+        //- /core/lib.rs crate:core
+        ",
+    );
+    let mut graph = CrateGraph::clone(&db.crate_graph());
+    let (main, serde, std, core) = (
+        crate_of(&graph, FileId(0)),
+        crate_of(&graph, FileId(1)),
+        crate_of(&graph, FileId(2)),
+        crate_of(&graph, FileId(3)),
+    );
+    graph.set_is_member(serde, false);
+    graph.set_is_member(std, false);
+    graph.set_is_lang(std, true);
+    graph.set_is_member(core, false);
+    graph.set_is_lang(core, true);
+    db.set_crate_graph(Arc::new(graph));
+
+    let by_origin = db.crates_by_origin();
+    assert_eq!(by_origin.len(), 3);
+    assert_eq!(by_origin[&CrateOrigin::Member], vec![main]);
+    assert_eq!(by_origin[&CrateOrigin::Dependency], vec![serde]);
+    let mut lang = vec![std, core];
+    lang.sort();
+    assert_eq!(by_origin[&CrateOrigin::Lang], lang);
+
+    let mut graph = CrateGraph::clone(&db.crate_graph());
+    graph.set_is_member(serde, true);
+    db.set_crate_graph(Arc::new(graph));
+    assert!(!db.crates_by_origin().contains_key(&CrateOrigin::Dependency));
+}
+
+#[test]
+fn rebase_file_range_through_edit() {
+    // In "fn foo() {}\nfn bar() {}\nfn baz() {}\n".
+    let file_id = FileId(0);
+    let frange = |start: u32, end: u32| FileRange {
+        file_id,
+        range: TextRange::from_to(start.into(), end.into()),
+    };
+    // Replaces `bar` with `quux`.
+    let edit = AtomTextEdit::replace(TextRange::from_to(15.into(), 18.into()), "quux".to_string());
+
+    let before = frange(3, 6);
+    assert_eq!(before.rebase(&edit), Some(before));
+    assert_eq!(frange(27, 30).rebase(&edit), Some(frange(28, 31)));
+    assert_eq!(frange(12, 23).rebase(&edit), Some(frange(12, 24)));
+    assert_eq!(frange(15, 18).rebase(&edit), None);
+    assert_eq!(frange(16, 17).rebase(&edit), None);
+    assert_eq!(frange(12, 16).rebase(&edit), None);
+
+    let insert = AtomTextEdit::insert(12.into(), "\n".to_string());
+    assert_eq!(frange(12, 23).rebase(&insert), Some(frange(12, 24)));
+    assert_eq!(frange(0, 12).rebase(&insert), Some(frange(0, 12)));
+    assert_eq!(frange(12, 12).rebase(&insert), Some(frange(12, 12)));
+    assert_eq!(frange(3, 12).rebase(&insert), Some(frange(3, 12)));
+}
+
+#[test]
+fn cross_kind_edges_between_workspace_and_libraries() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:util,serde
+        //- /util/lib.rs crate:util deps:serde
+        //- root /serde/
+        //- /serde/lib.rs crate:serde deps:derive
+        //- /serde/derive.rs crate:derive
+        ",
+    );
+    let mut serde_root = SourceRoot::clone(&db.source_root(SourceRootId(1)));
+    serde_root.is_library = true;
+    db.set_source_root(SourceRootId(1), Arc::new(serde_root));
+
+    let graph = db.crate_graph();
+    let (main, util, serde) =
+        (crate_of(&graph, FileId(0)), crate_of(&graph, FileId(1)), crate_of(&graph, FileId(2)));
+    let mut expected = vec![
+        (main, serde, SourceRootKind::Local, SourceRootKind::Library),
+        (util, serde, SourceRootKind::Local, SourceRootKind::Library),
+    ];
+    expected.sort_by_key(|&(from, to, _, _)| (from, to));
+    assert_eq!(graph.cross_kind_edges(&db), expected);
+}
+
+#[test]
+fn crates_missing_std_skips_no_std_and_lang_crates() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:std,tool
+        //- /tool/lib.rs crate:tool
+        //- /kernel/lib.rs crate:kernel deps:core
+        #![no_std]
+        //- /std/lib.rs crate:std deps:core
+        //- /core/lib.rs crate:core
+        #![no_core]
+        ",
+    );
+    let mut graph = CrateGraph::clone(&db.crate_graph());
+    let (main, tool, kernel) =
+        (crate_of(&graph, FileId(0)), crate_of(&graph, FileId(1)), crate_of(&graph, FileId(2)));
+    let (std, core) = (crate_of(&graph, FileId(3)), crate_of(&graph, FileId(4)));
+    for &(crate_id, name) in [(std, "std"), (core, "core")].iter() {
+        graph.set_is_member(crate_id, false);
+        graph.set_is_lang(crate_id, true);
+        graph.set_display_name(crate_id, name.to_string());
+    }
+    db.set_crate_display_data(Arc::new(graph.display_data()));
+    db.set_crate_graph(Arc::new(graph));
+
+    assert!(db.crate_is_no_std(kernel));
+    assert!(db.crate_is_no_std(core));
+    assert!(!db.crate_is_no_std(main));
+    assert_eq!(*db.crates_missing_std(), vec![tool]);
+}
+
+#[test]
+fn is_reachable_from_workspace_follows_library_deps() {
+    let mut db = TestDB::with_files(
+        "
+        //- /main.rs crate:main deps:serde
+        //- /serde/lib.rs crate:serde deps:serde_derive
+        //- /serde_derive/lib.rs crate:serde_derive
+        //- /old/lib.rs crate:old deps:unused
+        //- /unused/lib.rs crate:unused
+        ",
+    );
+    let mut graph = CrateGraph::clone(&db.crate_graph());
+    let (main, serde, serde_derive) =
+        (crate_of(&graph, FileId(0)), crate_of(&graph, FileId(1)), crate_of(&graph, FileId(2)));
+    let (old, unused) = (crate_of(&graph, FileId(3)), crate_of(&graph, FileId(4)));
+    for &crate_id in [serde, serde_derive, old, unused].iter() {
+        graph.set_is_member(crate_id, false);
+    }
+    db.set_crate_graph(Arc::new(graph));
+
+    assert!(db.is_reachable_from_workspace(main));
+    assert!(db.is_reachable_from_workspace(serde));
+    assert!(db.is_reachable_from_workspace(serde_derive));
+    assert!(!db.is_reachable_from_workspace(old));
+    assert!(!db.is_reachable_from_workspace(unused));
+}
+
+#[test]
+fn project_fingerprint_ignores_dependency_order() {
+    let fixture = |deps: &str| {
+        format!(
+            "
+            //- /main.rs crate:main deps:{}
+            //- /a/lib.rs crate:a
+            //- /b/lib.rs crate:b edition:2015
+            ",
+            deps
+        )
+    };
+    let mut db = TestDB::with_files(&fixture("a,b"));
+    let fingerprint = db.project_fingerprint();
+    assert_eq!(TestDB::with_files(&fixture("b,a")).project_fingerprint(), fingerprint);
+    assert_ne!(TestDB::with_files(&fixture("a")).project_fingerprint(), fingerprint);
+
+    db.set_file_text(FileId(0), Arc::new("fn main() {}".to_string()));
+    assert_eq!(db.project_fingerprint(), fingerprint);
+
+    let mut graph = CrateGraph::clone(&db.crate_graph());
+    let b = graph.crate_id_for_crate_root(FileId(2)).unwrap();
+    graph.set_display_name(b, "b".to_string());
+    db.set_crate_graph(Arc::new(graph.clone()));
+    assert_eq!(db.project_fingerprint(), fingerprint);
+    graph.set_cfg_test(b, true);
+    db.set_crate_graph(Arc::new(graph));
+    assert_ne!(db.project_fingerprint(), fingerprint);
+}
+
+#[test]
+fn edit_file_applies_edits_in_order() {
+    let (mut db, file_id) = TestDB::with_single_file("fn foo() {}\n");
+    edit_file(
+        &mut db,
+        file_id,
+        vec![
+            AtomTextEdit::insert(11.into(), "\nfn bar() {}".to_string()),
+            AtomTextEdit::delete(TextRange::from_to(0.into(), 12.into())),
+        ],
+    )
+    .unwrap();
+    assert_eq!(*FileLoader::file_text(&db, file_id), "fn bar() {}\n");
+    let parse = db.parse(file_id);
+    assert!(parse.errors().is_empty());
+    assert_eq!(parse.tree().syntax().text().to_string(), "fn bar() {}\n");
+
+    edit_file(&mut db, file_id, vec![AtomTextEdit::insert(7.into(), "x: u32".to_string())])
+        .unwrap();
+    assert_eq!(*FileLoader::file_text(&db, file_id), "fn bar(x: u32) {}\n");
+    assert!(db.parse(file_id).errors().is_empty());
+}
+
+#[test]
+fn files_referencing_crate_finds_uses_and_extern_crates() {
+    let db = TestDB::with_files(
+        "
+        //- /lib.rs
+        extern crate serde;
+        mod de;
+        mod ser;
+        mod other;
+        //- /de.rs
+        use serde::de::{Deserialize, Deserializer};
+        //- /ser.rs
+        fn f() {
+            use {std::fmt, serde::Serialize};
+        }
+        //- /other.rs
+        use serde_json::Value;
+        use crate::serde;
+        fn g() -> serde::Value { serde::Value }
+        //- root /vendor/
+        //- /vendor/lib.rs
+        use serde::Serialize;
+        ",
+    );
+    let files = db.files_referencing_crate("serde", WORKSPACE);
+    assert_eq!(files, vec![FileId(0), FileId(1), FileId(2)]);
+    assert_eq!(db.files_referencing_crate("serde", SourceRootId(1)), vec![FileId(4)]);
+    assert!(db.files_referencing_crate("rand", WORKSPACE).is_empty());
+}
+
+#[test]
+fn edit_file_rejects_invalid_ranges() {
+    let (mut db, file_id) = TestDB::with_single_file("fn ö() {}\n");
+    let insert = AtomTextEdit::insert(0.into(), "pub ".to_string());
+
+    let past_end = AtomTextEdit::delete(TextRange::from_to(8.into(), 20.into()));
+    assert_eq!(
+        edit_file(&mut db, file_id, vec![insert.clone(), past_end]),
+        Err(InvalidEditError { file_id, index: 1, range: TextRange::from_to(8.into(), 20.into()) })
+    );
+    // Inside of `ö`, which takes two bytes.
+    let mid_char = AtomTextEdit::insert(4.into(), "x".to_string());
+    assert!(edit_file(&mut db, file_id, vec![mid_char]).is_err());
+    assert_eq!(*FileLoader::file_text(&db, file_id), "fn ö() {}\n");
+}
+
+#[test]
+fn lib_rs_module_which_is_not_a_crate_root() {
+    let db = TestDB::with_files(
+        "
+        //- /src/main.rs crate:main
+        mod lib;
+        //- /src/lib.rs
+        mod inner;
+        //- /src/lib/inner.rs
+        //- /src/inner.rs
+        ",
+    );
+    let krate = db.crate_graph().crate_id_for_crate_root(FileId(0)).unwrap();
+    assert_eq!(db.module_file(krate, &["lib".into(), "inner".into()]), Some(FileId(2)));
+    assert_eq!(*db.module_file_candidates(FileId(1), "inner"), vec![FileId(2)]);
+    assert!(db.unresolved_mods(FileId(1)).is_empty());
+}
@@ -1,14 +1,14 @@
 //! FIXME: write short doc here
 
-use std::{fmt, sync::Arc, time};
+use std::{fmt, path::PathBuf, sync::Arc, time};
 
 use ra_db::{
     salsa::{Database, Durability, SweepStrategy},
-    CrateGraph, CrateId, FileId, RelativePathBuf, SourceDatabase, SourceDatabaseExt, SourceRoot,
-    SourceRootId,
+    CrateGraph, CrateId, FileId, FileMetadata, RelativePathBuf, SourceDatabase, SourceDatabaseExt,
+    SourceRoot, SourceRootId,
 };
 use ra_prof::{memory_usage, profile, Bytes};
-use ra_syntax::SourceFile;
+use ra_syntax::{ParserFeatures, SourceFile};
 #[cfg(not(feature = "wasm"))]
 use rayon::prelude::*;
 use rustc_hash::FxHashMap;
@@ -23,8 +23,12 @@ pub struct AnalysisChange {
     new_roots: Vec<(SourceRootId, bool)>,
     roots_changed: FxHashMap<SourceRootId, RootChange>,
     files_changed: Vec<(FileId, Arc<String>)>,
+    files_generated: Vec<(FileId, bool)>,
+    original_paths: Vec<(FileId, PathBuf)>,
     libraries_added: Vec<LibraryData>,
     crate_graph: Option<CrateGraph>,
+    parser_features: Option<ParserFeatures>,
+    root_paths: FxHashMap<SourceRootId, RelativePathBuf>,
     debug_data: DebugData,
 }
 
@@ -40,12 +44,24 @@ impl fmt::Debug for AnalysisChange {
         if !self.files_changed.is_empty() {
             d.field("files_changed", &self.files_changed.len());
         }
+        if !self.files_generated.is_empty() {
+            d.field("files_generated", &self.files_generated);
+        }
+        if !self.original_paths.is_empty() {
+            d.field("original_paths", &self.original_paths);
+        }
         if !self.libraries_added.is_empty() {
             d.field("libraries_added", &self.libraries_added.len());
         }
         if !self.crate_graph.is_none() {
             d.field("crate_graph", &self.crate_graph);
         }
+        if let Some(parser_features) = &self.parser_features {
+            d.field("parser_features", parser_features);
+        }
+        if !self.root_paths.is_empty() {
+            d.field("root_paths", &self.root_paths);
+        }
         d.finish()
     }
 }
@@ -74,6 +90,16 @@ impl AnalysisChange {
         self.files_changed.push((file_id, new_text))
     }
 
+    pub fn set_file_is_generated(&mut self, file_id: FileId, is_generated: bool) {
+        self.files_generated.push((file_id, is_generated))
+    }
+
+    /// Records the absolute path the file was loaded from, see
+    /// `SourceDatabaseExt::file_original_path`.
+    pub fn set_file_original_path(&mut self, file_id: FileId, path: PathBuf) {
+        self.original_paths.push((file_id, path))
+    }
+
     pub fn remove_file(&mut self, root_id: SourceRootId, file_id: FileId, path: RelativePathBuf) {
         let file = RemoveFile { file_id, path };
         self.roots_changed.entry(root_id).or_default().removed.push(file);
@@ -87,6 +113,15 @@ impl AnalysisChange {
         self.crate_graph = Some(graph);
     }
 
+    pub fn set_parser_features(&mut self, features: ParserFeatures) {
+        self.parser_features = Some(features);
+    }
+
+    /// Sets the directory of the source root, used by `source_root_for_path`.
+    pub fn set_root_path(&mut self, source_root_id: SourceRootId, path: RelativePathBuf) {
+        self.root_paths.insert(source_root_id, path);
+    }
+
     pub fn set_debug_crate_name(&mut self, crate_id: CrateId, name: String) {
         self.debug_data.crate_names.insert(crate_id, name);
     }
@@ -124,6 +159,25 @@ impl fmt::Debug for RootChange {
     }
 }
 
+/// Files added to or removed from a source root by an `AnalysisChange`, as
+/// passed to the sink of `AnalysisHost::set_source_root_change_sink`. Both
+/// lists are sorted by `FileId`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SourceRootChange {
+    pub root: SourceRootId,
+    pub added: Vec<FileId>,
+    pub removed: Vec<FileId>,
+}
+
+#[derive(Clone)]
+pub(crate) struct SourceRootChangeSink(pub(crate) Arc<dyn Fn(SourceRootChange) + Send + Sync>);
+
+impl fmt::Debug for SourceRootChangeSink {
+    fn fmt(&self, fmt: &mut fmt::Formatter) -> fmt::Result {
+        fmt.write_str("SourceRootChangeSink")
+    }
+}
+
 pub struct LibraryData {
     root_id: SourceRootId,
     root_change: RootChange,
@@ -189,11 +243,29 @@ impl RootDatabase {
         for (root_id, root_change) in change.roots_changed {
             self.apply_root_change(root_id, root_change);
         }
+        let mut changed_files = Vec::with_capacity(change.files_changed.len());
         for (file_id, text) in change.files_changed {
             let source_root_id = self.file_source_root(file_id);
             let source_root = self.source_root(source_root_id);
             let durability = durability(&source_root);
-            self.set_file_text_with_durability(file_id, text, durability)
+            self.set_file_text_with_durability(file_id, text, durability);
+            ra_db::set_file_text_loader(self, file_id, None);
+            changed_files.push(file_id);
+        }
+        ra_db::record_file_text_changes(self, changed_files);
+        if !change.files_generated.is_empty() || !change.original_paths.is_empty() {
+            let mut metadata = FileMetadata::clone(&self.file_metadata());
+            for (file_id, is_generated) in change.files_generated {
+                if is_generated {
+                    metadata.generated.insert(file_id);
+                } else {
+                    metadata.generated.remove(&file_id);
+                }
+            }
+            metadata.original_paths.extend(change.original_paths);
+            // Like the roots, this changes with the project rather than while
+            // typing.
+            self.set_file_metadata_with_durability(Arc::new(metadata), Durability::HIGH)
         }
         if !change.libraries_added.is_empty() {
             let mut libraries = Vec::clone(&self.library_roots());
@@ -214,17 +286,38 @@ impl RootDatabase {
             self.set_library_roots_with_durability(Arc::new(libraries), Durability::HIGH);
         }
         if let Some(crate_graph) = change.crate_graph {
-            self.set_crate_graph_with_durability(Arc::new(crate_graph), Durability::HIGH)
+            let display_data = crate_graph.display_data();
+            if *self.crate_display_data() != display_data {
+                self.set_crate_display_data_with_durability(
+                    Arc::new(display_data),
+                    Durability::HIGH,
+                )
+            }
+            // Renames and version bumps only touch `crate_display_data`, so
+            // nothing which depends on the graph itself is invalidated.
+            if !self.crate_graph().semantically_eq(&crate_graph) {
+                self.set_crate_graph_with_durability(Arc::new(crate_graph), Durability::HIGH)
+            }
+        }
+        if let Some(parser_features) = change.parser_features {
+            self.set_parser_features_with_durability(parser_features, Durability::HIGH)
+        }
+        if !change.root_paths.is_empty() {
+            let mut root_paths = FxHashMap::clone(&self.source_root_paths());
+            root_paths.extend(change.root_paths);
+            self.set_source_root_paths_with_durability(Arc::new(root_paths), Durability::HIGH)
         }
 
         Arc::make_mut(&mut self.debug_data).merge(change.debug_data)
     }
 
     fn apply_root_change(&mut self, root_id: SourceRootId, root_change: RootChange) {
-        let mut source_root = SourceRoot::clone(&self.source_root(root_id));
+        let old_source_root = self.source_root(root_id);
+        let mut source_root = SourceRoot::clone(&old_source_root);
         let durability = durability(&source_root);
         for add_file in root_change.added {
             self.set_file_text_with_durability(add_file.file_id, add_file.text, durability);
+            ra_db::set_file_text_loader(self, add_file.file_id, None);
             self.set_file_relative_path_with_durability(
                 add_file.file_id,
                 add_file.path.clone(),
@@ -237,6 +330,16 @@ impl RootDatabase {
             self.set_file_text_with_durability(remove_file.file_id, Default::default(), durability);
             source_root.remove_file(&remove_file.path);
         }
+        if let Some(sink) = &self.source_root_change_sink {
+            let diff = old_source_root.diff(&source_root);
+            if !diff.added.is_empty() || !diff.removed.is_empty() {
+                (sink.0)(SourceRootChange {
+                    root: root_id,
+                    added: diff.added,
+                    removed: diff.removed,
+                });
+            }
+        }
         self.set_source_root_with_durability(root_id, Arc::new(source_root), durability);
     }
 
@@ -4,14 +4,16 @@ use std::sync::Arc;
 
 use ra_db::{
     salsa::{self, Database, Durability},
-    Canceled, CheckCanceled, CrateId, FileId, FileLoader, FileLoaderDelegate, RelativePath,
-    SourceDatabase, SourceDatabaseExt, SourceRootId,
+    Canceled, CheckCanceled, CrateId, FileId, FileLoader, FileLoaderDelegate, FilePosition,
+    QueryStats, RelativePath, SourceDatabase, SourceDatabaseExt, SourceRootId,
 };
+use ra_syntax::TextUnit;
 use rustc_hash::FxHashMap;
 
 use crate::{
+    change::SourceRootChangeSink,
     symbol_index::{self, SymbolsDatabase},
-    FeatureFlags, LineIndex,
+    FeatureFlags, LineCol, LineIndex,
 };
 
 #[salsa::database(
@@ -29,6 +31,8 @@ pub(crate) struct RootDatabase {
     runtime: salsa::Runtime<RootDatabase>,
     pub(crate) feature_flags: Arc<FeatureFlags>,
     pub(crate) debug_data: Arc<DebugData>,
+    pub(crate) query_stats: Arc<QueryStats>,
+    pub(crate) source_root_change_sink: Option<SourceRootChangeSink>,
     pub(crate) last_gc: crate::wasm_shims::Instant,
     pub(crate) last_gc_check: crate::wasm_shims::Instant,
 }
@@ -72,6 +76,7 @@ impl salsa::Database for RootDatabase {
         Canceled::throw()
     }
     fn salsa_event(&self, event: impl Fn() -> salsa::Event<RootDatabase>) {
+        self.query_stats.record(&event);
         match event().kind {
             salsa::EventKind::DidValidateMemoizedValue { .. }
             | salsa::EventKind::WillExecute { .. } => {
@@ -96,12 +101,29 @@ impl RootDatabase {
             last_gc_check: crate::wasm_shims::Instant::now(),
             feature_flags: Arc::new(feature_flags),
             debug_data: Default::default(),
+            query_stats: Default::default(),
+            source_root_change_sink: None,
         };
         db.set_crate_graph_with_durability(Default::default(), Durability::HIGH);
+        db.set_crate_display_data_with_durability(Default::default(), Durability::HIGH);
+        db.set_parser_features_with_durability(Default::default(), Durability::HIGH);
+        let parse_timings = if cfg!(feature = "wasm") {
+            ra_db::ParseTimings::disabled()
+        } else {
+            Default::default()
+        };
+        db.set_parse_timings_with_durability(parse_timings, Durability::HIGH);
+        db.set_synthetic_files_with_durability(Default::default(), Durability::HIGH);
+        db.set_pinned_files_with_durability(Default::default(), Durability::LOW);
+        db.set_lazy_files_with_durability(Default::default(), Durability::HIGH);
+        db.set_source_root_paths_with_durability(Default::default(), Durability::HIGH);
+        db.set_file_text_revisions_with_durability(Default::default(), Durability::LOW);
+        db.set_file_metadata_with_durability(Default::default(), Durability::HIGH);
         db.set_local_roots_with_durability(Default::default(), Durability::HIGH);
         db.set_library_roots_with_durability(Default::default(), Durability::HIGH);
         let lru_capacity = lru_capacity.unwrap_or(ra_db::DEFAULT_LRU_CAP);
         db.query_mut(ra_db::ParseQuery).set_lru_capacity(lru_capacity);
+        db.query_mut(ra_db::LazyFileTextQuery).set_lru_capacity(lru_capacity);
         db.query_mut(hir::db::ParseMacroQuery).set_lru_capacity(lru_capacity);
         db.query_mut(hir::db::MacroExpandQuery).set_lru_capacity(lru_capacity);
         db
@@ -116,6 +138,8 @@ impl salsa::ParallelDatabase for RootDatabase {
             last_gc_check: self.last_gc_check,
             feature_flags: Arc::clone(&self.feature_flags),
             debug_data: Arc::clone(&self.debug_data),
+            query_stats: Arc::clone(&self.query_stats),
+            source_root_change_sink: self.source_root_change_sink.clone(),
         })
     }
 }
@@ -123,6 +147,24 @@ impl salsa::ParallelDatabase for RootDatabase {
 #[salsa::query_group(LineIndexDatabaseStorage)]
 pub(crate) trait LineIndexDatabase: ra_db::SourceDatabase + CheckCanceled {
     fn line_index(&self, file_id: FileId) -> Arc<LineIndex>;
+
+    /// Line and utf-16 column of the position. Offsets past the end of the file
+    /// are clamped to the end.
+    #[salsa::transparent]
+    fn position_to_line_col(&self, position: FilePosition) -> LineCol;
+
+    /// Inverse of `position_to_line_col`. Lines past the end of the file and
+    /// columns past the end of a line are clamped.
+    #[salsa::transparent]
+    fn line_col_to_position(&self, file_id: FileId, line_col: LineCol) -> FilePosition;
+
+    /// Text of the line the position is on, without the line ending. Offsets
+    /// past the end of the file are clamped to the end.
+    #[salsa::transparent]
+    fn line_text_at(&self, position: FilePosition) -> Arc<str>;
+
+    /// Sum of the line counts of the files in the module tree of the crate.
+    fn crate_line_count(&self, crate_id: CrateId) -> usize;
 }
 
 fn line_index(db: &impl LineIndexDatabase, file_id: FileId) -> Arc<LineIndex> {
@@ -130,6 +172,63 @@ fn line_index(db: &impl LineIndexDatabase, file_id: FileId) -> Arc<LineIndex> {
     Arc::new(LineIndex::new(&*text))
 }
 
+fn position_to_line_col(db: &impl LineIndexDatabase, position: FilePosition) -> LineCol {
+    let len = TextUnit::of_str(&db.file_text(position.file_id));
+    db.line_index(position.file_id).line_col(position.offset.min(len))
+}
+
+fn line_text_at(db: &impl LineIndexDatabase, position: FilePosition) -> Arc<str> {
+    let text = db.file_text(position.file_id);
+    let line_index = db.line_index(position.file_id);
+    let len = TextUnit::of_str(&text);
+    let line = line_index.line_col(position.offset.min(len)).line as usize;
+    let start = line_index.newlines[line];
+    let end = line_index.newlines.get(line + 1).copied().unwrap_or(len);
+    let mut line = &text[start.to_usize()..end.to_usize()];
+    if line.ends_with('\n') {
+        line = &line[..line.len() - 1];
+    }
+    if line.ends_with('\r') {
+        line = &line[..line.len() - 1];
+    }
+    Arc::from(line)
+}
+
+fn crate_line_count(db: &impl LineIndexDatabase, crate_id: CrateId) -> usize {
+    db.crate_reachable_files(crate_id)
+        .iter()
+        .map(|&file_id| db.line_index(file_id).line_count())
+        .sum()
+}
+
+fn line_col_to_position(
+    db: &impl LineIndexDatabase,
+    file_id: FileId,
+    line_col: LineCol,
+) -> FilePosition {
+    let text = db.file_text(file_id);
+    let len = TextUnit::of_str(&text);
+    let line_index = db.line_index(file_id);
+    let last_line = line_index.newlines.len() as u32 - 1;
+    let offset = if line_col.line > last_line {
+        len
+    } else {
+        // The start of the line terminator, be it `\n` or `\r\n`.
+        let line_end = match line_index.newlines.get(line_col.line as usize + 1) {
+            Some(&next_line_start) => {
+                let mut line_end = next_line_start - TextUnit::of_char('\n');
+                if text[..line_end.to_usize()].ends_with('\r') {
+                    line_end -= TextUnit::of_char('\r');
+                }
+                line_end
+            }
+            None => len,
+        };
+        line_index.offset(line_col).min(line_end)
+    };
+    FilePosition { file_id, offset }
+}
+
 #[derive(Debug, Default, Clone)]
 pub(crate) struct DebugData {
     pub(crate) root_paths: FxHashMap<SourceRootId, String>,
@@ -142,3 +241,165 @@ impl DebugData {
         self.crate_names.extend(other.crate_names.into_iter());
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use ra_db::{FileId, FilePosition, SourceRootId};
+    use ra_syntax::TextUnit;
+
+    use crate::{
+        mock_analysis::{single_file, MockAnalysis},
+        AnalysisChange, AnalysisHost, LineCol, SourceRootChange,
+    };
+
+    #[test]
+    fn position_line_col_roundtrip() {
+        let (analysis, file_id) = single_file("fn foo() {}\nfn bar() {}");
+        let position = FilePosition { file_id, offset: TextUnit::from(15) };
+        let line_col = analysis.position_to_line_col(position).unwrap();
+        assert_eq!(line_col, LineCol { line: 1, col_utf16: 3 });
+        assert_eq!(analysis.line_col_to_position(file_id, line_col).unwrap(), position);
+    }
+
+    #[test]
+    fn position_line_col_clamps_out_of_range() {
+        let (analysis, file_id) = single_file("fn foo() {}\nfn bar() {}");
+        let end = FilePosition { file_id, offset: TextUnit::from(23) };
+        let past_end = FilePosition { file_id, offset: TextUnit::from(100) };
+        assert_eq!(
+            analysis.position_to_line_col(past_end).unwrap(),
+            LineCol { line: 1, col_utf16: 11 }
+        );
+
+        let past_last_line = LineCol { line: 10, col_utf16: 0 };
+        assert_eq!(analysis.line_col_to_position(file_id, past_last_line).unwrap(), end);
+        let past_line_end = LineCol { line: 0, col_utf16: 100 };
+        let line_end = FilePosition { file_id, offset: TextUnit::from(11) };
+        assert_eq!(analysis.line_col_to_position(file_id, past_line_end).unwrap(), line_end);
+
+        let (analysis, file_id) = single_file("fn foo() {}\r\nfn bar() {}");
+        let line_end = FilePosition { file_id, offset: TextUnit::from(11) };
+        assert_eq!(analysis.line_col_to_position(file_id, past_line_end).unwrap(), line_end);
+    }
+
+    #[test]
+    fn line_text_at_strips_line_endings() {
+        let (analysis, file_id) = single_file("fn foo() {}\r\nfn bar() {}\n\nfn baz() {}");
+        let line_at = |offset: u32| {
+            let position = FilePosition { file_id, offset: TextUnit::from(offset) };
+            analysis.line_text_at(position).unwrap().to_string()
+        };
+        assert_eq!(line_at(0), "fn foo() {}");
+        assert_eq!(line_at(12), "fn foo() {}");
+        assert_eq!(line_at(16), "fn bar() {}");
+        assert_eq!(line_at(25), "");
+        assert_eq!(line_at(30), "fn baz() {}");
+        assert_eq!(line_at(100), "fn baz() {}");
+    }
+
+    #[test]
+    fn crate_line_count_sums_module_files() {
+        let mut mock = MockAnalysis::new();
+        let lib = mock.add_file("/lib.rs", "mod foo;\n\nfn main() {}\n");
+        mock.add_file("/foo.rs", "struct Foo;\nstruct Bar;");
+        mock.add_file("/unrelated.rs", "struct Baz;\n");
+        let analysis = mock.analysis();
+        let krate = analysis.crate_for(lib).unwrap()[0];
+        assert_eq!(analysis.crate_line_count(krate).unwrap(), 5);
+    }
+
+    #[test]
+    fn source_root_change_sink_reports_added_and_removed_files() {
+        let mut host = AnalysisHost::default();
+        let changes = Arc::new(Mutex::new(Vec::new()));
+        host.set_source_root_change_sink({
+            let changes = changes.clone();
+            move |change| changes.lock().unwrap().push(change)
+        });
+        let root = SourceRootId(0);
+        let text = Arc::new(String::new());
+
+        let mut change = AnalysisChange::new();
+        change.add_root(root, true);
+        change.add_file(root, FileId(1), "lib.rs".into(), text.clone());
+        change.add_file(root, FileId(2), "foo.rs".into(), text.clone());
+        host.apply_change(change);
+
+        let mut change = AnalysisChange::new();
+        change.add_file(root, FileId(3), "bar.rs".into(), text.clone());
+        change.remove_file(root, FileId(2), "foo.rs".into());
+        host.apply_change(change);
+
+        // Only text changes, which leave the files of the root alone.
+        let mut change = AnalysisChange::new();
+        change.change_file(FileId(1), Arc::new("mod bar;".to_string()));
+        host.apply_change(change);
+
+        assert_eq!(
+            *changes.lock().unwrap(),
+            vec![
+                SourceRootChange { root, added: vec![FileId(1), FileId(2)], removed: vec![] },
+                SourceRootChange { root, added: vec![FileId(3)], removed: vec![FileId(2)] },
+            ]
+        );
+    }
+
+    #[test]
+    fn prewarm_fills_parse_and_line_index_caches() {
+        use ra_db::salsa::{debug::DebugQueryTable, Database};
+
+        let mut mock = MockAnalysis::new();
+        let lib = mock.add_file("/lib.rs", "mod foo;");
+        let foo = mock.add_file("/foo.rs", "struct Foo;");
+        mock.add_file("/unrelated.rs", "struct Bar;");
+        let host = mock.analysis_host();
+        host.analysis().prewarm(&[lib, foo]).unwrap();
+
+        let mut parsed = host
+            .db
+            .query(ra_db::ParseQuery)
+            .entries::<Vec<_>>()
+            .into_iter()
+            .filter(|it| it.value.is_some())
+            .map(|it| it.key)
+            .collect::<Vec<_>>();
+        parsed.sort();
+        assert_eq!(parsed, vec![lib, foo]);
+        let mut indexed = host
+            .db
+            .query(super::LineIndexQuery)
+            .entries::<Vec<_>>()
+            .into_iter()
+            .map(|it| it.key)
+            .collect::<Vec<_>>();
+        indexed.sort();
+        assert_eq!(indexed, vec![lib, foo]);
+    }
+
+    #[test]
+    fn renaming_a_crate_keeps_the_crate_graph() {
+        use ra_db::{CrateGraph, SourceDatabase};
+
+        let mut mock = MockAnalysis::new();
+        let lib = mock.add_file("/lib.rs", "struct Foo;");
+        let mut host = mock.analysis_host();
+        let graph = host.db.crate_graph();
+        let krate = graph.crate_id_for_crate_root(lib).unwrap();
+
+        let mut renamed = CrateGraph::clone(&graph);
+        renamed.set_display_name(krate, "foo".to_string());
+        let mut change = AnalysisChange::new();
+        change.set_crate_graph(renamed.clone());
+        host.apply_change(change);
+        assert!(Arc::ptr_eq(&host.db.crate_graph(), &graph));
+        assert_eq!(host.db.crate_display_data().display_name(krate), Some("foo"));
+
+        renamed.set_is_member(krate, false);
+        let mut change = AnalysisChange::new();
+        change.set_crate_graph(renamed);
+        host.apply_change(change);
+        assert!(!host.db.crate_graph().is_member(krate));
+    }
+}
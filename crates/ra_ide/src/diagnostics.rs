@@ -32,6 +32,10 @@ pub(crate) fn diagnostics(db: &RootDatabase, file_id: FileId) -> Vec<Diagnostic>
         severity: Severity::Error,
         fix: None,
     }));
+    // Generated code is not for humans to fix, so only report broken syntax.
+    if db.file_is_generated(file_id) {
+        return res;
+    }
 
     for node in parse.tree().syntax().descendants() {
         check_unnecessary_braces_in_use_statement(&mut res, file_id, &node);
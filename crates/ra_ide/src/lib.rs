@@ -62,7 +62,7 @@ use crate::{db::LineIndexDatabase, display::ToNav, symbol_index::FileSymbol};
 
 pub use crate::{
     assists::{Assist, AssistId},
-    change::{AnalysisChange, LibraryData},
+    change::{AnalysisChange, LibraryData, SourceRootChange},
     completion::{CompletionItem, CompletionItemKind, InsertTextFormat},
     diagnostics::Severity,
     display::{file_structure, FunctionSignature, NavigationTarget, StructureNode},
@@ -194,6 +194,23 @@ impl AnalysisHost {
     pub fn collect_garbage(&mut self) {
         self.db.collect_garbage();
     }
+    /// Calls `sink` with the files added to and removed from a source root
+    /// whenever `apply_change` changes which files the root contains, for
+    /// tools which keep an index of files of their own.
+    pub fn set_source_root_change_sink(
+        &mut self,
+        sink: impl Fn(SourceRootChange) + Send + Sync + 'static,
+    ) {
+        self.db.source_root_change_sink = Some(change::SourceRootChangeSink(Arc::new(sink)));
+    }
+    /// Starts or stops counting how often each query is recomputed.
+    pub fn set_query_stats_enabled(&self, enabled: bool) {
+        self.db.query_stats.set_enabled(enabled)
+    }
+    /// Recomputation counts per query, the most recomputed queries first.
+    pub fn query_recompute_stats(&self) -> Vec<(String, usize)> {
+        self.db.query_stats.recompute_counts()
+    }
     /// NB: this clears the database
     pub fn per_query_memory_usage(&mut self) -> Vec<(String, ra_prof::Bytes)> {
         self.db.per_query_memory_usage()
@@ -267,12 +284,48 @@ impl Analysis {
         self.with_db(|db| db.parse(file_id).tree())
     }
 
+    /// Parses the files and computes their line indices in advance, so that
+    /// the requests which follow are fast.
+    pub fn prewarm(&self, files: &[FileId]) -> Cancelable<()> {
+        self.with_db(|db| {
+            for &file_id in files {
+                db.check_canceled();
+                db.parse(file_id);
+                db.line_index(file_id);
+            }
+        })
+    }
+
     /// Gets the file's `LineIndex`: data structure to convert between absolute
     /// offsets and line/column representation.
     pub fn file_line_index(&self, file_id: FileId) -> Cancelable<Arc<LineIndex>> {
         self.with_db(|db| db.line_index(file_id))
     }
 
+    /// Converts the position to a line and a utf-16 column, as used by LSP.
+    pub fn position_to_line_col(&self, position: FilePosition) -> Cancelable<LineCol> {
+        self.with_db(|db| db.position_to_line_col(position))
+    }
+
+    /// Converts a line and a utf-16 column, as used by LSP, to a position.
+    pub fn line_col_to_position(
+        &self,
+        file_id: FileId,
+        line_col: LineCol,
+    ) -> Cancelable<FilePosition> {
+        self.with_db(|db| db.line_col_to_position(file_id, line_col))
+    }
+
+    /// Text of the line the position is on, without the line ending.
+    pub fn line_text_at(&self, position: FilePosition) -> Cancelable<Arc<str>> {
+        self.with_db(|db| db.line_text_at(position))
+    }
+
+    /// Total number of lines of the files of the crate.
+    pub fn crate_line_count(&self, crate_id: CrateId) -> Cancelable<usize> {
+        self.with_db(|db| db.crate_line_count(crate_id))
+    }
+
     /// Selects the next syntactic nodes encompassing the range.
     pub fn extend_selection(&self, frange: FileRange) -> Cancelable<TextRange> {
         self.with_db(|db| extend_selection::extend_selection(db, frange))
@@ -1,6 +1,6 @@
 //! FIXME: write short doc here
 
-use crate::TextUnit;
+use crate::{TextRange, TextUnit};
 use rustc_hash::FxHashMap;
 use superslice::Ext;
 
@@ -8,6 +8,10 @@ use superslice::Ext;
 pub struct LineIndex {
     pub(crate) newlines: Vec<TextUnit>,
     pub(crate) utf16_lines: FxHashMap<u32, Vec<Utf16Char>>,
+    /// Utf-8 columns of the tabs, for lines which have any.
+    pub(crate) tab_lines: FxHashMap<u32, Vec<TextUnit>>,
+    /// Length of the whole text.
+    pub(crate) len: TextUnit,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -34,6 +38,8 @@ impl LineIndex {
     pub fn new(text: &str) -> LineIndex {
         let mut utf16_lines = FxHashMap::default();
         let mut utf16_chars = Vec::new();
+        let mut tab_lines = FxHashMap::default();
+        let mut tabs = Vec::new();
 
         let mut newlines = vec![0.into()];
         let mut curr_row = 0.into();
@@ -49,6 +55,10 @@ impl LineIndex {
                     utf16_lines.insert(line, utf16_chars);
                     utf16_chars = Vec::new();
                 }
+                if !tabs.is_empty() {
+                    tab_lines.insert(line, tabs);
+                    tabs = Vec::new();
+                }
 
                 // Prepare for processing the next line
                 curr_col = 0.into();
@@ -56,6 +66,9 @@ impl LineIndex {
                 continue;
             }
 
+            if c == '\t' {
+                tabs.push(curr_col);
+            }
             let char_len = TextUnit::of_char(c);
             if char_len.to_usize() > 1 {
                 utf16_chars.push(Utf16Char { start: curr_col, end: curr_col + char_len });
@@ -68,8 +81,11 @@ impl LineIndex {
         if !utf16_chars.is_empty() {
             utf16_lines.insert(line, utf16_chars);
         }
+        if !tabs.is_empty() {
+            tab_lines.insert(line, tabs);
+        }
 
-        LineIndex { newlines, utf16_lines }
+        LineIndex { newlines, utf16_lines, tab_lines, len: curr_row }
     }
 
     pub fn line_col(&self, offset: TextUnit) -> LineCol {
@@ -80,12 +96,62 @@ impl LineIndex {
         LineCol { line: line as u32, col_utf16: self.utf8_to_utf16_col(line as u32, col) as u32 }
     }
 
+    /// Like `line_col`, but with tabs expanded to the next multiple of
+    /// `tab_width` columns, the way editors display them. `line_col` itself,
+    /// which is what LSP positions are made of, counts a tab as one column.
+    pub fn display_line_col(&self, offset: TextUnit, tab_width: u32) -> LineCol {
+        let LineCol { line, col_utf16 } = self.line_col(offset);
+        let tab_width = tab_width.max(1);
+        let col = offset - self.newlines[line as usize];
+        let mut display_col = 0;
+        let mut prev_col = 0;
+        for &tab in self.tab_lines.get(&line).into_iter().flatten() {
+            if tab >= col {
+                break;
+            }
+            let tab_col = self.utf8_to_utf16_col(line, tab) as u32;
+            display_col += tab_col - prev_col;
+            display_col = (display_col / tab_width + 1) * tab_width;
+            prev_col = tab_col + 1;
+        }
+        display_col += col_utf16 - prev_col;
+        LineCol { line, col_utf16: display_col }
+    }
+
     pub fn offset(&self, line_col: LineCol) -> TextUnit {
         //FIXME: return Result
         let col = self.utf16_to_utf8_col(line_col.line, line_col.col_utf16);
         self.newlines[line_col.line as usize] + col
     }
 
+    /// Number of lines, not counting the empty line after a final `\n`, so a
+    /// file of `n` `\n`-terminated lines has `n` of them.
+    pub fn line_count(&self) -> usize {
+        match self.newlines.last() {
+            Some(&start) if start == self.len => self.newlines.len() - 1,
+            _ => self.newlines.len(),
+        }
+    }
+
+    /// Range of the line, including its trailing `\n`, or `None` if there is
+    /// no such line. A text ending with `\n` has an empty last line after it.
+    pub fn line_range(&self, line: u32) -> Option<TextRange> {
+        let start = *self.newlines.get(line as usize)?;
+        let end = self.newlines.get(line as usize + 1).copied().unwrap_or(self.len);
+        Some(TextRange::from_to(start, end))
+    }
+
+    /// Lines of `text`, which must be the text this index was built from.
+    /// Each line keeps its trailing `\n`, so the lines add up to `text`.
+    pub fn lines<'a>(&'a self, text: &'a str) -> impl Iterator<Item = &'a str> + 'a {
+        let ends = self.newlines[1..].iter().copied().chain(Some(TextUnit::of_str(text)));
+        self.newlines
+            .iter()
+            .zip(ends)
+            .map(move |(&start, end)| &text[start.to_usize()..end.to_usize()])
+            .filter(|line| !line.is_empty())
+    }
+
     fn utf8_to_utf16_col(&self, line: u32, mut col: TextUnit) -> usize {
         if let Some(utf16_chars) = self.utf16_lines.get(&line) {
             let mut correction = TextUnit::from_usize(0);
@@ -280,4 +346,68 @@ const C: char = \"メ メ\";
 
         assert_eq!(col_index.utf16_to_utf8_col(2, 15), TextUnit::from_usize(15));
     }
+
+    #[test]
+    fn test_display_line_col() {
+        let text = "\tfoo\nab\t\tc\nメ\tx";
+        let col_index = LineIndex::new(text);
+        let display = |needle: &str, tab_width: u32| {
+            let offset = TextUnit::from_usize(text.find(needle).unwrap());
+            let LineCol { line, col_utf16 } = col_index.display_line_col(offset, tab_width);
+            (line, col_utf16)
+        };
+
+        assert_eq!(display("foo", 1), (0, 1));
+        assert_eq!(display("foo", 4), (0, 4));
+        assert_eq!(display("\tc", 1), (1, 3));
+        assert_eq!(display("\tc", 4), (1, 4));
+        assert_eq!(display("c", 1), (1, 4));
+        assert_eq!(display("c", 4), (1, 8));
+        assert_eq!(display("x", 1), (2, 2));
+        assert_eq!(display("x", 4), (2, 4));
+
+        // Raw columns are not affected by tabs.
+        let offset = TextUnit::from_usize(text.find("c").unwrap());
+        assert_eq!(col_index.line_col(offset), LineCol { line: 1, col_utf16: 4 });
+    }
+
+    #[test]
+    fn test_lines() {
+        let lines = |text: &str| LineIndex::new(text).lines(text).collect::<Vec<_>>();
+
+        assert_eq!(lines(""), Vec::<&str>::new());
+        assert_eq!(lines("\n"), vec!["\n"]);
+        assert_eq!(lines("foo\n\nbar\n"), vec!["foo\n", "\n", "bar\n"]);
+        assert_eq!(lines("foo\nメ bar"), vec!["foo\n", "メ bar"]);
+
+        let text = "fn main() {\n\tlet x = 92;\n}";
+        assert_eq!(lines(text).concat(), text);
+    }
+
+    #[test]
+    fn test_line_range() {
+        let range = |start: u32, end: u32| Some(TextRange::from_to(start.into(), end.into()));
+
+        let index = LineIndex::new("foo\nbar baz\nquux");
+        assert_eq!(index.line_range(0), range(0, 4));
+        assert_eq!(index.line_range(1), range(4, 12));
+        assert_eq!(index.line_range(2), range(12, 16));
+        assert_eq!(index.line_range(3), None);
+
+        let index = LineIndex::new("foo\nbar\n");
+        assert_eq!(index.line_range(1), range(4, 8));
+        assert_eq!(index.line_range(2), range(8, 8));
+        assert_eq!(index.line_range(3), None);
+
+        assert_eq!(LineIndex::new("").line_range(0), range(0, 0));
+    }
+
+    #[test]
+    fn test_line_count() {
+        assert_eq!(LineIndex::new("").line_count(), 0);
+        assert_eq!(LineIndex::new("foo").line_count(), 1);
+        assert_eq!(LineIndex::new("foo\n").line_count(), 1);
+        assert_eq!(LineIndex::new("foo\nbar").line_count(), 2);
+        assert_eq!(LineIndex::new("foo\n\n").line_count(), 2);
+    }
 }
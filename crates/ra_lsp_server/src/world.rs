@@ -115,7 +115,13 @@ impl WorldState {
         };
         for ws in workspaces.iter() {
             let (graph, crate_names) = ws.to_crate_graph(&default_cfg_options, &mut load);
-            let shift = crate_graph.extend(graph);
+            let shift = match crate_graph.extend(graph) {
+                Ok(shift) => shift,
+                Err(e) => {
+                    log::error!("failed to add workspace to the crate graph: {}", e);
+                    continue;
+                }
+            };
             for (crate_id, name) in crate_names {
                 change.set_debug_crate_name(crate_id.shift(shift), name)
             }
@@ -115,6 +115,15 @@ pub(super) fn atom_expr(p: &mut Parser, r: Restrictions) -> Option<(CompletedMar
             p.bump(T![unsafe]);
             block_expr(p, Some(m))
         }
+        // test_err inline_const_without_feature
+        // fn foo() { let _ = const { 92 }; }
+        T![const] if la == T!['{'] && p.features().inline_const => {
+            // test inline_const_block
+            // fn foo() { let _ = const { 92 }; }
+            let m = p.start();
+            p.bump(T![const]);
+            block_expr(p, Some(m))
+        }
         T!['{'] => {
             // test for_range_from
             // fn foo() {
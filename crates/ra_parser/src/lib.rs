@@ -27,6 +27,17 @@ pub use syntax_kind::SyntaxKind;
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ParseError(pub String);
 
+/// Unstable syntax which the parser should accept. Everything is disabled by
+/// default, so that only stable Rust parses without errors.
+///
+/// Note that fragments and reparsed blocks are always parsed with the
+/// default features.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub struct ParserFeatures {
+    /// `const { ... }` blocks in expression position.
+    pub inline_const: bool,
+}
+
 /// `TokenSource` abstracts the source of the tokens parser operates one.
 ///
 /// Hopefully this will allow us to treat text and token trees in the same way!
@@ -68,11 +79,15 @@ pub trait TreeSink {
     fn error(&mut self, error: ParseError);
 }
 
-fn parse_from_tokens<F>(token_source: &mut dyn TokenSource, tree_sink: &mut dyn TreeSink, f: F)
-where
+fn parse_from_tokens<F>(
+    token_source: &mut dyn TokenSource,
+    tree_sink: &mut dyn TreeSink,
+    features: ParserFeatures,
+    f: F,
+) where
     F: FnOnce(&mut parser::Parser),
 {
-    let mut p = parser::Parser::new(token_source);
+    let mut p = parser::Parser::new(token_source, features);
     f(&mut p);
     let events = p.finish();
     event::process(tree_sink, events);
@@ -80,7 +95,17 @@ where
 
 /// Parse given tokens into the given sink as a rust file.
 pub fn parse(token_source: &mut dyn TokenSource, tree_sink: &mut dyn TreeSink) {
-    parse_from_tokens(token_source, tree_sink, grammar::root);
+    parse_with_features(token_source, tree_sink, ParserFeatures::default());
+}
+
+/// Parse given tokens into the given sink as a rust file, accepting the given
+/// unstable syntax.
+pub fn parse_with_features(
+    token_source: &mut dyn TokenSource,
+    tree_sink: &mut dyn TreeSink,
+    features: ParserFeatures,
+) {
+    parse_from_tokens(token_source, tree_sink, features, grammar::root);
 }
 
 pub enum FragmentKind {
@@ -100,6 +125,9 @@ pub enum FragmentKind {
     Statements,
 }
 
+/// Parse given tokens as a fragment of the given kind, with the default
+/// `ParserFeatures`: fragments come from macros, which don't know the
+/// features of the file.
 pub fn parse_fragment(
     token_source: &mut dyn TokenSource,
     tree_sink: &mut dyn TreeSink,
@@ -118,7 +146,7 @@ pub fn parse_fragment(
         FragmentKind::Items => grammar::fragments::macro_items,
         FragmentKind::Statements => grammar::fragments::macro_stmts,
     };
-    parse_from_tokens(token_source, tree_sink, parser)
+    parse_from_tokens(token_source, tree_sink, ParserFeatures::default(), parser)
 }
 
 /// A parsing function for a specific braced-block.
@@ -134,13 +162,14 @@ impl Reparser {
         grammar::reparser(node, first_child, parent).map(Reparser)
     }
 
-    /// Re-parse given tokens using this `Reparser`.
+    /// Re-parse given tokens using this `Reparser`, with the default
+    /// `ParserFeatures`.
     ///
     /// Tokens must start with `{`, end with `}` and form a valid brace
     /// sequence.
     pub fn parse(self, token_source: &mut dyn TokenSource, tree_sink: &mut dyn TreeSink) {
         let Reparser(r) = self;
-        let mut p = parser::Parser::new(token_source);
+        let mut p = parser::Parser::new(token_source, ParserFeatures::default());
         r(&mut p);
         let events = p.finish();
         event::process(tree_sink, events);
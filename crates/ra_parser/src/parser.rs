@@ -6,7 +6,7 @@ use drop_bomb::DropBomb;
 
 use crate::{
     event::Event,
-    ParseError,
+    ParseError, ParserFeatures,
     SyntaxKind::{self, EOF, ERROR, TOMBSTONE},
     TokenSet, TokenSource, T,
 };
@@ -24,11 +24,20 @@ pub(crate) struct Parser<'t> {
     token_source: &'t mut dyn TokenSource,
     events: Vec<Event>,
     steps: Cell<u32>,
+    features: ParserFeatures,
 }
 
 impl<'t> Parser<'t> {
-    pub(super) fn new(token_source: &'t mut dyn TokenSource) -> Parser<'t> {
-        Parser { token_source, events: Vec::new(), steps: Cell::new(0) }
+    pub(super) fn new(
+        token_source: &'t mut dyn TokenSource,
+        features: ParserFeatures,
+    ) -> Parser<'t> {
+        Parser { token_source, events: Vec::new(), steps: Cell::new(0), features }
+    }
+
+    /// Unstable syntax the parser was asked to accept.
+    pub(crate) fn features(&self) -> ParserFeatures {
+        self.features
     }
 
     pub(crate) fn finish(self) -> Vec<Event> {
@@ -4,7 +4,7 @@ use std::path::{Path, PathBuf};
 
 use cargo_metadata::{CargoOpt, MetadataCommand};
 use ra_arena::{impl_arena_id, Arena, RawId};
-use ra_db::Edition;
+use ra_db::{CrateKind, Edition};
 use rustc_hash::FxHashMap;
 
 use crate::Result;
@@ -34,6 +34,7 @@ impl_arena_id!(Target);
 #[derive(Debug, Clone)]
 struct PackageData {
     name: String,
+    version: String,
     manifest: PathBuf,
     targets: Vec<Target>,
     is_member: bool,
@@ -54,6 +55,7 @@ struct TargetData {
     name: String,
     root: PathBuf,
     kind: TargetKind,
+    crate_kinds: Vec<CrateKind>,
     is_proc_macro: bool,
 }
 
@@ -89,6 +91,9 @@ impl Package {
     pub fn name(self, ws: &CargoWorkspace) -> &str {
         ws.packages[self].name.as_str()
     }
+    pub fn version(self, ws: &CargoWorkspace) -> &str {
+        ws.packages[self].version.as_str()
+    }
     pub fn root(self, ws: &CargoWorkspace) -> &Path {
         ws.packages[self].manifest.parent().unwrap()
     }
@@ -126,6 +131,11 @@ impl Target {
     pub fn kind(self, ws: &CargoWorkspace) -> TargetKind {
         ws.targets[self].kind
     }
+    /// Crate types of the target which are known to `CrateKind`, empty for
+    /// examples, tests and benches.
+    pub fn crate_kinds(self, ws: &CargoWorkspace) -> &[CrateKind] {
+        &ws.targets[self].crate_kinds
+    }
     pub fn is_proc_macro(self, ws: &CargoWorkspace) -> bool {
         ws.targets[self].is_proc_macro
     }
@@ -146,11 +156,13 @@ impl CargoWorkspace {
         let ws_members = &meta.workspace_members;
 
         for meta_pkg in meta.packages {
-            let cargo_metadata::Package { id, edition, name, manifest_path, .. } = meta_pkg;
+            let cargo_metadata::Package { id, edition, name, version, manifest_path, .. } =
+                meta_pkg;
             let is_member = ws_members.contains(&id);
             let edition = edition.parse::<Edition>()?;
             let pkg = packages.alloc(PackageData {
                 name,
+                version: version.to_string(),
                 manifest: manifest_path,
                 targets: Vec::new(),
                 is_member,
@@ -167,6 +179,7 @@ impl CargoWorkspace {
                     name: meta_tgt.name,
                     root: meta_tgt.src_path.clone(),
                     kind: TargetKind::new(meta_tgt.kind.as_slice()),
+                    crate_kinds: meta_tgt.kind.iter().filter_map(|it| it.parse().ok()).collect(),
                     is_proc_macro,
                 });
                 pkg_data.targets.push(tgt);
@@ -1,10 +1,14 @@
 //! FIXME: write short doc here
 
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
+use ra_cfg::CfgOptions;
+use ra_db::{CrateGraph, Env, FileId, RelativePathBuf, SourceRoot};
 use rustc_hash::{FxHashMap, FxHashSet};
 use serde::Deserialize;
 
+use crate::Result;
+
 /// A root points to the directory which contains Rust crates. rust-analyzer watches all files in
 /// all roots. Roots might be nested.
 #[derive(Clone, Debug, Deserialize)]
@@ -52,3 +56,210 @@ pub struct JsonProject {
     pub(crate) roots: Vec<Root>,
     pub(crate) crates: Vec<Crate>,
 }
+
+impl Crate {
+    pub(crate) fn edition(&self) -> ra_db::Edition {
+        match self.edition {
+            Edition::Edition2015 => ra_db::Edition::Edition2015,
+            Edition::Edition2018 => ra_db::Edition::Edition2018,
+        }
+    }
+
+    pub(crate) fn cfg_options(&self, default_cfg_options: &CfgOptions) -> CfgOptions {
+        let mut opts = default_cfg_options.clone();
+        for name in &self.atom_cfgs {
+            opts.insert_atom(name.into());
+        }
+        for (key, value) in &self.key_value_cfgs {
+            opts.insert_key_value(key.into(), value.into());
+        }
+        opts
+    }
+}
+
+impl JsonProject {
+    /// Builds the crate graph of the project without looking at the file
+    /// system, for build systems which describe the crates fully.
+    ///
+    /// The crate roots are the only files: they get `FileId`s in the order of
+    /// the crates, and are put into the source root of the innermost project
+    /// root containing them. The `SourceRootId` of a source root is its index
+    /// in the returned `Vec`, and so the index of the project root.
+    ///
+    /// The format doesn't tell the crates of the workspace from vendored
+    /// ones, so no crate is marked as a member.
+    pub fn crate_graph_and_roots(
+        &self,
+        default_cfg_options: &CfgOptions,
+    ) -> Result<(CrateGraph, Vec<SourceRoot>)> {
+        let mut source_roots = vec![SourceRoot::new(); self.roots.len()];
+        let mut file_ids = FxHashMap::default();
+        for krate in self.crates.iter() {
+            if file_ids.contains_key(&krate.root_module) {
+                continue;
+            }
+            let (root_idx, path) = self.relative_to_root(&krate.root_module)?;
+            let file_id = FileId(file_ids.len() as u32);
+            source_roots[root_idx].insert_file(path, file_id);
+            file_ids.insert(krate.root_module.clone(), file_id);
+        }
+        let (crate_graph, errors) =
+            self.lower_crates(default_cfg_options, &mut |path| file_ids.get(path).copied());
+        if let Some(error) = errors.into_iter().next() {
+            return Err(error.into());
+        }
+        Ok((crate_graph, source_roots))
+    }
+
+    /// Lowers the crates to a `CrateGraph`, with `load` giving the `FileId`s
+    /// of the crate roots. The cfg options of each crate are added to
+    /// `default_cfg_options`.
+    ///
+    /// Crates whose root isn't loaded are left out. Crates and dependencies
+    /// which don't fit into the graph are left out too, and reported.
+    pub(crate) fn lower_crates(
+        &self,
+        default_cfg_options: &CfgOptions,
+        load: &mut dyn FnMut(&Path) -> Option<FileId>,
+    ) -> (CrateGraph, Vec<String>) {
+        let mut crate_graph = CrateGraph::default();
+        let mut errors = Vec::new();
+        let mut crates = FxHashMap::default();
+        for (idx, krate) in self.crates.iter().enumerate() {
+            let file_id = match load(&krate.root_module) {
+                Some(it) => it,
+                None => continue,
+            };
+            match crate_graph.try_add_crate_root(
+                file_id,
+                krate.edition(),
+                krate.cfg_options(default_cfg_options),
+                Env::default(),
+            ) {
+                Ok(crate_id) => {
+                    crates.insert(CrateId(idx), crate_id);
+                }
+                Err(e) => errors.push(format!("skipping crate {:?}: {}", krate.root_module, e)),
+            }
+        }
+
+        for (idx, krate) in self.crates.iter().enumerate() {
+            let from = match crates.get(&CrateId(idx)) {
+                Some(&it) => it,
+                None => continue,
+            };
+            for dep in krate.deps.iter() {
+                let to = match crates.get(&dep.krate) {
+                    Some(&it) => it,
+                    None => {
+                        if dep.krate.0 >= self.crates.len() {
+                            errors.push(format!(
+                                "{:?} depends on unknown crate {:?}",
+                                krate.root_module, dep.krate
+                            ));
+                        }
+                        continue;
+                    }
+                };
+                if crate_graph.add_dep(from, dep.name.as_str().into(), to).is_err() {
+                    errors.push(format!(
+                        "cyclic dependency {:?} -> {:?}",
+                        krate.root_module, dep.krate
+                    ));
+                }
+            }
+        }
+        (crate_graph, errors)
+    }
+
+    fn relative_to_root(&self, path: &Path) -> Result<(usize, RelativePathBuf)> {
+        let (idx, rel_path) = self
+            .roots
+            .iter()
+            .enumerate()
+            .filter_map(|(idx, root)| Some((idx, path.strip_prefix(&root.path).ok()?)))
+            .min_by_key(|(_, rel_path)| rel_path.components().count())
+            .ok_or_else(|| format!("{:?} is not inside of any of the project roots", path))?;
+        let rel_path = RelativePathBuf::from_path(rel_path)
+            .map_err(|_| format!("{:?} is not a valid relative path", rel_path))?;
+        Ok((idx, rel_path))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ra_cfg::{CfgExpr, CfgOptions};
+    use ra_db::RelativePath;
+
+    use super::JsonProject;
+
+    #[test]
+    fn crate_graph_and_roots_from_json() {
+        let project: JsonProject = serde_json::from_str(
+            r#"{
+                "roots": ["/ws", "/ws/vendor"],
+                "crates": [
+                    {
+                        "root_module": "/ws/vendor/dep/lib.rs",
+                        "edition": "2015",
+                        "deps": [],
+                        "atom_cfgs": ["unix"],
+                        "key_value_cfgs": {}
+                    },
+                    {
+                        "root_module": "/ws/src/main.rs",
+                        "edition": "2018",
+                        "deps": [{ "crate": 0, "name": "dep" }],
+                        "atom_cfgs": [],
+                        "key_value_cfgs": { "feature": "std" }
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let mut default_cfg_options = CfgOptions::default();
+        default_cfg_options.insert_atom("debug_assertions".into());
+        let (graph, roots) = project.crate_graph_and_roots(&default_cfg_options).unwrap();
+        assert_eq!(roots.len(), 2);
+        let dep_file = roots[1].file_by_relative_path(RelativePath::new("dep/lib.rs")).unwrap();
+        let main_file = roots[0].file_by_relative_path(RelativePath::new("src/main.rs")).unwrap();
+        let dep = graph.crate_id_for_crate_root(dep_file).unwrap();
+        let main = graph.crate_id_for_crate_root(main_file).unwrap();
+
+        assert_eq!(graph.edition(dep), ra_db::Edition::Edition2015);
+        assert_eq!(graph.edition(main), ra_db::Edition::Edition2018);
+        assert_eq!(graph.cfg_options(dep).check(&CfgExpr::Atom("unix".into())), Some(true));
+        let key_value = CfgExpr::KeyValue { key: "feature".into(), value: "std".into() };
+        assert_eq!(graph.cfg_options(main).check(&key_value), Some(true));
+        let debug_assertions = CfgExpr::Atom("debug_assertions".into());
+        assert_eq!(graph.cfg_options(main).check(&debug_assertions), Some(true));
+        let deps = graph
+            .dependencies(main)
+            .map(|dep| (dep.crate_id(), dep.name.to_string()))
+            .collect::<Vec<_>>();
+        assert_eq!(deps, vec![(dep, "dep".to_string())]);
+        assert_eq!(graph.dependencies(dep).count(), 0);
+        assert!(!graph.is_member(dep));
+        assert!(!graph.is_member(main));
+    }
+
+    #[test]
+    fn unknown_dependency_is_an_error() {
+        let project: JsonProject = serde_json::from_str(
+            r#"{
+                "roots": ["/ws"],
+                "crates": [
+                    {
+                        "root_module": "/ws/src/lib.rs",
+                        "edition": "2018",
+                        "deps": [{ "crate": 1, "name": "missing" }],
+                        "atom_cfgs": [],
+                        "key_value_cfgs": {}
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert!(project.crate_graph_and_roots(&CfgOptions::default()).is_err());
+    }
+}
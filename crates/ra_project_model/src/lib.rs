@@ -126,52 +126,15 @@ impl ProjectWorkspace {
         let mut names = FxHashMap::default();
         match self {
             ProjectWorkspace::Json { project } => {
-                let mut crates = FxHashMap::default();
-                for (id, krate) in project.crates.iter().enumerate() {
-                    let crate_id = json_project::CrateId(id);
-                    if let Some(file_id) = load(&krate.root_module) {
-                        let edition = match krate.edition {
-                            json_project::Edition::Edition2015 => Edition::Edition2015,
-                            json_project::Edition::Edition2018 => Edition::Edition2018,
-                        };
-                        let cfg_options = {
-                            let mut opts = default_cfg_options.clone();
-                            for name in &krate.atom_cfgs {
-                                opts.insert_atom(name.into());
-                            }
-                            for (key, value) in &krate.key_value_cfgs {
-                                opts.insert_key_value(key.into(), value.into());
-                            }
-                            opts
-                        };
-                        crates.insert(
-                            crate_id,
-                            crate_graph.add_crate_root(
-                                file_id,
-                                edition,
-                                cfg_options,
-                                Env::default(),
-                            ),
-                        );
-                    }
+                let (graph, errors) = project.lower_crates(default_cfg_options, load);
+                for error in errors {
+                    log::error!("{}", error);
                 }
-
-                for (id, krate) in project.crates.iter().enumerate() {
-                    for dep in &krate.deps {
-                        let from_crate_id = json_project::CrateId(id);
-                        let to_crate_id = dep.krate;
-                        if let (Some(&from), Some(&to)) =
-                            (crates.get(&from_crate_id), crates.get(&to_crate_id))
-                        {
-                            if let Err(_) = crate_graph.add_dep(from, dep.name.clone().into(), to) {
-                                log::error!(
-                                    "cyclic dependency {:?} -> {:?}",
-                                    from_crate_id,
-                                    to_crate_id
-                                );
-                            }
-                        }
-                    }
+                crate_graph = graph;
+                // All roots of a JSON project are members, see `to_roots`.
+                let crate_ids = crate_graph.iter().collect::<Vec<_>>();
+                for crate_id in crate_ids {
+                    crate_graph.set_is_member(crate_id, true);
                 }
             }
             ProjectWorkspace::Cargo { cargo, sysroot } => {
@@ -185,13 +148,25 @@ impl ProjectWorkspace {
                             opts
                         };
 
-                        let crate_id = crate_graph.add_crate_root(
+                        let crate_id = match crate_graph.try_add_crate_root(
                             file_id,
                             Edition::Edition2018,
                             cfg_options,
                             Env::default(),
-                        );
+                        ) {
+                            Ok(it) => it,
+                            Err(e) => {
+                                log::error!(
+                                    "skipping sysroot crate {}: {}",
+                                    krate.name(&sysroot),
+                                    e
+                                );
+                                continue;
+                            }
+                        };
+                        crate_graph.set_is_lang(crate_id, true);
                         sysroot_crates.insert(krate, crate_id);
+                        crate_graph.set_display_name(crate_id, krate.name(&sysroot).to_string());
                         names.insert(crate_id, krate.name(&sysroot).to_string());
                     }
                 }
@@ -228,12 +203,26 @@ impl ProjectWorkspace {
                                 opts.insert_features(pkg.features(&cargo).iter().map(Into::into));
                                 opts
                             };
-                            let crate_id = crate_graph.add_crate_root(
+                            let crate_id = match crate_graph.try_add_crate_root(
                                 file_id,
                                 edition,
                                 cfg_options,
                                 Env::default(),
-                            );
+                            ) {
+                                Ok(it) => it,
+                                Err(e) => {
+                                    log::error!("skipping target of {}: {}", pkg.name(&cargo), e);
+                                    continue;
+                                }
+                            };
+                            // Like the package root in `to_roots`.
+                            crate_graph.set_is_member(crate_id, pkg.is_member(&cargo));
+                            crate_graph.set_display_name(crate_id, pkg.name(&cargo).to_string());
+                            crate_graph.set_version(crate_id, pkg.version(&cargo).to_string());
+                            let crate_kinds = tgt.crate_kinds(&cargo);
+                            if !crate_kinds.is_empty() {
+                                crate_graph.set_crate_kinds(crate_id, crate_kinds.to_vec());
+                            }
                             names.insert(crate_id, pkg.name(&cargo).to_string());
                             if tgt.kind(&cargo) == TargetKind::Lib {
                                 lib_tgt = Some(crate_id);
@@ -7,7 +7,8 @@ use ra_text_edit::TextEditBuilder;
 use rustc_hash::FxHashMap;
 
 use crate::{
-    AstNode, Direction, NodeOrToken, SyntaxElement, SyntaxNode, SyntaxNodePtr, TextRange, TextUnit,
+    ast, AstNode, Direction, NodeOrToken, Parse, SyntaxElement, SyntaxNode, SyntaxNodePtr,
+    TextRange, TextUnit, WalkEvent,
 };
 
 /// Returns ancestors of the node at the offset, sorted by length. This should
@@ -118,6 +119,46 @@ pub fn diff(from: &SyntaxNode, to: &SyntaxNode) -> TreeDiff {
     }
 }
 
+/// Checks if the two files have the same syntax tree, up to whitespace and
+/// comments: the nodes must have the same kinds and nest the same way, and the
+/// remaining tokens must have the same kinds and texts. Parse errors are not
+/// compared.
+pub fn parse_trees_equal(a: &Parse<ast::SourceFile>, b: &Parse<ast::SourceFile>) -> bool {
+    let (a, b) = (a.syntax_node(), b.syntax_node());
+    let mut lhs_events = significant_events(&a);
+    let mut rhs_events = significant_events(&b);
+    loop {
+        let (lhs, rhs) = match (lhs_events.next(), rhs_events.next()) {
+            (None, None) => return true,
+            (Some(lhs), Some(rhs)) => (lhs, rhs),
+            _ => return false,
+        };
+        let same = match (lhs, rhs) {
+            (
+                WalkEvent::Enter(NodeOrToken::Node(lhs)),
+                WalkEvent::Enter(NodeOrToken::Node(rhs)),
+            ) => lhs.kind() == rhs.kind(),
+            (
+                WalkEvent::Enter(NodeOrToken::Token(lhs)),
+                WalkEvent::Enter(NodeOrToken::Token(rhs)),
+            ) => lhs.kind() == rhs.kind() && lhs.text() == rhs.text(),
+            (WalkEvent::Leave(_), WalkEvent::Leave(_)) => true,
+            _ => false,
+        };
+        if !same {
+            return false;
+        }
+    }
+
+    fn significant_events(node: &SyntaxNode) -> impl Iterator<Item = WalkEvent<SyntaxElement>> {
+        node.preorder_with_tokens().filter(|event| match event {
+            WalkEvent::Enter(NodeOrToken::Token(token)) => !token.kind().is_trivia(),
+            WalkEvent::Leave(NodeOrToken::Token(_)) => false,
+            _ => true,
+        })
+    }
+}
+
 /// Adds specified children (tokens or nodes) to the current node at the
 /// specific position.
 ///
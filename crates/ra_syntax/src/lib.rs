@@ -48,7 +48,7 @@ pub use crate::{
         Direction, NodeOrToken, SyntaxElement, SyntaxNode, SyntaxToken, SyntaxTreeBuilder,
     },
 };
-pub use ra_parser::{SyntaxKind, T};
+pub use ra_parser::{ParseError, ParserFeatures, SyntaxKind, T};
 pub use rowan::{SmolStr, SyntaxText, TextRange, TextUnit, TokenAtOffset, WalkEvent};
 
 /// `Parse` is the result of the parsing: a syntax tree and a collection of
@@ -146,7 +146,11 @@ pub use crate::ast::SourceFile;
 
 impl SourceFile {
     pub fn parse(text: &str) -> Parse<SourceFile> {
-        let (green, mut errors) = parsing::parse_text(text);
+        SourceFile::parse_with_features(text, ParserFeatures::default())
+    }
+
+    pub fn parse_with_features(text: &str, features: ParserFeatures) -> Parse<SourceFile> {
+        let (green, mut errors) = parsing::parse_text(text, features);
         let root = SyntaxNode::new_root(green.clone());
 
         if cfg!(debug_assertions) {
@@ -6,16 +6,18 @@ mod text_token_source;
 mod text_tree_sink;
 mod reparsing;
 
+use ra_parser::ParserFeatures;
+
 use crate::{syntax_node::GreenNode, SyntaxError};
 
 pub use self::lexer::{classify_literal, tokenize, Token};
 
 pub(crate) use self::reparsing::incremental_reparse;
 
-pub(crate) fn parse_text(text: &str) -> (GreenNode, Vec<SyntaxError>) {
+pub(crate) fn parse_text(text: &str, features: ParserFeatures) -> (GreenNode, Vec<SyntaxError>) {
     let tokens = tokenize(&text);
     let mut token_source = text_token_source::TextTokenSource::new(text, &tokens);
     let mut tree_sink = text_tree_sink::TextTreeSink::new(text, &tokens);
-    ra_parser::parse(&mut token_source, &mut tree_sink);
+    ra_parser::parse_with_features(&mut token_source, &mut tree_sink, features);
     tree_sink.finish()
 }
@@ -5,7 +5,7 @@ use std::{
 
 use test_utils::{collect_tests, dir_tests, project_dir, read_text};
 
-use crate::{fuzz, SourceFile};
+use crate::{algo, fuzz, ParserFeatures, SourceFile};
 
 #[test]
 fn lexer_tests() {
@@ -27,10 +27,32 @@ fn main() {
     assert!(parse.ok().is_ok());
 }
 
+#[test]
+fn parse_trees_equal_ignores_trivia() {
+    let compact = SourceFile::parse("fn main(){let x=1+2;}");
+    let spread = SourceFile::parse(
+        "
+// The entry point.
+fn main() {
+    let x = 1 /* one */ + 2;
+}
+",
+    );
+    assert!(algo::parse_trees_equal(&compact, &spread));
+
+    let renamed = SourceFile::parse("fn main() { let y = 1 + 2; }");
+    assert!(!algo::parse_trees_equal(&compact, &renamed));
+    let regrouped = SourceFile::parse("fn main() { let x = (1 + 2); }");
+    assert!(!algo::parse_trees_equal(&compact, &regrouped));
+}
+
 #[test]
 fn parser_tests() {
+    // Unstable syntax is tested with its feature enabled in `ok`, and without
+    // it in `err`.
+    let features = ParserFeatures { inline_const: true };
     dir_tests(&test_data_dir(), &["parser/inline/ok", "parser/ok"], |text, path| {
-        let parse = SourceFile::parse(text);
+        let parse = SourceFile::parse_with_features(text, features);
         let errors = parse.errors();
         assert_eq!(
             errors,
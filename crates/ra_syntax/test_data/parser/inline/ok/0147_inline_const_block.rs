@@ -0,0 +1 @@
+fn foo() { let _ = const { 92 }; }
@@ -35,3 +35,39 @@ impl AtomTextEdit {
         text
     }
 }
+
+/// Where `offset` ends up after `edit` is applied. Offsets inside the deleted
+/// range are clamped to its start, offsets at an insertion point stay before
+/// the inserted text. Unlike `TextEdit::apply_to_offset`, this never fails.
+pub fn apply_edit_to_offset(offset: TextUnit, edit: &AtomTextEdit) -> TextUnit {
+    if offset <= edit.delete.start() {
+        offset
+    } else if offset < edit.delete.end() {
+        edit.delete.start()
+    } else {
+        offset + TextUnit::of_str(&edit.insert) - edit.delete.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use text_unit::{TextRange, TextUnit};
+
+    use super::{apply_edit_to_offset, AtomTextEdit};
+
+    #[test]
+    fn apply_edit_to_offset_around_edit() {
+        let edit = AtomTextEdit::replace(TextRange::from_to(4.into(), 8.into()), "ab".to_string());
+        let apply = |offset: u32| apply_edit_to_offset(TextUnit::from(offset), &edit);
+        assert_eq!(apply(0), 0.into());
+        assert_eq!(apply(4), 4.into());
+        assert_eq!(apply(5), 4.into());
+        assert_eq!(apply(7), 4.into());
+        assert_eq!(apply(8), 6.into());
+        assert_eq!(apply(20), 18.into());
+
+        let insert = AtomTextEdit::insert(4.into(), "abc".to_string());
+        assert_eq!(apply_edit_to_offset(4.into(), &insert), 4.into());
+        assert_eq!(apply_edit_to_offset(5.into(), &insert), 8.into());
+    }
+}